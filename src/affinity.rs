@@ -0,0 +1,228 @@
+
+use std;
+use std::fmt;
+use std::ptr;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+
+extern crate rayon;
+use self::rayon::prelude::*;
+
+use hwloc::{Topology, ObjectType, CpuSet, CPUBIND_THREAD};
+
+use aligned::AlignedBuffer;
+
+/// How worker threads are bound to the cores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Threads are not bound
+    None,
+    /// Thread i is bound to core i, threads fill one NUMA node after the other
+    Compact,
+    /// Threads are distributed round robin over the NUMA nodes
+    Scatter,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "none" => Ok(Policy::None),
+            "compact" => Ok(Policy::Compact),
+            "scatter" => Ok(Policy::Scatter),
+            _ => Err(format!("unknown binding policy '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Policy::None => write!(f, "none"),
+            Policy::Compact => write!(f, "compact"),
+            Policy::Scatter => write!(f, "scatter"),
+        }
+    }
+}
+
+lazy_static! {
+    // binding needs a mutable topology, so the shared TOPOLOGY can not be used
+    static ref BIND_TOPOLOGY: Mutex<Topology> = Mutex::new(Topology::new());
+    static ref POLICY: RwLock<Policy> = RwLock::new(Policy::None);
+}
+
+/// The policy used for threads which are started by this crate
+pub fn policy() -> Policy {
+    *POLICY.read().unwrap()
+}
+
+/// Set the policy used for threads which are started by this crate
+pub fn set_policy(policy: Policy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+/// The order in which the cores are handed out to the threads, as indices into the core list
+///
+/// nodes contains the NUMA node of every core.
+fn core_order(policy: Policy, nodes: &[usize]) -> Vec<usize> {
+    match policy {
+        Policy::None | Policy::Compact => (0..nodes.len()).collect(),
+        Policy::Scatter => {
+            let node_count = nodes.iter().max().map_or(0, |max| max + 1);
+            let mut per_node = vec![vec![]; node_count];
+            for (core, &node) in nodes.iter().enumerate() {
+                per_node[node].push(core);
+            }
+
+            // take the first core of every node, then the second and so on
+            let per_node = &per_node;
+            let rounds = per_node.iter().map(|cores| cores.len()).max().unwrap_or(0);
+            (0..rounds)
+                .flat_map(move |round| per_node.iter().filter_map(move |cores| cores.get(round).cloned()))
+                .collect()
+        }
+    }
+}
+
+/// The cpuset thread index is bound to, the cores are reused if there are more threads than cores
+fn cpuset_for(topology: &Topology, policy: Policy, index: usize) -> Option<CpuSet> {
+    let cores = match topology.objects_with_type(&ObjectType::Core) {
+        Ok(ref cores) if !cores.is_empty() => cores.iter().filter_map(|core| core.cpuset()).collect::<Vec<_>>(),
+        _ => return None,
+    };
+
+    let numa_nodes = topology.objects_with_type(&ObjectType::NUMANode)
+        .map(|nodes| nodes.iter().filter_map(|node| node.cpuset()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let nodes = cores.iter().map(|core| {
+        let first = core.first();
+        numa_nodes.iter().position(|node| first >= 0 && node.is_set(first as u32)).unwrap_or(0)
+    }).collect::<Vec<_>>();
+
+    let order = core_order(policy, &nodes);
+    let mut cpuset = cores[order[index % order.len()]].clone();
+
+    // stay on one hardware thread of the core
+    cpuset.singlify();
+    Some(cpuset)
+}
+
+/// Bind the calling thread to the core of thread index under the given policy
+pub fn bind_current_thread(policy: Policy, index: usize) -> Result<(), String> {
+    if policy == Policy::None {
+        return Ok(());
+    }
+
+    let mut topology = BIND_TOPOLOGY.lock().unwrap();
+
+    let cpuset = match cpuset_for(&topology, policy, index) {
+        Some(cpuset) => cpuset,
+        None => return Err("the topology does not contain any cores".to_string()),
+    };
+
+    topology.set_cpubind(cpuset, CPUBIND_THREAD)
+        .map_err(|e| format!("could not bind thread {}: {:?}", index, e))
+}
+
+/// Arrays shorter than this are initialized by the calling thread
+const FIRST_TOUCH_MIN_LEN: usize = 1 << 16;
+
+/// A raw pointer into the buffer which is initialized by first_touch
+///
+/// Every worker writes a disjoint part of the buffer, so it may be shared.
+#[derive(Clone, Copy)]
+struct SharedPtr<T>(*mut T);
+
+unsafe impl<T> Send for SharedPtr<T> {}
+unsafe impl<T> Sync for SharedPtr<T> {}
+
+/// Allocate an aligned buffer and initialize element i with f(i)
+///
+/// The buffer is split into one contiguous part per rayon worker and the parts are written in
+/// parallel, so the pages are usually placed on the NUMA node of the thread which works on them
+/// later. The placement is best effort: rayon does not guarantee which worker runs which part,
+/// so a part can be touched by a thread on another node, most likely when the pool is busy.
+pub fn first_touch<T, F>(len: usize, f: F) -> AlignedBuffer<T>
+    where T: Copy + Send + Sync, F: Fn(usize) -> T + Sync
+{
+    let mut data = unsafe { AlignedBuffer::uninitialized(len) };
+    let target = SharedPtr(data.as_mut_ptr());
+
+    // the memory is uninitialized, so it is only written through the raw pointer and never
+    // read or dropped
+    if len < FIRST_TOUCH_MIN_LEN {
+        for i in 0..len {
+            unsafe { ptr::write(target.0.offset(i as isize), f(i)) };
+        }
+        return data;
+    }
+
+    let threads = rayon::current_num_threads();
+    let part = (len + threads - 1) / threads;
+
+    (0..threads).into_par_iter().for_each(|chunk| {
+        let start = std::cmp::min(chunk * part, len);
+        let end = std::cmp::min(start + part, len);
+        for i in start..end {
+            unsafe { ptr::write(target.0.offset(i as isize), f(i)) };
+        }
+    });
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        for &policy in &[Policy::None, Policy::Compact, Policy::Scatter] {
+            assert_eq!(format!("{}", policy).parse::<Policy>(), Ok(policy));
+        }
+        assert!("spread".parse::<Policy>().is_err());
+    }
+
+    #[test]
+    fn test_core_order() {
+        // two nodes with three cores each
+        let nodes = [0, 0, 0, 1, 1, 1];
+
+        assert_eq!(core_order(Policy::Compact, &nodes), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(core_order(Policy::Scatter, &nodes), vec![0, 3, 1, 4, 2, 5]);
+
+        // unbalanced nodes
+        assert_eq!(core_order(Policy::Scatter, &[0, 1, 1, 1]), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_bind() {
+        assert_eq!(bind_current_thread(Policy::None, 0), Ok(()));
+    }
+
+    // hwloc can not bind threads on every platform and containers may deny it, run with --ignored
+    // where binding is allowed
+    #[test]
+    #[ignore]
+    fn test_bind_scatter() {
+        let result = std::thread::spawn(|| bind_current_thread(Policy::Scatter, 1)).join().unwrap();
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_first_touch() {
+        let small = first_touch(10, |i| i);
+        assert_eq!(small, (0..10).collect::<Vec<_>>());
+
+        let len = FIRST_TOUCH_MIN_LEN * 3 + 7;
+        let large = first_touch(len, |i| i as f64);
+        assert!(large.iter().enumerate().all(|(i, &x)| x == i as f64));
+
+        // both paths allocate with the configured alignment
+        let bytes = ::aligned::alignment().bytes;
+        assert_eq!(small.as_ptr() as usize % bytes, 0);
+        assert_eq!(large.as_ptr() as usize % bytes, 0);
+    }
+}
@@ -0,0 +1,348 @@
+extern crate libc;
+
+use std;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// The size of a transparent huge page on x86_64
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// The alignment of newly allocated buffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alignment {
+    /// The alignment in bytes, a power of two
+    pub bytes: usize,
+    /// Buffers of at least HUGE_PAGE_SIZE bytes are aligned to huge pages and backed by
+    /// transparent huge pages
+    pub huge_pages: bool,
+}
+
+impl Alignment {
+    pub fn new(bytes: usize, huge_pages: bool) -> Self {
+        assert!(bytes.is_power_of_two(), "the alignment must be a power of two");
+
+        Self {
+            bytes: bytes,
+            huge_pages: huge_pages,
+        }
+    }
+
+    /// The alignment of a buffer of size bytes
+    ///
+    /// posix_memalign needs at least the alignment of a pointer.
+    fn of_size(&self, size: usize) -> usize {
+        let bytes = std::cmp::max(self.bytes, std::mem::size_of::<usize>());
+
+        if self.huge_pages && size >= HUGE_PAGE_SIZE {
+            std::cmp::max(bytes, HUGE_PAGE_SIZE)
+        }
+        else {
+            bytes
+        }
+    }
+}
+
+impl Default for Alignment {
+    /// Cache lines, which is also the width of AVX-512 registers
+    fn default() -> Self {
+        Self::new(64, false)
+    }
+}
+
+impl FromStr for Alignment {
+    type Err = String;
+
+    /// Either the alignment in bytes or "huge" for cache lines and huge pages
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s == "huge" {
+            return Ok(Self::new(Self::default().bytes, true));
+        }
+
+        match s.parse::<usize>() {
+            Ok(bytes) if bytes.is_power_of_two() => Ok(Self::new(bytes, false)),
+            _ => Err(format!("invalid alignment '{}', expected a power of two or huge", s)),
+        }
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.huge_pages {
+            write!(f, "huge")
+        }
+        else {
+            write!(f, "{}", self.bytes)
+        }
+    }
+}
+
+lazy_static! {
+    static ref ALIGNMENT: RwLock<Alignment> = RwLock::new(Alignment::default());
+}
+
+/// The alignment used for buffers allocated by this crate
+pub fn alignment() -> Alignment {
+    *ALIGNMENT.read().unwrap()
+}
+
+/// Set the alignment used for buffers allocated by this crate
+pub fn set_alignment(alignment: Alignment) {
+    *ALIGNMENT.write().unwrap() = alignment;
+}
+
+/// Ask the kernel to back the memory with transparent huge pages, this is only a hint
+#[cfg(target_os = "linux")]
+unsafe fn advise_huge_pages(ptr: *mut libc::c_void, size: usize) {
+    libc::madvise(ptr, size, libc::MADV_HUGEPAGE);
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn advise_huge_pages(_ptr: *mut libc::c_void, _size: usize) {}
+
+/// A fixed size heap buffer with a guaranteed alignment
+///
+/// The memory is allocated with posix_memalign and released with free, so unlike a Vec of a
+/// wider type which is cast to a Vec<T>, the allocation is always freed with the layout it was
+/// created with. The buffer dereferences to a slice.
+pub struct AlignedBuffer<T> {
+    ptr: *mut T,
+    len: usize,
+    alignment: usize,
+}
+
+unsafe impl<T: Send> Send for AlignedBuffer<T> {}
+unsafe impl<T: Sync> Sync for AlignedBuffer<T> {}
+
+impl<T> AlignedBuffer<T> {
+    /// Allocate len uninitialized elements aligned to align bytes
+    unsafe fn allocate(len: usize, align: usize) -> Self {
+        let size = len.checked_mul(std::mem::size_of::<T>()).expect("the buffer size overflows");
+        let align = std::cmp::max(align, std::mem::align_of::<T>());
+
+        if size == 0 {
+            // a dangling but aligned pointer, as empty slices must not be null
+            return Self { ptr: align as *mut T, len: len, alignment: align };
+        }
+
+        let mut ptr = ptr::null_mut();
+        if libc::posix_memalign(&mut ptr, align, size) != 0 {
+            panic!("could not allocate {} bytes aligned to {} bytes", size, align);
+        }
+
+        if align >= HUGE_PAGE_SIZE {
+            advise_huge_pages(ptr, size);
+        }
+
+        Self {
+            ptr: ptr as *mut T,
+            len: len,
+            alignment: align,
+        }
+    }
+
+    /// The alignment of the buffer in bytes
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// A pointer to the first element which does not go through a slice
+    ///
+    /// Uninitialized elements must only be written through this pointer, a slice of them would
+    /// already claim that they are valid values.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr
+    }
+}
+
+impl<T: Copy> AlignedBuffer<T> {
+    /// Allocate len uninitialized elements with the alignment set by set_alignment
+    ///
+    /// Every element has to be written before it is read.
+    pub unsafe fn uninitialized(len: usize) -> Self {
+        Self::uninitialized_with(len, alignment())
+    }
+
+    /// Allocate len uninitialized elements with the given alignment
+    pub unsafe fn uninitialized_with(len: usize, alignment: Alignment) -> Self {
+        Self::allocate(len, alignment.of_size(len * std::mem::size_of::<T>()))
+    }
+
+    /// Allocate len elements with the value value
+    pub fn from_elem(value: T, len: usize) -> Self {
+        let mut ret = unsafe { Self::uninitialized(len) };
+        let target = ret.as_mut_ptr();
+        for i in 0..len {
+            unsafe { ptr::write(target.offset(i as isize), value) };
+        }
+        ret
+    }
+
+    /// Copy values into a new buffer
+    pub fn from_slice(values: &[T]) -> Self {
+        let mut ret = unsafe { Self::uninitialized(values.len()) };
+        ret.copy_from_slice(values);
+        ret
+    }
+
+    /// Reinterpret the elements as elements of type U without copying
+    ///
+    /// The caller has to make sure that every bit pattern of the data is a valid U. The size of
+    /// the buffer has to be a multiple of the size of U.
+    pub unsafe fn cast<U: Copy>(self) -> AlignedBuffer<U> {
+        let size = self.len * std::mem::size_of::<T>();
+        assert!(std::mem::size_of::<U>() > 0 && size % std::mem::size_of::<U>() == 0, "the buffer can not be split into elements of the new type");
+        assert_eq!(self.alignment % std::mem::align_of::<U>(), 0, "the buffer is not aligned for the new type");
+
+        let ret = AlignedBuffer {
+            ptr: self.ptr as *mut U,
+            len: size / std::mem::size_of::<U>(),
+            alignment: self.alignment,
+        };
+        std::mem::forget(self);
+        ret
+    }
+}
+
+impl<T> Drop for AlignedBuffer<T> {
+    fn drop(&mut self) {
+        if self.len * std::mem::size_of::<T>() > 0 {
+            unsafe { libc::free(self.ptr as *mut libc::c_void) };
+        }
+    }
+}
+
+impl<T> Deref for AlignedBuffer<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for AlignedBuffer<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T: Clone> Clone for AlignedBuffer<T> {
+    /// The clone has the alignment of the original buffer
+    fn clone(&self) -> Self {
+        let mut ret = unsafe { Self::allocate(self.len, self.alignment) };
+        for (target, value) in ret.iter_mut().zip(self.iter()) {
+            unsafe { ptr::write(target, value.clone()) };
+        }
+        ret
+    }
+}
+
+impl<T: Copy> From<Vec<T>> for AlignedBuffer<T> {
+    fn from(values: Vec<T>) -> Self {
+        Self::from_slice(&values)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AlignedBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for AlignedBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: PartialEq> PartialEq<Vec<T>> for AlignedBuffer<T> {
+    fn eq(&self, other: &Vec<T>) -> bool {
+        **self == other[..]
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AlignedBuffer<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut AlignedBuffer<T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address<T>(buffer: &AlignedBuffer<T>) -> usize {
+        buffer.as_ptr() as usize
+    }
+
+    #[test]
+    fn test_alignment() {
+        for &bytes in &[8, 32, 64, 4096] {
+            let alignment = Alignment::new(bytes, false);
+
+            for &len in &[0, 1, 3, 1000] {
+                let buffer = unsafe { AlignedBuffer::<f64>::uninitialized_with(len, alignment) };
+                assert_eq!(buffer.len(), len);
+                assert_eq!(address(&buffer) % bytes, 0, "{} elements aligned to {}", len, bytes);
+                assert_eq!(address(&buffer.clone()) % bytes, 0);
+            }
+        }
+
+        // only large buffers are placed on huge pages
+        let huge = Alignment::new(64, true);
+        let small = unsafe { AlignedBuffer::<u8>::uninitialized_with(100, huge) };
+        assert_eq!(small.alignment(), 64);
+        let large = unsafe { AlignedBuffer::<u8>::uninitialized_with(HUGE_PAGE_SIZE, huge) };
+        assert_eq!(large.alignment(), HUGE_PAGE_SIZE);
+        assert_eq!(address(&large) % HUGE_PAGE_SIZE, 0);
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut buffer = AlignedBuffer::from_elem(1., 5);
+        buffer[2] = 3.;
+        assert_eq!(buffer, vec![1., 1., 3., 1., 1.]);
+        assert_eq!(buffer.clone(), buffer);
+        assert_eq!(AlignedBuffer::from(vec![1, 2, 3]), vec![1, 2, 3]);
+
+        for v in &mut buffer {
+            *v += 1.;
+        }
+        assert_eq!((&buffer).into_iter().sum::<f64>(), 13.);
+
+        let ints = AlignedBuffer::from_slice(&[1u32, 2, 3, 4]);
+        let ptr = ints.as_ptr() as usize;
+        let longs = unsafe { ints.cast::<u64>() };
+        assert_eq!(longs.len(), 2);
+        assert_eq!(longs.as_ptr() as usize, ptr);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("32".parse::<Alignment>(), Ok(Alignment::new(32, false)));
+        assert_eq!("huge".parse::<Alignment>(), Ok(Alignment::new(64, true)));
+        assert!("48".parse::<Alignment>().is_err());
+        assert!("cache".parse::<Alignment>().is_err());
+
+        for alignment in &[Alignment::default(), Alignment::new(32, false), Alignment::new(64, true)] {
+            assert_eq!(format!("{}", alignment).parse::<Alignment>().as_ref(), Ok(alignment));
+        }
+    }
+}
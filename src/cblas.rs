@@ -0,0 +1,336 @@
+
+//! The CBLAS interface, exported by the cdylib target
+//!
+//! The routines follow the signatures of cblas.h, so C programs can link against this crate
+//! instead of a BLAS library. gemm is computed by the packed kernel, the level 1 routines use
+//! the STREAM kernels from triades for contiguous vectors.
+
+use std;
+use std::os::raw::{c_int, c_double};
+use std::panic;
+use std::slice;
+
+use triades;
+use matmul::gemm::gemm_strided;
+
+/// Values of enum CBLAS_ORDER
+pub const CBLAS_ROW_MAJOR: c_int = 101;
+pub const CBLAS_COL_MAJOR: c_int = 102;
+
+/// Values of enum CBLAS_TRANSPOSE, ConjTrans is the same as Trans for real matrices
+pub const CBLAS_NO_TRANS: c_int = 111;
+pub const CBLAS_TRANS: c_int = 112;
+pub const CBLAS_CONJ_TRANS: c_int = 113;
+
+/// Run the body of an exported routine and abort the process if it panics
+///
+/// Unwinding into the C caller is undefined behaviour, so a panic, e.g. a failed assertion in a
+/// kernel, must not leave the routine.
+fn abort_on_panic<R, F: FnOnce() -> R>(body: F) -> R {
+    panic::catch_unwind(panic::AssertUnwindSafe(body)).unwrap_or_else(|_| std::process::abort())
+}
+
+/// Report an illegal argument like cblas_xerbla, pos is the 1-based position of the argument
+fn xerbla(routine: &str, pos: usize, message: &str) {
+    eprintln!("Parameter {} to routine {} was incorrect: {}", pos, routine, message);
+}
+
+/// Whether an operand is transposed, None for invalid values
+fn transposed(trans: c_int) -> Option<bool> {
+    match trans {
+        CBLAS_NO_TRANS => Some(false),
+        CBLAS_TRANS | CBLAS_CONJ_TRANS => Some(true),
+        _ => None,
+    }
+}
+
+/// The row and column stride of a stored matrix with leading dimension ld
+fn storage_strides(row_major: bool, ld: usize) -> (usize, usize) {
+    if row_major { (ld, 1) } else { (1, ld) }
+}
+
+/// Compute C = alpha * op(A) * op(B) + beta * C
+///
+/// op(A) is a (m x k), op(B) a (k x n) and C a (m x n) matrix, all stored in the given order
+/// with leading dimensions lda, ldb and ldc. Illegal arguments are reported and C is left
+/// untouched.
+#[no_mangle]
+pub unsafe extern "C" fn cblas_dgemm(order: c_int, trans_a: c_int, trans_b: c_int,
+                                     m: c_int, n: c_int, k: c_int,
+                                     alpha: c_double, a: *const c_double, lda: c_int,
+                                     b: *const c_double, ldb: c_int,
+                                     beta: c_double, c: *mut c_double, ldc: c_int) {
+    abort_on_panic(|| {
+        let row_major = match order {
+            CBLAS_ROW_MAJOR => true,
+            CBLAS_COL_MAJOR => false,
+            _ => return xerbla("cblas_dgemm", 1, "illegal order"),
+        };
+        let trans_a = match transposed(trans_a) {
+            Some(trans) => trans,
+            None => return xerbla("cblas_dgemm", 2, "illegal TransA"),
+        };
+        let trans_b = match transposed(trans_b) {
+            Some(trans) => trans,
+            None => return xerbla("cblas_dgemm", 3, "illegal TransB"),
+        };
+
+        if m < 0 {
+            return xerbla("cblas_dgemm", 4, "M < 0");
+        }
+        if n < 0 {
+            return xerbla("cblas_dgemm", 5, "N < 0");
+        }
+        if k < 0 {
+            return xerbla("cblas_dgemm", 6, "K < 0");
+        }
+
+        let (m, n, k) = (m as usize, n as usize, k as usize);
+
+        // the shapes of the stored matrices
+        let (a_rows, a_columns) = if trans_a { (k, m) } else { (m, k) };
+        let (b_rows, b_columns) = if trans_b { (n, k) } else { (k, n) };
+
+        // the leading dimension is the row length in row major and the column length in column major
+        let min_ld = |rows: usize, columns: usize| std::cmp::max(1, if row_major { columns } else { rows }) as c_int;
+
+        if lda < min_ld(a_rows, a_columns) {
+            return xerbla("cblas_dgemm", 9, "lda is too small");
+        }
+        if ldb < min_ld(b_rows, b_columns) {
+            return xerbla("cblas_dgemm", 11, "ldb is too small");
+        }
+        if ldc < min_ld(m, n) {
+            return xerbla("cblas_dgemm", 14, "ldc is too small");
+        }
+
+        if m == 0 || n == 0 {
+            return;
+        }
+
+        // transposing an operand swaps its strides
+        let (rsa, csa) = storage_strides(row_major, lda as usize);
+        let (rsa, csa) = if trans_a { (csa, rsa) } else { (rsa, csa) };
+        let (rsb, csb) = storage_strides(row_major, ldb as usize);
+        let (rsb, csb) = if trans_b { (csb, rsb) } else { (rsb, csb) };
+        let (rsc, csc) = storage_strides(row_major, ldc as usize);
+
+        gemm_strided(m, n, k, alpha, a, rsa, csa, b, rsb, csb, beta, c, rsc, csc);
+    })
+}
+
+/// The offset of element i of a vector of length n, negative increments run from the end
+fn offset(i: usize, n: usize, inc: c_int) -> isize {
+    if inc >= 0 {
+        (i * inc as usize) as isize
+    }
+    else {
+        ((n - 1 - i) * (-inc) as usize) as isize
+    }
+}
+
+/// Copy the vector x to y
+#[no_mangle]
+pub unsafe extern "C" fn cblas_dcopy(n: c_int, x: *const c_double, inc_x: c_int, y: *mut c_double, inc_y: c_int) {
+    abort_on_panic(|| {
+        if n <= 0 {
+            return;
+        }
+        let n = n as usize;
+
+        if inc_x == 1 && inc_y == 1 {
+            triades::copy(slice::from_raw_parts(x, n), slice::from_raw_parts_mut(y, n));
+            return;
+        }
+
+        for i in 0..n {
+            *y.offset(offset(i, n, inc_y)) = *x.offset(offset(i, n, inc_x));
+        }
+    })
+}
+
+/// Compute y = alpha * x + y
+#[no_mangle]
+pub unsafe extern "C" fn cblas_daxpy(n: c_int, alpha: c_double, x: *const c_double, inc_x: c_int, y: *mut c_double, inc_y: c_int) {
+    abort_on_panic(|| {
+        if n <= 0 || alpha == 0. {
+            return;
+        }
+        let n = n as usize;
+
+        if inc_x == 1 && inc_y == 1 {
+            triades::axpy(slice::from_raw_parts_mut(y, n), slice::from_raw_parts(x, n), alpha);
+            return;
+        }
+
+        for i in 0..n {
+            let y = y.offset(offset(i, n, inc_y));
+            *y = alpha * *x.offset(offset(i, n, inc_x)) + *y;
+        }
+    })
+}
+
+/// The dot product of x and y
+#[no_mangle]
+pub unsafe extern "C" fn cblas_ddot(n: c_int, x: *const c_double, inc_x: c_int, y: *const c_double, inc_y: c_int) -> c_double {
+    abort_on_panic(|| {
+        if n <= 0 {
+            return 0.;
+        }
+        let n = n as usize;
+
+        (0..n).map(|i| *x.offset(offset(i, n, inc_x)) * *y.offset(offset(i, n, inc_y))).sum()
+    })
+}
+
+/// Compute x = alpha * x, non-positive increments leave x untouched like the reference BLAS
+#[no_mangle]
+pub unsafe extern "C" fn cblas_dscal(n: c_int, alpha: c_double, x: *mut c_double, inc_x: c_int) {
+    abort_on_panic(|| {
+        if n <= 0 || inc_x <= 0 {
+            return;
+        }
+
+        for i in 0..n as usize {
+            *x.offset((i * inc_x as usize) as isize) *= alpha;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use matmul::{naive, Matrix};
+
+    /// Store a matrix with leading dimension ld, the padding is filled with NaN
+    fn store(matrix: &Matrix, row_major: bool, ld: usize) -> Vec<f64> {
+        let len = if row_major { matrix.rows * ld } else { matrix.columns * ld };
+        let mut data = vec![std::f64::NAN; len];
+
+        for i in 0..matrix.rows {
+            for j in 0..matrix.columns {
+                let index = if row_major { i * ld + j } else { j * ld + i };
+                data[index] = matrix[(i, j)];
+            }
+        }
+        data
+    }
+
+    fn load(data: &[f64], rows: usize, columns: usize, row_major: bool, ld: usize) -> Matrix {
+        let mut matrix = Matrix::zero(rows, columns);
+
+        for i in 0..rows {
+            for j in 0..columns {
+                matrix[(i, j)] = if row_major { data[i * ld + j] } else { data[j * ld + i] };
+            }
+        }
+        matrix
+    }
+
+    fn transpose(matrix: &Matrix) -> Matrix {
+        let mut ret = Matrix::zero(matrix.columns, matrix.rows);
+        for i in 0..matrix.rows {
+            for j in 0..matrix.columns {
+                ret[(j, i)] = matrix[(i, j)];
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_dgemm() {
+        let (m, n, k) = (7, 5, 9);
+        let (alpha, beta) = (2., 0.5);
+
+        for &order in &[CBLAS_ROW_MAJOR, CBLAS_COL_MAJOR] {
+            for &trans_a in &[CBLAS_NO_TRANS, CBLAS_TRANS] {
+                for &trans_b in &[CBLAS_NO_TRANS, CBLAS_CONJ_TRANS] {
+                    let row_major = order == CBLAS_ROW_MAJOR;
+
+                    let a = Matrix::<f64>::random(m, k);
+                    let b = Matrix::<f64>::random(k, n);
+                    let c = Matrix::<f64>::random(m, n);
+
+                    let a_stored = if trans_a == CBLAS_TRANS { transpose(&a) } else { a.clone() };
+                    let b_stored = if trans_b == CBLAS_CONJ_TRANS { transpose(&b) } else { b.clone() };
+
+                    // padded leading dimensions
+                    let ld = |matrix: &Matrix| if row_major { matrix.columns + 3 } else { matrix.rows + 2 };
+                    let (lda, ldb, ldc) = (ld(&a_stored), ld(&b_stored), ld(&c));
+
+                    let a_data = store(&a_stored, row_major, lda);
+                    let b_data = store(&b_stored, row_major, ldb);
+                    let mut c_data = store(&c, row_major, ldc);
+
+                    unsafe {
+                        cblas_dgemm(order, trans_a, trans_b, m as c_int, n as c_int, k as c_int,
+                                    alpha, a_data.as_ptr(), lda as c_int, b_data.as_ptr(), ldb as c_int,
+                                    beta, c_data.as_mut_ptr(), ldc as c_int);
+                    }
+
+                    let mut compare = Matrix::<f64>::zero(m, n);
+                    naive::mult(&a, &b, &mut compare);
+                    compare.data.iter_mut().zip(c.data.iter()).for_each(|(ab, c)| *ab = alpha * *ab + beta * c);
+
+                    assert_eq!(load(&c_data, m, n, row_major, ldc), compare, "{} {} {}", order, trans_a, trans_b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dgemm_illegal() {
+        let a = vec![1.; 16];
+        let mut c = vec![3.; 16];
+
+        unsafe {
+            cblas_dgemm(100, CBLAS_NO_TRANS, CBLAS_NO_TRANS, 4, 4, 4, 1., a.as_ptr(), 4, a.as_ptr(), 4, 0., c.as_mut_ptr(), 4);
+            cblas_dgemm(CBLAS_ROW_MAJOR, 110, CBLAS_NO_TRANS, 4, 4, 4, 1., a.as_ptr(), 4, a.as_ptr(), 4, 0., c.as_mut_ptr(), 4);
+            cblas_dgemm(CBLAS_ROW_MAJOR, CBLAS_NO_TRANS, CBLAS_NO_TRANS, -1, 4, 4, 1., a.as_ptr(), 4, a.as_ptr(), 4, 0., c.as_mut_ptr(), 4);
+            cblas_dgemm(CBLAS_ROW_MAJOR, CBLAS_NO_TRANS, CBLAS_NO_TRANS, 4, 4, 4, 1., a.as_ptr(), 3, a.as_ptr(), 4, 0., c.as_mut_ptr(), 4);
+        }
+        assert!(c.iter().all(|&x| x == 3.));
+
+        // a valid call
+        unsafe {
+            cblas_dgemm(CBLAS_COL_MAJOR, CBLAS_NO_TRANS, CBLAS_NO_TRANS, 4, 4, 4, 1., a.as_ptr(), 4, a.as_ptr(), 4, 0., c.as_mut_ptr(), 4);
+        }
+        assert!(c.iter().all(|&x| x == 4.));
+    }
+
+    #[test]
+    fn test_level1() {
+        let x = (0..8).map(|i| i as f64).collect::<Vec<_>>();
+
+        unsafe {
+            let mut y = vec![0.; 8];
+            cblas_dcopy(8, x.as_ptr(), 1, y.as_mut_ptr(), 1);
+            assert_eq!(y, x);
+
+            // every second element of x backwards into the first four elements of y
+            let mut y = vec![-1.; 8];
+            cblas_dcopy(4, x.as_ptr(), 2, y.as_mut_ptr(), -1);
+            assert_eq!(y, vec![6., 4., 2., 0., -1., -1., -1., -1.]);
+
+            let mut y = vec![1.; 8];
+            cblas_daxpy(8, 2., x.as_ptr(), 1, y.as_mut_ptr(), 1);
+            assert_eq!(y, x.iter().map(|x| 2. * x + 1.).collect::<Vec<_>>());
+
+            let mut y = vec![1.; 8];
+            cblas_daxpy(4, 2., x.as_ptr(), 2, y.as_mut_ptr(), 2);
+            assert_eq!(y, vec![1., 1., 5., 1., 9., 1., 13., 1.]);
+
+            assert_eq!(cblas_ddot(8, x.as_ptr(), 1, x.as_ptr(), 1), 140.);
+            assert_eq!(cblas_ddot(3, x.as_ptr(), 1, x.as_ptr(), -1), 1.);
+            assert_eq!(cblas_ddot(0, x.as_ptr(), 1, x.as_ptr(), 1), 0.);
+
+            let mut y = x.clone();
+            cblas_dscal(4, 3., y.as_mut_ptr(), 2);
+            assert_eq!(y, vec![0., 1., 6., 3., 12., 5., 18., 7.]);
+
+            cblas_dscal(4, 3., y.as_mut_ptr(), -1);
+            assert_eq!(y, vec![0., 1., 6., 3., 12., 5., 18., 7.]);
+        }
+    }
+}
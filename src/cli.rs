@@ -0,0 +1,316 @@
+
+use std;
+use std::fmt;
+use std::str::FromStr;
+
+use rust::affinity::Policy;
+use rust::aligned::Alignment;
+use rust::matmul::strassen::DEFAULT_CUTOFF;
+
+pub const USAGE: &'static str = "\
+Usage: rust [OPTIONS]
+
+Options:
+    -k, --kernel NAMES      Comma separated list of kernels or \"all\" (default: naive_rayon)
+    -s, --size SIZES        Comma separated list of sizes, either N or MxKxN (default: 4096)
+    -r, --reps N            Number of measured repetitions (default: 10)
+    -w, --warmup N          Number of warm-up runs which are not measured (default: 1)
+    -t, --threads N         Number of worker threads, 0 uses all cores (default: 0)
+    -f, --format FORMAT     Output format, text or csv (default: text)
+    -b, --bind POLICY       Bind the worker threads to cores, none, compact or scatter (default: none)
+        --align BYTES       Alignment of matrices and arrays in bytes, or huge for cache lines and
+                            transparent huge pages (default: 64)
+        --cutoff N          Quadrants with at most N rows are multiplied classically by the strassen and
+                            winograd kernels (default: 512)
+        --stream NAMES      Run the comma separated STREAM kernels or \"all\" instead of matmul
+        --level2 NAMES      Run the comma separated level 2 BLAS kernels or \"all\" on (M x N) matrices
+                            of the given sizes instead of matmul
+        --array-size N      Array length for the STREAM kernels (default: ARRAY_SIZE or 4000000)
+        --write-allocate    Count a write allocate read for every written array
+        --roofline          Measure bandwidth and peak and place the kernels on the roofline,
+                            --stream selects the kernels for the bandwidth (default: copy,vtriad,vtriad_rayon)
+        --csv PATH          Write the roofline as CSV to PATH
+        --svg PATH          Write the roofline plot as SVG to PATH
+        --profile PATH      Write a cpu profile of the measured runs of every kernel and size to PATH
+                            with the run inserted before the extension, e.g. prof.naive_64x64x64.out
+        --tune              Find the fastest kernel and tile size among the selected kernels for every
+                            size and store it in MATMUL_TUNING_FILE (default: matmul_tuning.tsv)
+    -l, --list              List all kernels and exit
+    -h, --help              Print this help and exit
+";
+
+/// The format of the benchmark results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+/// The size of a (m x k) * (k x n) multiplication
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub m: usize,
+    pub k: usize,
+    pub n: usize,
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let dims = s.split('x')
+            .map(|dim| dim.parse::<usize>().map_err(|_| format!("invalid size '{}'", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match dims.len() {
+            1 => Ok(Size { m: dims[0], k: dims[0], n: dims[0] }),
+            3 => Ok(Size { m: dims[0], k: dims[1], n: dims[2] }),
+            _ => Err(format!("invalid size '{}', expected N or MxKxN", s)),
+        }
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.m, self.k, self.n)
+    }
+}
+
+/// The configuration of a benchmark run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub kernels: Vec<String>,
+    pub sizes: Vec<Size>,
+    pub reps: usize,
+    pub warmup: usize,
+    pub threads: usize,
+    pub bind: Policy,
+    pub align: Alignment,
+    pub format: Format,
+    pub cutoff: usize,
+    pub streams: Vec<String>,
+    pub level2: Vec<String>,
+    pub array_size: usize,
+    pub write_allocate: bool,
+    pub roofline: bool,
+    pub csv: Option<String>,
+    pub svg: Option<String>,
+    pub profile: Option<String>,
+    pub tune: bool,
+    pub list: bool,
+    pub help: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            kernels: vec!["naive_rayon".to_string()],
+            sizes: vec![Size { m: 4096, k: 4096, n: 4096 }],
+            reps: 10,
+            warmup: 1,
+            threads: 0,
+            bind: Policy::None,
+            align: Alignment::default(),
+            format: Format::Text,
+            cutoff: DEFAULT_CUTOFF,
+            streams: vec![],
+            level2: vec![],
+            array_size: match std::env::var("ARRAY_SIZE") {
+                Ok(len) => len.parse::<usize>().expect("ARRAY_SIZE env variable must be the array length"),
+                Err(_)  => 4000000
+            },
+            write_allocate: false,
+            roofline: false,
+            csv: None,
+            svg: None,
+            profile: None,
+            tune: false,
+            list: false,
+            help: false,
+        }
+    }
+}
+
+fn parse_list<T: FromStr<Err=String>>(value: &str) -> Result<Vec<T>, String> {
+    value.split(',').map(|item| item.trim().parse::<T>()).collect()
+}
+
+fn parse_number(option: &str, value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|_| format!("{} expects a number, got '{}'", option, value))
+}
+
+impl Config {
+    /// Parse the configuration from the command line arguments, without the program name
+    pub fn parse<I: Iterator<Item=String>>(mut args: I) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        while let Some(arg) = args.next() {
+            // support --option=value as well as --option value
+            let (option, inline) = match arg.find('=') {
+                Some(pos) if arg.starts_with("--") => (arg[..pos].to_string(), Some(arg[pos + 1..].to_string())),
+                _ => (arg.clone(), None),
+            };
+
+            match option.as_str() {
+                "-l" | "--list" => { config.list = true; continue; }
+                "-h" | "--help" => { config.help = true; continue; }
+                "--write-allocate" => { config.write_allocate = true; continue; }
+                "--roofline" => { config.roofline = true; continue; }
+                "--tune" => { config.tune = true; continue; }
+                _ => {}
+            }
+
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("{} expects a value", option)),
+            };
+
+            match option.as_str() {
+                "-k" | "--kernel" => config.kernels = value.split(',').map(|name| name.trim().to_string()).collect(),
+                "-s" | "--size" => config.sizes = parse_list(&value)?,
+                "-r" | "--reps" => config.reps = parse_number(&option, &value)?,
+                "-w" | "--warmup" => config.warmup = parse_number(&option, &value)?,
+                "-t" | "--threads" => config.threads = parse_number(&option, &value)?,
+                "-f" | "--format" => config.format = value.parse()?,
+                "-b" | "--bind" => config.bind = value.parse()?,
+                "--align" => config.align = value.parse()?,
+                "--cutoff" => config.cutoff = parse_number(&option, &value)?,
+                "--stream" => config.streams = value.split(',').map(|name| name.trim().to_string()).collect(),
+                "--level2" => config.level2 = value.split(',').map(|name| name.trim().to_string()).collect(),
+                "--array-size" => config.array_size = parse_number(&option, &value)?,
+                "--csv" => config.csv = Some(value),
+                "--svg" => config.svg = Some(value),
+                "--profile" => config.profile = Some(value),
+                _ => return Err(format!("unknown option '{}'", option)),
+            }
+        }
+
+        if config.reps == 0 {
+            return Err("--reps must be at least 1".to_string());
+        }
+
+        Ok(config)
+    }
+
+    /// The file of the cpu profile of one measured run, or None if profiling is disabled
+    ///
+    /// The run name is inserted before the extension of --profile, so every kernel and size gets
+    /// its own profile instead of overwriting the previous one.
+    pub fn profile_path(&self, run: &str) -> Option<String> {
+        self.profile.as_ref().map(|path| {
+            let run = run.chars()
+                .map(|c| if c.is_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+                .collect::<String>();
+
+            let name = path.rfind('/').map_or(0, |pos| pos + 1);
+            match path[name..].rfind('.') {
+                Some(pos) if pos > 0 => format!("{}.{}{}", &path[..name + pos], run, &path[name + pos..]),
+                _ => format!("{}.{}", path, run),
+            }
+        })
+    }
+
+    /// Parse the configuration from the arguments of this process
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = parse(&["-k", "naive,asm", "--size=128,64x32x16", "-r", "3", "--warmup", "0", "-f", "csv"]).unwrap();
+
+        assert_eq!(config.kernels, vec!["naive", "asm"]);
+        assert_eq!(config.sizes, vec![Size { m: 128, k: 128, n: 128 }, Size { m: 64, k: 32, n: 16 }]);
+        assert_eq!(config.reps, 3);
+        assert_eq!(config.warmup, 0);
+        assert_eq!(config.format, Format::Csv);
+        assert!(config.streams.is_empty());
+
+        let config = parse(&["--stream", "copy,vtriad", "--array-size", "1024", "--write-allocate"]).unwrap();
+
+        assert_eq!(config.streams, vec!["copy", "vtriad"]);
+        assert_eq!(config.array_size, 1024);
+        assert!(config.write_allocate);
+
+        let config = parse(&["--roofline", "--csv", "roof.csv", "--svg=roof.svg"]).unwrap();
+
+        assert!(config.roofline);
+        assert_eq!(config.csv, Some("roof.csv".to_string()));
+        assert_eq!(config.svg, Some("roof.svg".to_string()));
+
+        let config = parse(&["--tune", "-k", "all"]).unwrap();
+
+        assert!(config.tune);
+        assert_eq!(config.kernels, vec!["all"]);
+
+        let config = parse(&["-t", "4", "--bind", "scatter"]).unwrap();
+
+        assert_eq!(config.threads, 4);
+        assert_eq!(config.bind, Policy::Scatter);
+        assert_eq!(config.align, Alignment::default());
+
+        let config = parse(&["--align", "huge", "--stream", "copy"]).unwrap();
+
+        assert_eq!(config.align, Alignment::new(64, true));
+        assert_eq!(parse(&["--align", "32"]).unwrap().align, Alignment::new(32, false));
+
+        let config = parse(&["-k", "strassen,winograd", "--cutoff", "256"]).unwrap();
+
+        assert_eq!(config.kernels, vec!["strassen", "winograd"]);
+        assert_eq!(config.cutoff, 256);
+
+        let config = parse(&["--level2", "gemv_rayon, ger", "-s", "1024x1x512"]).unwrap();
+
+        assert_eq!(config.level2, vec!["gemv_rayon", "ger"]);
+        assert_eq!(config.sizes, vec![Size { m: 1024, k: 1, n: 512 }]);
+    }
+
+    #[test]
+    fn test_profile_path() {
+        assert_eq!(Config::default().profile_path("naive 64x64x64"), None);
+
+        let config = parse(&["--profile", "out/prof.out"]).unwrap();
+        assert_eq!(config.profile_path("naive 64x64x64"), Some("out/prof.naive_64x64x64.out".to_string()));
+        assert_eq!(config.profile_path("copy 1024"), Some("out/prof.copy_1024.out".to_string()));
+
+        let config = parse(&["--profile", "./profiles/cpu"]).unwrap();
+        assert_eq!(config.profile_path("gemv 8x4"), Some("./profiles/cpu.gemv_8x4".to_string()));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["--size", "12x3"]).is_err());
+        assert!(parse(&["--reps"]).is_err());
+        assert!(parse(&["--reps", "0"]).is_err());
+        assert!(parse(&["--format", "xml"]).is_err());
+        assert!(parse(&["--bind", "spread"]).is_err());
+        assert!(parse(&["--align", "24"]).is_err());
+        assert!(parse(&["--unknown", "1"]).is_err());
+    }
+}
@@ -0,0 +1,514 @@
+
+//! Level 2 BLAS: matrix vector products and rank-1 updates
+//!
+//! Like the STREAM kernels these touch every element of the matrix once per call and are bound
+//! by the memory bandwidth, so they are benchmarked the same way, see Level2 and LEVEL2.
+
+extern crate rayon;
+extern crate simd;
+
+use std;
+use std::ops::{Index, IndexMut};
+
+use self::rayon::prelude::*;
+use self::simd::x86::avx::f64x4;
+
+use matmul::{Matrix, TransposedMatrix};
+use matmul::gemm::Operand;
+
+/// The number of elements in a f64x4
+const CHUNK: usize = 4;
+
+/// The dot product of a and b
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    assert_eq!(a.len(), b.len());
+
+    let len_simd = a.len() - a.len() % CHUNK;
+    let mut sum = f64x4::splat(0.);
+
+    for i in (0..len_simd).step_by(CHUNK) {
+        sum = sum + f64x4::load(a, i) * f64x4::load(b, i);
+    }
+
+    let mut ret = sum.extract(0) + sum.extract(1) + sum.extract(2) + sum.extract(3);
+    for i in len_simd..a.len() {
+        ret += a[i] * b[i];
+    }
+    ret
+}
+
+/// Compute y = s * x + y with f64x4
+fn axpy(y: &mut [f64], x: &[f64], s: f64) {
+    assert_eq!(x.len(), y.len());
+
+    let len_simd = x.len() - x.len() % CHUNK;
+    let s_simd = f64x4::splat(s);
+
+    for i in (0..len_simd).step_by(CHUNK) {
+        (s_simd * f64x4::load(x, i) + f64x4::load(y, i)).store(y, i);
+    }
+
+    for i in len_simd..x.len() {
+        y[i] += s * x[i];
+    }
+}
+
+/// Compute y = beta * y, with beta = 0 the previous content of y, even NaN, is ignored
+fn scale(y: &mut [f64], beta: f64) {
+    if beta == 0. {
+        y.iter_mut().for_each(|y| *y = 0.);
+    }
+    else if beta != 1. {
+        y.iter_mut().for_each(|y| *y *= beta);
+    }
+}
+
+/// The length of the parts of y which are computed by one rayon task, a multiple of CHUNK
+fn part_len(len: usize) -> usize {
+    let part = (len + rayon::current_num_threads() - 1) / rayon::current_num_threads();
+    std::cmp::max(CHUNK, (part + CHUNK - 1) / CHUNK * CHUNK)
+}
+
+/// Compute y = alpha * A * x + beta * y element by element
+///
+/// This works for every layout and is the reference for the other variants.
+#[allow(non_snake_case)]
+pub fn gemv<M>(alpha: f64, A: &M, x: &[f64], beta: f64, y: &mut [f64])
+    where M: Operand + Index<(usize, usize), Output=f64>
+{
+    let (rows, columns) = A.shape();
+    assert_eq!(x.len(), columns);
+    assert_eq!(y.len(), rows);
+
+    scale(y, beta);
+
+    for i in 0..rows {
+        let mut sum = 0.;
+        for j in 0..columns {
+            sum += A[(i, j)] * x[j];
+        }
+        y[i] += alpha * sum;
+    }
+}
+
+/// Compute y = alpha * A * x + beta * y with a simd dot product per row
+#[allow(non_snake_case)]
+pub fn gemv_simd(alpha: f64, A: &Matrix, x: &[f64], beta: f64, y: &mut [f64]) {
+    assert_eq!(x.len(), A.columns);
+    assert_eq!(y.len(), A.rows);
+
+    scale(y, beta);
+
+    if A.columns == 0 {
+        return;
+    }
+
+    for (row, y) in A.data.chunks(A.columns).zip(y.iter_mut()) {
+        *y += alpha * dot(row, x);
+    }
+}
+
+/// Compute y = alpha * A * x + beta * y, the rows are distributed over the rayon workers
+#[allow(non_snake_case)]
+pub fn gemv_rayon(alpha: f64, A: &Matrix, x: &[f64], beta: f64, y: &mut [f64]) {
+    assert_eq!(x.len(), A.columns);
+    assert_eq!(y.len(), A.rows);
+
+    if A.columns == 0 {
+        scale(y, beta);
+        return;
+    }
+
+    A.data.par_chunks(A.columns).zip(y.par_iter_mut()).for_each(|(row, y)| {
+        let ax = alpha * dot(row, x);
+        *y = if beta == 0. { ax } else { ax + beta * *y };
+    });
+}
+
+/// Compute y = alpha * A * x + beta * y on a column major matrix
+///
+/// y is updated with one axpy per column, so A is streamed in storage order.
+#[allow(non_snake_case)]
+pub fn gemv_transposed_simd(alpha: f64, A: &TransposedMatrix, x: &[f64], beta: f64, y: &mut [f64]) {
+    assert_eq!(x.len(), A.columns);
+    assert_eq!(y.len(), A.rows);
+
+    scale(y, beta);
+
+    if A.rows == 0 {
+        return;
+    }
+
+    for (column, &x) in A.data.chunks(A.rows).zip(x.iter()) {
+        axpy(y, column, alpha * x);
+    }
+}
+
+/// Compute y = alpha * A * x + beta * y on a column major matrix
+///
+/// y is split into one part per rayon worker, every worker applies all columns to its part.
+#[allow(non_snake_case)]
+pub fn gemv_transposed_rayon(alpha: f64, A: &TransposedMatrix, x: &[f64], beta: f64, y: &mut [f64]) {
+    assert_eq!(x.len(), A.columns);
+    assert_eq!(y.len(), A.rows);
+
+    if A.rows == 0 {
+        return;
+    }
+
+    let part = part_len(A.rows);
+
+    y.par_chunks_mut(part).enumerate().for_each(|(index, y)| {
+        let start = index * part;
+        scale(y, beta);
+
+        for (column, &x) in A.data.chunks(A.rows).zip(x.iter()) {
+            axpy(y, &column[start..start + y.len()], alpha * x);
+        }
+    });
+}
+
+/// Compute the rank-1 update A = alpha * x * y^T + A element by element
+///
+/// This works for every layout and is the reference for the other variants.
+#[allow(non_snake_case)]
+pub fn ger<M>(alpha: f64, x: &[f64], y: &[f64], A: &mut M)
+    where M: Operand + IndexMut<(usize, usize), Output=f64>
+{
+    let (rows, columns) = A.shape();
+    assert_eq!(x.len(), rows);
+    assert_eq!(y.len(), columns);
+
+    for i in 0..rows {
+        for j in 0..columns {
+            A[(i, j)] += alpha * x[i] * y[j];
+        }
+    }
+}
+
+/// Compute A = alpha * x * y^T + A with a simd axpy per row
+#[allow(non_snake_case)]
+pub fn ger_simd(alpha: f64, x: &[f64], y: &[f64], A: &mut Matrix) {
+    assert_eq!(x.len(), A.rows);
+    assert_eq!(y.len(), A.columns);
+
+    if A.columns == 0 {
+        return;
+    }
+
+    for (row, &x) in A.data.chunks_mut(A.columns).zip(x.iter()) {
+        axpy(row, y, alpha * x);
+    }
+}
+
+/// Compute A = alpha * x * y^T + A, the rows are distributed over the rayon workers
+#[allow(non_snake_case)]
+pub fn ger_rayon(alpha: f64, x: &[f64], y: &[f64], A: &mut Matrix) {
+    assert_eq!(x.len(), A.rows);
+    assert_eq!(y.len(), A.columns);
+
+    if A.columns == 0 {
+        return;
+    }
+
+    A.data.par_chunks_mut(A.columns).zip(x.par_iter()).for_each(|(row, &x)| axpy(row, y, alpha * x));
+}
+
+/// Compute A = alpha * x * y^T + A on a column major matrix with a simd axpy per column
+#[allow(non_snake_case)]
+pub fn ger_transposed_simd(alpha: f64, x: &[f64], y: &[f64], A: &mut TransposedMatrix) {
+    assert_eq!(x.len(), A.rows);
+    assert_eq!(y.len(), A.columns);
+
+    if A.rows == 0 {
+        return;
+    }
+
+    for (column, &y) in A.data.chunks_mut(A.rows).zip(y.iter()) {
+        axpy(column, x, alpha * y);
+    }
+}
+
+/// Compute A = alpha * x * y^T + A on a column major matrix, the columns are distributed over
+/// the rayon workers
+#[allow(non_snake_case)]
+pub fn ger_transposed_rayon(alpha: f64, x: &[f64], y: &[f64], A: &mut TransposedMatrix) {
+    assert_eq!(x.len(), A.rows);
+    assert_eq!(y.len(), A.columns);
+
+    if A.rows == 0 {
+        return;
+    }
+
+    A.data.par_chunks_mut(A.rows).zip(y.par_iter()).for_each(|(column, &y)| axpy(column, x, alpha * y));
+}
+
+/// The operands of a level 2 kernel, A is stored in both layouts
+pub struct Level2Operands {
+    pub a: Matrix,
+    pub a_t: TransposedMatrix,
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+}
+
+impl Level2Operands {
+    /// Operands for a (m x n) matrix, x has length n and y length m
+    ///
+    /// The vectors are sized for gemv, ger uses them swapped as x * y^T needs x of length m.
+    pub fn new(m: usize, n: usize) -> Self {
+        let a = Matrix::random(m, n);
+
+        Self {
+            a_t: TransposedMatrix::from(a.clone()),
+            a: a,
+            x: (0..n).map(|i| i as f64).collect(),
+            y: (0..m).map(|i| i as f64).collect(),
+        }
+    }
+}
+
+/// A level 2 kernel with the traffic and work it causes
+pub struct Level2 {
+    pub name: &'static str,
+    /// Whether the matrix is written, gemv only reads it while ger updates it
+    pub writes_matrix: bool,
+    pub run: fn(&mut Level2Operands),
+}
+
+impl Level2 {
+    /// The bytes moved for a (m x n) matrix
+    pub fn bytes(&self, m: usize, n: usize) -> u64 {
+        let elements = if self.writes_matrix {
+            // A is read and written, x and y are read
+            2 * m * n + m + n
+        }
+        else {
+            // A and x are read, y is read and written
+            m * n + n + 2 * m
+        };
+        (elements * std::mem::size_of::<f64>()) as u64
+    }
+
+    /// The floating point operations for a (m x n) matrix, one multiply and add per element
+    pub fn flop(&self, m: usize, n: usize) -> u64 {
+        (2 * m * n) as u64
+    }
+}
+
+/// All level 2 kernels which can be selected by name, the _t variants work on the column major
+/// TransposedMatrix
+pub static LEVEL2: &'static [Level2] = &[
+    Level2 { name: "gemv", writes_matrix: false, run: run_gemv },
+    Level2 { name: "gemv_simd", writes_matrix: false, run: run_gemv_simd },
+    Level2 { name: "gemv_rayon", writes_matrix: false, run: run_gemv_rayon },
+    Level2 { name: "gemv_t", writes_matrix: false, run: run_gemv_t },
+    Level2 { name: "gemv_t_simd", writes_matrix: false, run: run_gemv_t_simd },
+    Level2 { name: "gemv_t_rayon", writes_matrix: false, run: run_gemv_t_rayon },
+    Level2 { name: "ger", writes_matrix: true, run: run_ger },
+    Level2 { name: "ger_simd", writes_matrix: true, run: run_ger_simd },
+    Level2 { name: "ger_rayon", writes_matrix: true, run: run_ger_rayon },
+    Level2 { name: "ger_t", writes_matrix: true, run: run_ger_t },
+    Level2 { name: "ger_t_simd", writes_matrix: true, run: run_ger_t_simd },
+    Level2 { name: "ger_t_rayon", writes_matrix: true, run: run_ger_t_rayon },
+];
+
+/// Find a level 2 kernel by its name
+pub fn find_level2(name: &str) -> Option<&'static Level2> {
+    LEVEL2.iter().find(|kernel| kernel.name == name)
+}
+
+/// The alpha and beta used in the benchmarks
+const ALPHA: f64 = 1.5;
+const BETA: f64 = 0.5;
+
+fn run_gemv(ops: &mut Level2Operands) {
+    gemv(ALPHA, &ops.a, &ops.x, BETA, &mut ops.y);
+}
+
+fn run_gemv_simd(ops: &mut Level2Operands) {
+    gemv_simd(ALPHA, &ops.a, &ops.x, BETA, &mut ops.y);
+}
+
+fn run_gemv_rayon(ops: &mut Level2Operands) {
+    gemv_rayon(ALPHA, &ops.a, &ops.x, BETA, &mut ops.y);
+}
+
+fn run_gemv_t(ops: &mut Level2Operands) {
+    gemv(ALPHA, &ops.a_t, &ops.x, BETA, &mut ops.y);
+}
+
+fn run_gemv_t_simd(ops: &mut Level2Operands) {
+    gemv_transposed_simd(ALPHA, &ops.a_t, &ops.x, BETA, &mut ops.y);
+}
+
+fn run_gemv_t_rayon(ops: &mut Level2Operands) {
+    gemv_transposed_rayon(ALPHA, &ops.a_t, &ops.x, BETA, &mut ops.y);
+}
+
+fn run_ger(ops: &mut Level2Operands) {
+    ger(ALPHA, &ops.y, &ops.x, &mut ops.a);
+}
+
+fn run_ger_simd(ops: &mut Level2Operands) {
+    ger_simd(ALPHA, &ops.y, &ops.x, &mut ops.a);
+}
+
+fn run_ger_rayon(ops: &mut Level2Operands) {
+    ger_rayon(ALPHA, &ops.y, &ops.x, &mut ops.a);
+}
+
+fn run_ger_t(ops: &mut Level2Operands) {
+    ger(ALPHA, &ops.y, &ops.x, &mut ops.a_t);
+}
+
+fn run_ger_t_simd(ops: &mut Level2Operands) {
+    ger_transposed_simd(ALPHA, &ops.y, &ops.x, &mut ops.a_t);
+}
+
+fn run_ger_t_rayon(ops: &mut Level2Operands) {
+    ger_transposed_rayon(ALPHA, &ops.y, &ops.x, &mut ops.a_t);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shapes with and without simd tails
+    const SHAPES: &'static [(usize, usize)] = &[(1, 1), (8, 4), (13, 7), (32, 48), (67, 131)];
+
+    fn vector(len: usize, offset: usize) -> Vec<f64> {
+        (0..len).map(|i| ((i + offset) % 11) as f64).collect()
+    }
+
+    #[test]
+    fn test_gemv() {
+        for &(m, n) in SHAPES {
+            let a = Matrix::<f64>::random(m, n);
+            let a_t = TransposedMatrix::from(a.clone());
+            let x = vector(n, 3);
+            let y = vector(m, 5);
+
+            let mut compare = y.clone();
+            gemv(2., &a, &x, 0.5, &mut compare);
+
+            let mut res = y.clone();
+            gemv(2., &a_t, &x, 0.5, &mut res);
+            assert_eq!(res, compare, "gemv_t {}x{}", m, n);
+
+            let mut res = y.clone();
+            gemv_simd(2., &a, &x, 0.5, &mut res);
+            assert_eq!(res, compare, "gemv_simd {}x{}", m, n);
+
+            let mut res = y.clone();
+            gemv_rayon(2., &a, &x, 0.5, &mut res);
+            assert_eq!(res, compare, "gemv_rayon {}x{}", m, n);
+
+            let mut res = y.clone();
+            gemv_transposed_simd(2., &a_t, &x, 0.5, &mut res);
+            assert_eq!(res, compare, "gemv_transposed_simd {}x{}", m, n);
+
+            let mut res = y.clone();
+            gemv_transposed_rayon(2., &a_t, &x, 0.5, &mut res);
+            assert_eq!(res, compare, "gemv_transposed_rayon {}x{}", m, n);
+        }
+    }
+
+    #[test]
+    fn test_gemv_beta() {
+        let a = Matrix::<f64>::random(9, 6);
+        let a_t = TransposedMatrix::from(a.clone());
+        let x = vector(6, 0);
+
+        let mut compare = vec![0.; 9];
+        gemv(1., &a, &x, 0., &mut compare);
+
+        // beta = 0 must not read y
+        let nan = vec![std::f64::NAN; 9];
+
+        let mut res = nan.clone();
+        gemv_simd(1., &a, &x, 0., &mut res);
+        assert_eq!(res, compare);
+
+        let mut res = nan.clone();
+        gemv_rayon(1., &a, &x, 0., &mut res);
+        assert_eq!(res, compare);
+
+        let mut res = nan.clone();
+        gemv_transposed_simd(1., &a_t, &x, 0., &mut res);
+        assert_eq!(res, compare);
+
+        let mut res = nan.clone();
+        gemv_transposed_rayon(1., &a_t, &x, 0., &mut res);
+        assert_eq!(res, compare);
+    }
+
+    #[test]
+    fn test_ger() {
+        for &(m, n) in SHAPES {
+            let a = Matrix::<f64>::random(m, n);
+            let x = vector(m, 1);
+            let y = vector(n, 4);
+
+            let mut compare = a.clone();
+            ger(3., &x, &y, &mut compare);
+            let compare_t = TransposedMatrix::from(compare.clone());
+
+            let mut res = a.clone();
+            ger_simd(3., &x, &y, &mut res);
+            assert_eq!(res, compare, "ger_simd {}x{}", m, n);
+
+            let mut res = a.clone();
+            ger_rayon(3., &x, &y, &mut res);
+            assert_eq!(res, compare, "ger_rayon {}x{}", m, n);
+
+            let mut res = TransposedMatrix::from(a.clone());
+            ger(3., &x, &y, &mut res);
+            assert_eq!(res, compare_t, "ger_t {}x{}", m, n);
+
+            let mut res = TransposedMatrix::from(a.clone());
+            ger_transposed_simd(3., &x, &y, &mut res);
+            assert_eq!(res, compare_t, "ger_transposed_simd {}x{}", m, n);
+
+            let mut res = TransposedMatrix::from(a.clone());
+            ger_transposed_rayon(3., &x, &y, &mut res);
+            assert_eq!(res, compare_t, "ger_transposed_rayon {}x{}", m, n);
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        for kernel in LEVEL2 {
+            assert_eq!(find_level2(kernel.name).map(|k| k.name), Some(kernel.name));
+        }
+        assert!(find_level2("gemm").is_none());
+
+        // all gemv variants compute the same y, all ger variants the same A
+        let reference = |name: &str| {
+            let mut ops = Level2Operands::new(21, 10);
+            (find_level2(name).unwrap().run)(&mut ops);
+            ops
+        };
+        let gemv_ops = reference("gemv");
+        let ger_ops = reference("ger");
+        assert!(ger_ops.a != Level2Operands::new(21, 10).a);
+
+        for kernel in LEVEL2 {
+            let mut ops = Level2Operands::new(21, 10);
+            (kernel.run)(&mut ops);
+
+            if kernel.name.starts_with("ger_t") {
+                assert_eq!(ops.a_t, TransposedMatrix::from(ger_ops.a.clone()), "{}", kernel.name);
+            }
+            else if kernel.writes_matrix {
+                assert_eq!(ops.a, ger_ops.a, "{}", kernel.name);
+            }
+            else {
+                assert_eq!(ops.y, gemv_ops.y, "{}", kernel.name);
+            }
+        }
+
+        assert_eq!(LEVEL2[0].bytes(4, 2), ((8 + 2 + 8) * 8) as u64);
+        assert_eq!(find_level2("ger").unwrap().bytes(4, 2), ((16 + 4 + 2) * 8) as u64);
+        assert_eq!(LEVEL2[0].flop(4, 2), 16);
+    }
+}
@@ -0,0 +1,55 @@
+#![cfg_attr(test, feature(test))]
+
+#![feature(iterator_step_by)]
+#![feature(iterator_for_each)]
+
+#![feature(drop_types_in_const)]
+#![feature(const_fn)]
+
+#![feature(inclusive_range_syntax)]
+
+#![feature(asm)]
+
+#[macro_use]
+extern crate lazy_static;
+extern crate hwloc;
+extern crate rayon;
+
+use hwloc::Topology;
+
+
+#[allow(unused)]
+pub mod triades;
+
+#[allow(unused)]
+pub mod matmul;
+
+pub mod measure;
+
+pub mod roofline;
+
+pub mod affinity;
+
+pub mod aligned;
+
+pub mod cblas;
+
+pub mod level2;
+
+use triades::NumType;
+
+
+lazy_static ! {
+    static ref TOPOLOGY: Topology = Topology::new();
+}
+
+extern crate rand;
+#[allow(unused)]
+fn random_array() -> Vec<NumType>
+{
+    let array_length = match std::env::var("ARRAY_SIZE") {
+        Ok(len) => len.parse::<usize>().expect("ARRAY_SIZE env variable must be the array length"),
+        Err(_)  => 4000000
+    };
+    (0..array_length).map(|_| rand::random::<NumType>()).collect()
+}
@@ -1,113 +1,304 @@
-#![cfg_attr(test, feature(test))]
+extern crate rust;
+extern crate cpuprofiler;
+extern crate rayon;
 
-#![feature(iterator_step_by)]
-#![feature(iterator_for_each)]
+use cpuprofiler::PROFILER;
 
-#![feature(drop_types_in_const)]
-#![feature(const_fn)]
+use std::fs::File;
+use std::io::Write;
 
-#![feature(inclusive_range_syntax)]
 
-#![feature(asm)]
+mod cli;
 
-#[macro_use]
-extern crate lazy_static;
-extern crate hwloc;
-extern crate cpuprofiler;
-extern crate rayon;
+use rust::affinity;
+use rust::aligned;
+use rust::level2::{LEVEL2, Level2, Level2Operands, find_level2};
+use rust::triades::*;
+use rust::matmul::*;
+use rust::measure::{measure, matmul_flop, Report};
+use rust::roofline::{Roofline, Machine, Point, measure_bandwidth, measure_peak};
+use cli::{Config, Format};
 
-use cpuprofiler::PROFILER;
 
-use hwloc::Topology;
+fn select_kernels(names: &[String]) -> Result<Vec<&'static Kernel>, String> {
+    if names.iter().any(|name| name == "all") {
+        return Ok(KERNELS.to_vec());
+    }
 
-#[allow(unused)]
-mod triades;
+    names.iter()
+        .map(|name| find_kernel(name).ok_or_else(|| format!("unknown kernel '{}', see --list", name)))
+        .collect()
+}
 
-#[allow(unused)]
-mod matmul;
+fn select_streams(names: &[String]) -> Result<Vec<&'static Stream>, String> {
+    if names.iter().any(|name| name == "all") {
+        return Ok(STREAMS.iter().collect());
+    }
 
-use triades::*;
-use matmul::*;
+    names.iter()
+        .map(|name| find_stream(name).ok_or_else(|| format!("unknown STREAM kernel '{}', see --list", name)))
+        .collect()
+}
 
+fn select_level2(names: &[String]) -> Result<Vec<&'static Level2>, String> {
+    if names.iter().any(|name| name == "all") {
+        return Ok(LEVEL2.iter().collect());
+    }
 
-lazy_static ! {
-    static ref TOPOLOGY: Topology = Topology::new();
+    names.iter()
+        .map(|name| find_level2(name).ok_or_else(|| format!("unknown level 2 kernel '{}', see --list", name)))
+        .collect()
 }
 
-extern crate rand;
-#[allow(unused)]
-fn random_array() -> Vec<NumType>
-{
-    let array_length = match std::env::var("ARRAY_SIZE") {
-        Ok(len) => len.parse::<usize>().expect("ARRAY_SIZE env variable must be the array length"),
-        Err(_)  => 4000000
-    };
-    (0..array_length).map(|_| rand::random::<NumType>()).collect()
+fn print_header(config: &Config) {
+    match config.format {
+        Format::Text => println!("{}", Report::text_header()),
+        Format::Csv => println!("{}", Report::csv_header()),
+    }
 }
 
-#[allow(unused)]
-fn main() {
-    let n = 128 * 32;
+fn print_report(config: &Config, report: &Report) {
+    match config.format {
+        Format::Text => println!("{}", report),
+        Format::Csv => println!("{}", report.csv()),
+    }
+}
+
+/// Start profiling the run name into its own file, see Config::profile_path
+fn start_profiler(config: &Config, name: &str) -> Result<(), String> {
+    if let Some(path) = config.profile_path(name) {
+        PROFILER.lock().unwrap().start(path.as_str()).map_err(|e| format!("could not start the profiler: {:?}", e))?;
+    }
+    Ok(())
+}
+
+fn stop_profiler(config: &Config) -> Result<(), String> {
+    if config.profile.is_some() {
+        PROFILER.lock().unwrap().stop().map_err(|e| format!("could not stop the profiler: {:?}", e))?;
+    }
+    Ok(())
+}
+
+fn run_streams(config: &Config) -> Result<(), String> {
+    let streams = select_streams(&config.streams)?;
+
+    let (mut res, a, b, c) = prepare_arrays_with_length(config.array_size);
+
+    print_header(config);
+
+    for stream in streams {
+        let name = format!("{} {}", stream.name, config.array_size);
+
+        start_profiler(config, &name)?;
+        let stats = measure(config.warmup, config.reps, &mut res, |res| (stream.run)(res, &a, &b, &c), |_| {});
+        stop_profiler(config)?;
+
+        print_report(config, &Report {
+            name: name,
+            stats: stats,
+            flop: stream.flop(config.array_size),
+            bytes: stream.bytes(config.array_size, config.write_allocate),
+        });
+    }
+
+    Ok(())
+}
+
+fn run_level2(config: &Config) -> Result<(), String> {
+    let kernels = select_level2(&config.level2)?;
+
+    print_header(config);
+
+    for size in &config.sizes {
+        // A is (m x n), k is not used
+        let mut operands = Level2Operands::new(size.m, size.n);
+
+        for kernel in &kernels {
+            let name = format!("{} {}x{}", kernel.name, size.m, size.n);
+
+            start_profiler(config, &name)?;
+            let stats = measure(config.warmup, config.reps, &mut operands, |ops| (kernel.run)(ops), |_| {});
+            stop_profiler(config)?;
+
+            print_report(config, &Report {
+                name: name,
+                stats: stats,
+                flop: kernel.flop(size.m, size.n),
+                bytes: kernel.bytes(size.m, size.n),
+            });
+        }
+    }
 
-    let a = Matrix::random(n, n);
-    let b = Matrix::random(n, n);
-    let mut c = Matrix::zero(n, n);
+    Ok(())
+}
+
+fn run_matmul(config: &Config) -> Result<(), String> {
+    let kernels = select_kernels(&config.kernels)?;
+
+    print_header(config);
+
+    for size in &config.sizes {
+        let mut operands = Operands::new(Matrix::random(size.m, size.k), Matrix::random(size.k, size.n));
+
+        // compulsory traffic: read A and B, read and write C
+        let bytes = ((size.m * size.k + size.k * size.n + 2 * size.m * size.n) * std::mem::size_of::<f64>()) as u64;
+
+        for kernel in &kernels {
+            if !kernel.supports(size.m, size.k, size.n) {
+                eprintln!("skipping {}: size {} is not supported", kernel.name(), size);
+                continue;
+            }
+
+            operands.prepare(kernel.layouts());
+            let name = format!("{} {}", kernel.name(), size);
+
+            start_profiler(config, &name)?;
+            let stats = measure(config.warmup, config.reps, &mut operands, |ops| kernel.run(ops), |ops| ops.reset());
+            stop_profiler(config)?;
+
+            print_report(config, &Report {
+                name: name,
+                stats: stats,
+                flop: matmul_flop(size.m, size.k, size.n),
+                bytes: bytes,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &str, content: &str) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+fn run_roofline(config: &Config) -> Result<(), String> {
+    let kernels = select_kernels(&config.kernels)?;
+
+    let streams = if config.streams.is_empty() {
+        select_streams(&["copy".to_string(), "vtriad".to_string(), "vtriad_rayon".to_string()])?
+    }
+    else {
+        select_streams(&config.streams)?
+    };
+
+    let mut roofline = Roofline::new(Machine {
+        bandwidth: measure_bandwidth(&streams, config.array_size, config.reps, config.write_allocate),
+        peak: measure_peak(10_000_000, config.reps),
+    });
 
-    let a_t = TileMatrix::from(a.clone());
-    let b_t = TileMatrix::from(b.clone());
-    let mut c_t = TileMatrix::from(c.clone());
+    for size in &config.sizes {
+        let mut operands = Operands::new(Matrix::random(size.m, size.k), Matrix::random(size.k, size.n));
 
-    #[allow(non_snake_case)]
-    let bT = TransposedMatrix::from(b.clone());
+        for kernel in &kernels {
+            if !kernel.supports(size.m, size.k, size.n) {
+                eprintln!("skipping {}: size {} is not supported", kernel.name(), size);
+                continue;
+            }
 
-    rayon::initialize(rayon::Configuration::new());
+            operands.prepare(kernel.layouts());
 
-    #[cfg(release)] // otherwise the output does not reflect runtime
-    PROFILER.lock().unwrap().start("/tmp/profile");
-    for _ in 0..10 {
-        //matmul::naive::mult(&a, &b, &mut c); c.reset();
+            let stats = measure(config.warmup, config.reps, &mut operands, |ops| kernel.run(ops), |ops| ops.reset());
+            roofline.points.push(Point::matmul(format!("{} {}", kernel.name(), size), size.m, size.k, size.n, &stats));
+        }
+    }
 
-        //matmul::naive_unchecked::mult(&a, &b, &mut c); c.reset();
-        //matmul::naive_iter::mult(&a, &bT, &mut c); c.reset();
-        //matmul::naive_reordered::mult(&a, &b, &mut c); c.reset();
-//        matmul::naive_simd::mult(&a, &b, &mut c); c.reset();
-        matmul::naive_rayon::mult(&a, &b, &mut c); c.reset();
+    print!("{}", roofline);
 
-//        matmul::tiled::mult(&a_t, &b_t, &mut c_t); c_t.reset();
-//        matmul::tiled_rayon::mult(&a_t, &b_t, &mut c_t); c_t.reset();
+    if let Some(ref path) = config.csv {
+        write_file(path, &roofline.csv())?;
+    }
+    if let Some(ref path) = config.svg {
+        write_file(path, &roofline.svg())?;
+    }
+
+    Ok(())
+}
 
-//        matmul::blocked::mult(&a, &bT, &mut c); c.reset()
-        matmul::asm::mult(&a, &bT, &mut c); c.reset()
+fn run_tune(config: &Config) -> Result<(), String> {
+    let kernels = select_kernels(&config.kernels)?;
+    let tiles = autotune::default_tiles();
 
+    for size in &config.sizes {
+        match autotune::tune_and_store(size.m, size.k, size.n, &kernels, &tiles, config.warmup, config.reps)? {
+            Some(entry) => println!("{:<16} {:<18} tile {:>4} {:>8.2} GFLOP/s", size.to_string(), entry.kernel, entry.tile, entry.gflops),
+            None => eprintln!("skipping {}: no selected kernel supports it", size),
+        }
     }
-    #[cfg(release)] // otherwise the output does not reflect runtime
-    PROFILER.lock().unwrap().stop();
 
-    let t: &Matrix = &c_t[0];
-    println!("{}", c[0]);
-    println!("{}", t[0]);
+    println!("results written to {}", autotune::tuning_file());
 
+    Ok(())
+}
 
-    //matmul::blocked::mult(&a, &b, &mut c);
+fn run(config: &Config) -> Result<(), String> {
+    affinity::set_policy(config.bind);
+    aligned::set_alignment(config.align);
+    strassen::set_cutoff(config.cutoff);
 
-    //let mut res = vec![0 as NumType; ARRAY_SIZE];
-    //let a = random_array();
-    //let b = random_array();
-    //let c = random_array();
+    let policy = config.bind;
+    let configuration = rayon::Configuration::new().start_handler(move |index| {
+        if let Err(e) = affinity::bind_current_thread(policy, index) {
+            eprintln!("warning: {}", e);
+        }
+    });
+    let configuration = if config.threads > 0 { configuration.num_threads(config.threads) } else { configuration };
 
+    rayon::initialize(configuration)
+        .map_err(|e| format!("could not start the thread pool: {}", e))?;
 
-//    copy(&a, &mut res);
-//    add(&mut res, &a, &b);
-//    striad(&mut res, &a, &b, S);
-//    vtriad(&mut res, &a, &b, &c);
+    if config.tune {
+        run_tune(config)
+    }
+    else if config.roofline {
+        run_roofline(config)
+    }
+    else if !config.level2.is_empty() {
+        run_level2(config)
+    }
+    else if config.streams.is_empty() {
+        run_matmul(config)
+    }
+    else {
+        run_streams(config)
+    }
+}
 
-    //vtriad_rayon(&mut res, &a, &b, &c);
+fn main() {
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    //vtriad_rayon(&mut res, &a, &b, &c);
-    //vtriad_threads(&mut res, &a, &b, &c);
-    //vtriad_itertools(&mut res, &a, &b, &c);
-    //vtriad_simd(&mut res, &a, &b, &c);
-    //for i in 0..ARRAY_SIZE {
-     //   println!("{}: {} * {} + {} = {}", i, a[i], c[i], b[i], res[i]);
-    //}
+    if config.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+
+    if config.list {
+        for kernel in KERNELS {
+            let (a, b, c) = kernel.layouts();
+            println!("{:<18} A: {}, B: {}, C: {}", kernel.name(), a, b, c);
+        }
+        for stream in STREAMS {
+            println!("{:<18} STREAM, {} read, {} written", stream.name, stream.reads, stream.writes);
+        }
+        for kernel in LEVEL2 {
+            println!("{:<18} level 2, matrix {}", kernel.name, if kernel.writes_matrix { "read and written" } else { "read" });
+        }
+        println!();
+        println!("caches:      {}", tuning::caches());
+        println!("block sizes: {}", tuning::block_sizes::<f64>());
+        return;
+    }
+
+    if let Err(e) = run(&config) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
 }
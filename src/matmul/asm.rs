@@ -3,7 +3,6 @@
 use super::macros;
 
 use std;
-use self::std::ptr;
 
 extern crate simd;
 use self::simd::x86::avx::f64x4;
@@ -19,19 +18,18 @@ pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
     assert_eq!(A.rows, C.rows);
     assert_eq!(B.columns, C.columns);
 
-    assert_eq!(C.rows % 4, 0);
-    assert_eq!(A.columns % 4, 0);
-    assert_eq!(C.columns % 4, 0);
-
-    assert!(A.is_aligned() && B.is_aligned() && C.is_aligned());
+    // the asm loops handle 4 elements of the dot product at once, the rest is added afterwards
+    let k_simd = A.columns - A.columns % 4;
 
     (0..C.rows).into_par_iter().for_each(|i| {
         let c_data = C.data.as_ptr() as *mut f64;
         for j in 0..C.columns {
             let mut result1 = 0.0;
 
-            let mut indexA = i * C.columns;
-            let mut indexB = j * C.rows; // TODO
+            // rows of A and columns of B are only aligned if their length is a multiple of 4,
+            // so the loads are unaligned
+            let mut indexA = i * A.columns;
+            let mut indexB = j * B.rows;
 
             let mut res = f64x4::splat(0.0);
 
@@ -57,53 +55,50 @@ pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
                     :"%ymm1", "%ymm2", "%ymm3", "%ymm4", "%eax"
                     :
                 );*/
-                if C.columns % 16 == 0 {
+                // the loops run at least once, so they are skipped for k < 4
+                if k_simd > 0 && C.columns % 16 == 0 {
                     asm!(
                         "
                         movq $$0, %rdx
                         1:
-                        vmovapd ($1, $3, 8), %ymm1
-                        vmovapd ($2, $4, 8), %ymm2
+                        vmovupd ($3, $1, 8), %ymm1
+                        vmovupd ($4, $2, 8), %ymm2
                         vfmadd231pd %ymm1, %ymm2, $0
 
-                        addq $$4, $3
-                        addq $$4, $4
+                        addq $$4, $1
+                        addq $$4, $2
                         addq $$4, %rdx
                         cmp $5, %rdx
                         jl  1b
                         "
-                        : "+x"(res)
+                        : "+x"(res), "+r"(indexA), "+r"(indexB)
                         : "r" (A.data.as_ptr()),
                           "r" (B.data.as_ptr() as *mut f64),
-                          "r" (indexA),
-                          "r" (indexB),
-                          "r" (A.columns)
-                        : "rdx", "ymm1", "ymm2", "ymm3"
+                          "r" (k_simd)
+                        : "rdx", "ymm1", "ymm2", "ymm3", "cc"
                     );
                 }
-                else {
+                else if k_simd > 0 {
                     asm!(
                         "
                         movq $$0, %rdx
                         1:
-                        vmovapd ($1, $3, 8), %ymm1
-                        vmovapd ($2, $4, 8), %ymm2
+                        vmovupd ($3, $1, 8), %ymm1
+                        vmovupd ($4, $2, 8), %ymm2
                         vmulpd %ymm1, %ymm2, %ymm3
                         vaddpd %ymm3, $0, $0
 
-                        addq $$4, $3
-                        addq $$4, $4
+                        addq $$4, $1
+                        addq $$4, $2
                         addq $$4, %rdx
                         cmp $5, %rdx
                         jl  1b
                         "
-                        : "+x"(res)
+                        : "+x"(res), "+r"(indexA), "+r"(indexB)
                         : "r" (A.data.as_ptr()),
                           "r" (B.data.as_ptr() as *mut f64),
-                          "r" (indexA),
-                          "r" (indexB),
-                          "r" (A.columns)
-                        : "rdx", "ymm1", "ymm2", "ymm3"
+                          "r" (k_simd)
+                        : "rdx", "ymm1", "ymm2", "ymm3", "cc"
                     );
                 }
             }
@@ -148,10 +143,19 @@ pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
             for k in 0..4 {
                 result1 += res.extract(k);
             }
+
+            // indexA and indexB point behind the part done by the asm loop
+            for k in 0..A.columns - k_simd {
+                result1 += A.data[indexA + k] * B.data[indexB + k];
+            }
+
             unsafe {
-                ptr::write(c_data.offset((i * C.columns + j) as isize), result1);}
+                *c_data.offset((i * C.columns + j) as isize) += result1;
+            }
         }
     });
 }
 
+kernel!(Asm, "asm", f64, (Matrix, TransposedMatrix, Matrix), |m, k, n| true);
+
 generate_tests!(Matrix, TransposedMatrix, Matrix);
\ No newline at end of file
@@ -0,0 +1,424 @@
+
+use std;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+extern crate rayon;
+
+use measure::{measure, matmul_flop};
+
+use super::*;
+
+/// The environment variable which overrides the path of the tuning file
+pub const TUNING_FILE_VAR: &'static str = "MATMUL_TUNING_FILE";
+
+/// The tuning file used if TUNING_FILE_VAR is not set
+pub const DEFAULT_TUNING_FILE: &'static str = "matmul_tuning.tsv";
+
+/// The path of the tuning file
+pub fn tuning_file() -> String {
+    std::env::var(TUNING_FILE_VAR).unwrap_or_else(|_| DEFAULT_TUNING_FILE.to_string())
+}
+
+/// The CPU model from /proc/cpuinfo
+pub fn cpu_model() -> String {
+    let mut cpuinfo = String::new();
+
+    if File::open("/proc/cpuinfo").and_then(|mut file| file.read_to_string(&mut cpuinfo)).is_ok() {
+        for line in cpuinfo.lines() {
+            if line.starts_with("model name") {
+                if let Some(pos) = line.find(':') {
+                    return line[pos + 1..].trim().to_string();
+                }
+            }
+        }
+    }
+
+    "unknown".to_string()
+}
+
+/// The key of this machine in the tuning file, the CPU model and the number of threads
+pub fn machine_key() -> String {
+    format!("{}/{}", cpu_model(), rayon::current_num_threads())
+}
+
+/// The best kernel for a problem size on a machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub machine: String,
+    pub m: usize,
+    pub k: usize,
+    pub n: usize,
+    pub kernel: String,
+    /// The block size of the tiled layout, only used by tiled kernels
+    pub tile: usize,
+    pub gflops: f64,
+}
+
+impl fmt::Display for Entry {
+    /// Format the entry as a line of the tuning file
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}\t{}\t{}\t{}\t{}\t{}", self.machine, self.m, self.k, self.n, self.kernel, self.tile, self.gflops)
+    }
+}
+
+impl FromStr for Entry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let fields = s.split('\t').collect::<Vec<_>>();
+
+        if fields.len() != 7 {
+            return Err(format!("invalid tuning entry '{}'", s));
+        }
+
+        let number = |field: &str| field.parse::<usize>().map_err(|_| format!("invalid tuning entry '{}'", s));
+
+        Ok(Self {
+            machine: fields[0].to_string(),
+            m: number(fields[1])?,
+            k: number(fields[2])?,
+            n: number(fields[3])?,
+            kernel: fields[4].to_string(),
+            tile: number(fields[5])?,
+            gflops: fields[6].parse::<f64>().map_err(|_| format!("invalid tuning entry '{}'", s))?,
+        })
+    }
+}
+
+/// The contents of a tuning file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TuningTable {
+    pub entries: Vec<Entry>,
+}
+
+impl TuningTable {
+    /// Load the table from path, a missing file results in an empty table
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut content = String::new();
+
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut content).map_err(|e| format!("could not read {}: {}", path, e))?,
+            Err(_) => return Ok(Self::default()),
+        };
+
+        let entries = content.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| line.parse::<Entry>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { entries: entries })
+    }
+
+    /// Write the table to path
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut content = "# machine\tm\tk\tn\tkernel\ttile\tgflops\n".to_string();
+        for entry in &self.entries {
+            content += &format!("{}\n", entry);
+        }
+
+        File::create(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("could not write {}: {}", path, e))
+    }
+
+    /// Find the entry for the given machine and problem size
+    pub fn lookup(&self, machine: &str, m: usize, k: usize, n: usize) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.machine == machine && entry.m == m && entry.k == k && entry.n == n)
+    }
+
+    /// Add an entry, an existing entry for the same machine and size is replaced
+    pub fn insert(&mut self, entry: Entry) {
+        self.entries.retain(|e| !(e.machine == entry.machine && e.m == entry.m && e.k == entry.k && e.n == entry.n));
+        self.entries.push(entry);
+    }
+}
+
+/// The tile sizes tried for tiled kernels
+pub fn default_tiles() -> Vec<usize> {
+    let derived = tuning::block_sizes::<f64>().tile;
+
+    let mut tiles = vec![32, 64, 128, 256, derived];
+    tiles.sort();
+    tiles.dedup();
+    tiles
+}
+
+/// Benchmark the kernels and tile sizes for a (m x k) * (k x n) multiplication
+///
+/// Every supported kernel is measured, kernels with a tiled layout once for every tile size.
+/// Returns the fastest combination, or None if no kernel supports the size.
+pub fn tune(m: usize, k: usize, n: usize, kernels: &[&'static Kernel], tiles: &[usize], warmup: usize, reps: usize) -> Option<Entry> {
+    let a = Matrix::<f64>::random(m, k);
+    let b = Matrix::<f64>::random(k, n);
+
+    let machine = machine_key();
+    let mut best: Option<Entry> = None;
+
+    for kernel in kernels {
+        if !kernel.supports(m, k, n) {
+            continue;
+        }
+
+        let (la, lb, lc) = kernel.layouts();
+        let tiled = la.uses_tile() || lb.uses_tile() || lc.uses_tile();
+        let candidates = if tiled { tiles.to_vec() } else { vec![tuning::block_sizes::<f64>().tile] };
+
+        for tile in candidates {
+            let mut operands = Operands::new(a.clone(), b.clone());
+            operands.set_tile(tile);
+            operands.prepare(kernel.layouts());
+
+            let stats = measure(warmup, reps, &mut operands, |ops| kernel.run(ops), |ops| ops.reset());
+            let gflops = stats.gflops(matmul_flop(m, k, n)).0;
+
+            if best.as_ref().map_or(true, |best| gflops > best.gflops) {
+                best = Some(Entry {
+                    machine: machine.clone(),
+                    m: m,
+                    k: k,
+                    n: n,
+                    kernel: kernel.name().to_string(),
+                    tile: tile,
+                    gflops: gflops,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+lazy_static! {
+    static ref TABLE: RwLock<Option<TuningTable>> = RwLock::new(None);
+}
+
+/// Run f on the tuning table, it is loaded from the tuning file on first use
+fn with_table<F, R>(f: F) -> R
+    where F: FnOnce(&TuningTable) -> R
+{
+    if TABLE.read().unwrap().is_none() {
+        let mut table = TABLE.write().unwrap();
+        if table.is_none() {
+            // an unreadable file is treated like a missing one, mult falls back to packed then
+            *table = Some(TuningTable::load(&tuning_file()).unwrap_or_default());
+        }
+    }
+
+    f(TABLE.read().unwrap().as_ref().unwrap())
+}
+
+/// Tune a problem size and store the result in the tuning file
+pub fn tune_and_store(m: usize, k: usize, n: usize, kernels: &[&'static Kernel], tiles: &[usize], warmup: usize, reps: usize) -> Result<Option<Entry>, String> {
+    let entry = match tune(m, k, n, kernels, tiles, warmup, reps) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    let path = tuning_file();
+    let mut table = TuningTable::load(&path)?;
+    table.insert(entry.clone());
+    table.save(&path)?;
+
+    *TABLE.write().unwrap() = Some(table);
+
+    Ok(Some(entry))
+}
+
+/// The kernel and tile size which table selects for a (m x k) * (k x n) multiplication
+fn choose(table: &TuningTable, m: usize, k: usize, n: usize) -> Option<(&'static Kernel, usize)> {
+    table.lookup(&machine_key(), m, k, n).and_then(|entry| {
+        find_kernel(&entry.kernel)
+            .and_then(|kernel| if kernel.supports(m, k, n) { Some((kernel, entry.tile)) } else { None })
+    })
+}
+
+/// Run the chosen kernel on the borrowed operands, packed::mult without a choice
+#[allow(non_snake_case)]
+fn dispatch(choice: Option<(&'static Kernel, usize)>, A: &Matrix, B: &Matrix, C: &mut Matrix) {
+    match choice {
+        Some((kernel, tile)) => kernel.mult_row_major(A, B, C, tile),
+        None => packed::mult(A, B, C),
+    }
+}
+
+/// Run the chosen view kernel, packed without a choice
+#[allow(non_snake_case)]
+fn dispatch_view(choice: Option<&'static ViewKernel>, A: MatrixView, B: MatrixView, C: &mut MatrixViewMut) {
+    match choice {
+        Some(kernel) => kernel.mult_view(A, B, C),
+        None => packed::Packed.mult_view(A, B, C),
+    }
+}
+
+/// Compute C += A * B with the kernel which table selects for this size and machine
+#[allow(non_snake_case)]
+pub fn mult_with(table: &TuningTable, A: &Matrix, B: &Matrix, C: &mut Matrix) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    dispatch(choose(table, A.rows, A.columns, B.columns), A, B, C);
+}
+
+/// Compute C += A * B with the kernel which was tuned for this size and machine
+///
+/// Sizes without an entry in the tuning file use the packed kernel. Row major kernels work on
+/// the operands directly, only kernels with other layouts convert them.
+#[allow(non_snake_case)]
+pub fn mult(A: &Matrix, B: &Matrix, C: &mut Matrix) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    let choice = with_table(|table| choose(table, A.rows, A.columns, B.columns));
+    dispatch(choice, A, B, C);
+}
+
+/// The view kernel which table selects for a (m x k) * (k x n) multiplication
+///
+/// Tuned kernels which do not work on views, e.g. the tiled ones, are not chosen.
+fn choose_view(table: &TuningTable, m: usize, k: usize, n: usize) -> Option<&'static ViewKernel> {
+    table.lookup(&machine_key(), m, k, n).and_then(|entry| {
+        find_view_kernel(&entry.kernel).ok()
+            .and_then(|kernel| if kernel.supports(m, k, n) { Some(kernel) } else { None })
+    })
+}
+
+/// Compute C += A * B on views with the kernel which table selects for this size and machine
+#[allow(non_snake_case)]
+pub fn mult_view_with(table: &TuningTable, A: MatrixView, B: MatrixView, C: &mut MatrixViewMut) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    dispatch_view(choose_view(table, A.rows, A.columns, B.columns), A, B, C);
+}
+
+/// Compute C += A * B on views with the kernel which was tuned for this size and machine
+///
+/// Sizes without an entry in the tuning file and tuned kernels which need another layout use the
+/// packed kernel, so the blocks are never copied.
+#[allow(non_snake_case)]
+pub fn mult_view(A: MatrixView, B: MatrixView, C: &mut MatrixViewMut) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    let choice = with_table(|table| choose_view(table, A.rows, A.columns, B.columns));
+    dispatch_view(choice, A, B, C);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate libc;
+
+    #[test]
+    fn test_entry() {
+        let entry = Entry {
+            machine: "Intel(R) Xeon(R) CPU E5-2630 v3 @ 2.40GHz/16".to_string(),
+            m: 128,
+            k: 64,
+            n: 32,
+            kernel: "tiled_rayon".to_string(),
+            tile: 64,
+            gflops: 12.5,
+        };
+
+        assert_eq!(format!("{}", entry).parse::<Entry>(), Ok(entry));
+        assert!("a\t1\t2\t3\tnaive\t4".parse::<Entry>().is_err());
+        assert!("a\t1\tx\t3\tnaive\t4\t1.0".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn test_table() {
+        let entry = |kernel: &str, n: usize| Entry {
+            machine: "test/1".to_string(),
+            m: n,
+            k: n,
+            n: n,
+            kernel: kernel.to_string(),
+            tile: 128,
+            gflops: 1.,
+        };
+
+        let mut table = TuningTable::default();
+        table.insert(entry("naive", 64));
+        table.insert(entry("tiled", 128));
+        table.insert(entry("packed", 64));
+
+        assert_eq!(table.entries.len(), 2);
+        assert_eq!(table.lookup("test/1", 64, 64, 64), Some(&entry("packed", 64)));
+        assert_eq!(table.lookup("test/2", 64, 64, 64), None);
+
+        let path = std::env::temp_dir().join("matmul_tuning_test.tsv");
+        let path = path.to_str().unwrap();
+
+        table.save(path).unwrap();
+        assert_eq!(TuningTable::load(path), Ok(table));
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(TuningTable::load(path), Ok(TuningTable::default()));
+    }
+
+    #[test]
+    fn test_tune() {
+        let kernels = [find_kernel("naive").unwrap(), find_kernel("tiled").unwrap(), find_kernel("iter").unwrap()];
+
+        let entry = tune(32, 32, 32, &kernels, &[16, 32], 0, 1).unwrap();
+        assert!(entry.kernel == "naive" || entry.kernel == "tiled" || entry.kernel == "iter");
+        assert!(entry.gflops > 0.);
+
+        // iter needs m == n
+        let entry = tune(32, 16, 8, &kernels[2..], &[16], 0, 1);
+        assert_eq!(entry, None);
+    }
+
+    #[test]
+    fn test_mult() {
+        let entry = |kernel: &str, m: usize, k: usize, n: usize| Entry {
+            machine: machine_key(),
+            m: m,
+            k: k,
+            n: n,
+            kernel: kernel.to_string(),
+            tile: 16,
+            gflops: 1.,
+        };
+
+        // a table in a temporary file, so the result does not depend on the working directory
+        let mut table = TuningTable::default();
+        table.insert(entry("naive", 24, 40, 16));
+        table.insert(entry("tiled_rayon", 32, 32, 32));
+        table.insert(entry("morton", 16, 16, 16));
+
+        let path = std::env::temp_dir().join(format!("matmul_tuning_mult_{}.tsv", unsafe { libc::getpid() }));
+        let path = path.to_str().unwrap();
+        table.save(path).unwrap();
+        let table = TuningTable::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // row major, tiled, morton and no entry, which falls back to packed
+        for &(m, k, n) in &[(24, 40, 16), (32, 32, 32), (16, 16, 16), (8, 12, 4)] {
+            let a = Matrix::<f64>::random(m, k);
+            let b = Matrix::<f64>::random(k, n);
+
+            let mut compare: Matrix = generate::from_fn(m, n, |i, j| (i + j) as f64);
+            let mut res = compare.clone();
+            naive::mult(&a, &b, &mut compare);
+
+            let mut view_res = res.clone();
+            mult_with(&table, &a, &b, &mut res);
+            assert_eq!(compare, res, "{}x{}x{}", m, k, n);
+
+            // the tiled and morton entries are not chosen for views, they use packed
+            mult_view_with(&table, a.view(), b.view(), &mut view_res.view_mut());
+            assert_eq!(compare, view_res, "view {}x{}x{}", m, k, n);
+        }
+    }
+}
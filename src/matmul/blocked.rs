@@ -4,8 +4,9 @@ use super::macros;
 
 use super::*;
 
+/// Compute C += A * B, the operands may be stored in any layout, B is registered as TransposedMatrix
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
+pub fn mult<T: Element, LA: Layout, LB: Layout, LC: Layout>(A: &Matrix<T, LA>, B: &Matrix<T, LB>, C: &mut Matrix<T, LC>) {
     assert_eq!(A.columns, B.rows);
     assert_eq!(A.rows, C.rows);
     assert_eq!(B.columns, C.columns);
@@ -35,4 +36,6 @@ pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
     }
 }
 
+kernel!(Blocked, "blocked", (Matrix, TransposedMatrix, Matrix), |m, k, n| m % 2 == 0 && k % 2 == 0 && n % 2 == 0);
+
 generate_tests!(Matrix, TransposedMatrix, Matrix);
\ No newline at end of file
@@ -0,0 +1,403 @@
+//! Property based differential testing of kernels against naive::mult
+//!
+//! Random cases are drawn from a seed, every kernel computes the supported ones in its layouts and
+//! the result is compared to naive::mult. A failing case is shrunk to a minimal one, which is
+//! reported with its seed, so it can be reproduced with `check`. The registry only holds f64
+//! kernels, other element types are checked through `MatMul` with `run_typed`.
+
+use std;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+extern crate rand;
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+
+use super::*;
+
+/// The environment variable which sets the number of cases per kernel
+pub const CASES_VAR: &'static str = "MATMUL_CASES";
+
+/// The environment variable which sets the seed of the cases
+pub const SEED_VAR: &'static str = "MATMUL_SEED";
+
+/// The number of cases per kernel if CASES_VAR is not set
+pub const DEFAULT_CASES: usize = 24;
+
+/// The seed if SEED_VAR is not set, so runs are reproducible by default
+pub const DEFAULT_SEED: u64 = 2017;
+
+/// The largest dimension of a random case
+pub const MAX_SIZE: usize = 72;
+
+/// Sizes around the simd width and powers of two, where remainder handling breaks
+const EDGE_SIZES: &'static [usize] = &[1, 2, 3, 4, 5, 7, 8, 9, 15, 16, 17, 31, 32, 33, 63, 64, 65];
+
+/// The block sizes of tiled layouts
+const TILES: &'static [usize] = &[4, 8, 16, 32, 64];
+
+/// The element values of a case
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Values {
+    /// Uniform in [-1, 1)
+    Uniform,
+    /// Normally distributed with mean 0 and standard deviation 1
+    Normal,
+    /// Integers in [-8, 8), the products are exact in any summation order
+    Integers,
+}
+
+/// A (m x k) * (k x n) multiplication with random operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Case {
+    pub m: usize,
+    pub k: usize,
+    pub n: usize,
+    /// The block size of tiled layouts
+    pub tile: usize,
+    pub values: Values,
+    /// The seed of the operands
+    pub seed: u64,
+}
+
+impl Case {
+    /// Draw a random case
+    ///
+    /// Kernels often require square or even sizes, so a third of the cases is square and another
+    /// third square with a multiple of 4.
+    pub fn random<R: Rng>(rng: &mut R) -> Self {
+        let (m, k, n) = match rng.gen_range(0, 3) {
+            0 => (dimension(rng), dimension(rng), dimension(rng)),
+            1 => { let d = dimension(rng); (d, d, d) },
+            _ => { let d = (dimension(rng) + 3) / 4 * 4; (d, d, d) },
+        };
+
+        Self {
+            m: m,
+            k: k,
+            n: n,
+            tile: TILES[rng.gen_range(0, TILES.len())],
+            values: match rng.gen_range(0, 3) {
+                0 => Values::Uniform,
+                1 => Values::Normal,
+                _ => Values::Integers,
+            },
+            seed: rng.gen(),
+        }
+    }
+
+    /// Create the operands A and B
+    ///
+    /// Integer element types round the values down, so they are always compared exactly.
+    pub fn operands<T: Element>(&self) -> (Matrix<T>, Matrix<T>) {
+        let mut generator = Generator::new(self.seed);
+
+        match self.values {
+            Values::Uniform => (generator.uniform(self.m, self.k, -1., 1.), generator.uniform(self.k, self.n, -1., 1.)),
+            Values::Normal => (generator.normal(self.m, self.k, 0., 1.), generator.normal(self.k, self.n, 0., 1.)),
+            Values::Integers => (integers(&mut generator, self.m, self.k), integers(&mut generator, self.k, self.n)),
+        }
+    }
+
+    /// The allowed difference to naive::mult for elements of type T
+    ///
+    /// Integer values have to match exactly, reordered sums of floats may differ by a few
+    /// rounding errors, which are larger for f32.
+    pub fn tolerance<T: Element>(&self) -> Tolerance {
+        match self.values {
+            Values::Integers => Tolerance::exact(),
+            _ if T::EPSILON == 0. => Tolerance::exact(),
+            _ => {
+                let bound = f64::max(1e-10, 1e4 * T::EPSILON);
+                Tolerance::new(bound, bound, 0)
+            }
+        }
+    }
+
+    /// Smaller cases, the smallest ones first
+    ///
+    /// Every dimension is reduced on its own and all together, which keeps square cases square.
+    fn shrink(&self) -> Vec<Case> {
+        let smaller = |d: usize| {
+            let mut sizes = vec![1, d / 2, d - 1];
+            sizes.retain(|&s| s > 0 && s < d);
+            sizes.dedup();
+            sizes
+        };
+
+        let mut cases = Vec::new();
+        for s in smaller(self.m) {
+            if self.m == self.k && self.k == self.n {
+                cases.push(Case { m: s, k: s, n: s, ..*self });
+            }
+            cases.push(Case { m: s, ..*self });
+        }
+        for s in smaller(self.k) {
+            cases.push(Case { k: s, ..*self });
+        }
+        for s in smaller(self.n) {
+            cases.push(Case { n: s, ..*self });
+        }
+        cases
+    }
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}x{} with tile {}, {:?} values and seed {}", self.m, self.k, self.n, self.tile, self.values, self.seed)
+    }
+}
+
+/// A random dimension, edge sizes are drawn half of the time
+fn dimension<R: Rng>(rng: &mut R) -> usize {
+    if rng.gen() {
+        EDGE_SIZES[rng.gen_range(0, EDGE_SIZES.len())]
+    }
+    else {
+        rng.gen_range(1, MAX_SIZE + 1)
+    }
+}
+
+/// A random matrix of small integers
+fn integers<T: Element>(generator: &mut Generator, rows: usize, columns: usize) -> Matrix<T> {
+    let matrix: Matrix = generator.uniform(rows, columns, -8., 8.);
+    generate::from_fn(rows, columns, |i, j| T::from_f64(matrix[(i, j)].floor()))
+}
+
+/// Compare the result of a kernel run to naive::mult, a panic of the kernel is reported as error
+fn verify<T: Element>(run: std::thread::Result<Matrix<T>>, compare: &Matrix<T>, tolerance: Tolerance) -> Result<(), String> {
+    let result = match run {
+        Ok(result) => result,
+        Err(_) => return Err("the kernel panicked".to_string()),
+    };
+
+    match worst_offender(&result, compare, tolerance) {
+        Ok(None) => Ok(()),
+        Ok(Some(offender)) => Err(format!("the results differ at {}", offender)),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Run kernel on case and compare the result to naive::mult
+///
+/// The kernel has to support the size of the case. A panic of the kernel is reported as error.
+pub fn check(kernel: &Kernel, case: &Case) -> Result<(), String> {
+    let (a, b) = case.operands();
+
+    let mut compare = Matrix::<f64>::zero(case.m, case.n);
+    naive::mult(&a, &b, &mut compare);
+
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut operands = Operands::new(a, b);
+        operands.set_tile(case.tile);
+        operands.prepare(kernel.layouts());
+        kernel.run(&mut operands);
+        operands.result(kernel.layouts().2)
+    }));
+
+    verify(run, &compare, case.tolerance::<f64>())
+}
+
+/// Run kernel on case with elements of type T in the layouts A, B and C, see check
+///
+/// The operands are converted with From, so blocked layouts use the tuned block size of T
+/// instead of the tile of the case.
+pub fn check_typed<T, A, B, C, K>(kernel: &K, case: &Case) -> Result<(), String>
+    where T: Element, K: MatMul<A, B, C>,
+          A: From<Matrix<T>>, B: From<Matrix<T>>, C: From<Matrix<T>>, Matrix<T>: From<C>
+{
+    let (a, b) = case.operands::<T>();
+
+    let mut compare = Matrix::<T>::zero(case.m, case.n);
+    naive::mult(&a, &b, &mut compare);
+
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        let (a, b, mut c) = (A::from(a), B::from(b), C::from(Matrix::<T>::zero(case.m, case.n)));
+        kernel.mult(&a, &b, &mut c);
+        Matrix::<T>::from(c)
+    }));
+
+    verify(run, &compare, case.tolerance::<T>())
+}
+
+/// Reduce a failing case as long as a smaller case which supports accepts fails too
+fn shrink_with<S, F>(supports: S, check: F, mut case: Case, mut error: String) -> (Case, String)
+    where S: Fn(&Case) -> bool, F: Fn(&Case) -> Result<(), String>
+{
+    loop {
+        let smaller = case.shrink().into_iter()
+            .filter(|c| supports(c))
+            .filter_map(|c| check(&c).err().map(|e| (c, e)))
+            .next();
+
+        match smaller {
+            Some((c, e)) => { case = c; error = e; },
+            None => return (case, error),
+        }
+    }
+}
+
+/// Reduce a failing case as long as a smaller supported case fails too
+pub fn shrink(kernel: &Kernel, case: Case, error: String) -> (Case, String) {
+    shrink_with(|c| kernel.supports(c.m, c.k, c.n), |c| check(kernel, c), case, error)
+}
+
+/// Check up to cases cases drawn from seed which supports accepts, see run
+fn run_with<S, F>(name: &str, supports: S, check: F, cases: usize, seed: u64) -> Result<usize, String>
+    where S: Fn(&Case) -> bool, F: Fn(&Case) -> Result<(), String>
+{
+    let mut rng = XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x2545F491, 0x9E3779B9]);
+    let mut checked = 0;
+
+    for _ in 0..cases * 20 {
+        if checked == cases {
+            break;
+        }
+
+        let case = Case::random(&mut rng);
+        if !supports(&case) {
+            continue;
+        }
+
+        if let Err(error) = check(&case) {
+            let (minimal, error) = shrink_with(&supports, &check, case, error);
+            return Err(format!("{} fails on {} (shrunk from {}): {}", name, minimal, case, error));
+        }
+        checked += 1;
+    }
+
+    Ok(checked)
+}
+
+/// Check kernel on up to cases supported cases drawn from seed
+///
+/// Returns the number of checked cases, which is smaller than cases if the kernel supports few
+/// sizes, or the minimal failing case.
+pub fn run(kernel: &Kernel, cases: usize, seed: u64) -> Result<usize, String> {
+    run_with(kernel.name(), |c| kernel.supports(c.m, c.k, c.n), |c| check(kernel, c), cases, seed)
+}
+
+/// Check kernel with elements of type T in the layouts A, B and C, see run and check_typed
+pub fn run_typed<T, A, B, C, K>(kernel: &K, cases: usize, seed: u64) -> Result<usize, String>
+    where T: Element, K: MatMul<A, B, C>,
+          A: From<Matrix<T>>, B: From<Matrix<T>>, C: From<Matrix<T>>, Matrix<T>: From<C>
+{
+    run_with(MatMul::<A, B, C>::name(kernel),
+             |c| MatMul::<A, B, C>::supports(kernel, c.m, c.k, c.n),
+             |c| check_typed::<T, A, B, C, K>(kernel, c),
+             cases, seed)
+}
+
+/// The number of cases and the seed from the environment
+pub fn settings() -> (usize, u64) {
+    let cases = std::env::var(CASES_VAR).ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_CASES);
+    let seed = std::env::var(SEED_VAR).ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_SEED);
+    (cases, seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// naive::mult, which is wrong from 5 rows on
+    struct Broken;
+
+    impl Kernel for Broken {
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+
+        fn layouts(&self) -> (LayoutKind, LayoutKind, LayoutKind) {
+            (LayoutKind::RowMajor, LayoutKind::RowMajor, LayoutKind::RowMajor)
+        }
+
+        fn supports(&self, _m: usize, _k: usize, _n: usize) -> bool {
+            true
+        }
+
+        fn run(&self, operands: &mut Operands) {
+            let (a, b, c) = Select::<Matrix, Matrix, Matrix>::select(operands);
+            naive::mult(a, b, c);
+            if c.rows >= 5 {
+                c[(4, 0)] += 1.;
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels() {
+        let (cases, seed) = settings();
+
+        for kernel in KERNELS {
+            match run(*kernel, cases, seed) {
+                Ok(checked) => assert!(checked > 0, "no case was supported by {}", kernel.name()),
+                Err(error) => panic!("{}", error),
+            }
+        }
+    }
+
+    /// Check the generic kernels, which are only registered for f64, with elements of type T
+    fn check_element_type<T: Element>() {
+        let (cases, seed) = settings();
+
+        let results = vec![
+            run_typed::<T, Matrix<T>, Matrix<T>, Matrix<T>, _>(&naive_simd::NaiveSimd, cases, seed),
+            run_typed::<T, Matrix<T>, Matrix<T>, Matrix<T>, _>(&naive_rayon::NaiveRayon, cases, seed),
+            run_typed::<T, Matrix<T>, Matrix<T>, Matrix<T>, _>(&recursive::Recursive, cases, seed),
+            run_typed::<T, Matrix<T>, TransposedMatrix<T>, Matrix<T>, _>(&naive_transposed::NaiveTransposed, cases, seed),
+            run_typed::<T, TileMatrix<T>, TileMatrix<T>, TileMatrix<T>, _>(&tiled::Tiled, cases, seed),
+            run_typed::<T, TileMatrix<T>, TileMatrix<T>, TileMatrix<T>, _>(&strassen::Strassen, cases, seed),
+        ];
+
+        for result in results {
+            match result {
+                Ok(checked) => assert!(checked > 0),
+                Err(error) => panic!("{}", error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_element_types() {
+        check_element_type::<f32>();
+        check_element_type::<i32>();
+
+        let case = Case { m: 9, k: 40, n: 17, tile: 8, values: Values::Normal, seed: 4 };
+        assert_eq!(case.tolerance::<i32>(), Tolerance::exact());
+        assert!(case.tolerance::<f32>().absolute > case.tolerance::<f64>().absolute);
+
+        // integer elements are not truncated to zero
+        let (a, _) = case.operands::<i32>();
+        assert!(a.data.iter().any(|&x| x != 0));
+    }
+
+    #[test]
+    fn test_shrink() {
+        let case = Case { m: 40, k: 17, n: 9, tile: 8, values: Values::Integers, seed: 1 };
+        assert!(check(&Broken, &case).is_err());
+        assert_eq!(check(&naive::Naive, &case), Ok(()));
+
+        let (minimal, error) = shrink(&Broken, case, String::new());
+        assert_eq!((minimal.m, minimal.k, minimal.n), (5, 1, 1));
+        assert!(error.contains("(4, 0)"), "{}", error);
+
+        assert!(run(&Broken, 50, 3).unwrap_err().contains("5x1x1"));
+    }
+
+    #[test]
+    fn test_cases() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        for _ in 0..100 {
+            let case = Case::random(&mut rng);
+            assert!(case.m > 0 && case.k > 0 && case.n > 0 && case.m <= MAX_SIZE + 3);
+
+            let (a, b) = case.operands::<f64>();
+            assert_eq!((a.rows, a.columns, b.rows, b.columns), (case.m, case.k, case.k, case.n));
+            assert_eq!(case.operands(), (a, b));
+
+            for smaller in case.shrink() {
+                assert!(smaller.m * smaller.k * smaller.n < case.m * case.k * case.n);
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+
+use std;
+
+use super::*;
+
+/// Whether an operand of gemm is used as is or transposed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transpose {
+    NoTrans,
+    Trans,
+}
+
+/// A matrix which can be read with strides by gemm
+pub trait Operand {
+    /// The rows and columns of the stored matrix
+    fn shape(&self) -> (usize, usize);
+
+    /// The distance between two rows and two columns in elements
+    fn strides(&self) -> (usize, usize);
+
+    fn as_ptr(&self) -> *const f64;
+}
+
+impl<L: Dense> Operand for Matrix<f64, L> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    fn strides(&self) -> (usize, usize) {
+        Matrix::strides(self)
+    }
+
+    fn as_ptr(&self) -> *const f64 {
+        self.data.as_ptr()
+    }
+}
+
+impl<'a> Operand for MatrixView<'a> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    fn strides(&self) -> (usize, usize) {
+        (self.ld, 1)
+    }
+
+    fn as_ptr(&self) -> *const f64 {
+        MatrixView::as_ptr(self)
+    }
+}
+
+/// The shape and strides of op(X)
+fn op<X: Operand>(x: &X, trans: Transpose) -> (usize, usize, usize, usize) {
+    let (rows, columns) = x.shape();
+    let (rs, cs) = x.strides();
+
+    match trans {
+        Transpose::NoTrans => (rows, columns, rs, cs),
+        Transpose::Trans => (columns, rows, cs, rs),
+    }
+}
+
+/// Compute C = alpha * op(A) * op(B) + beta * C
+///
+/// op(A) is a (m x k), op(B) a (k x n) and C a (m x n) matrix. Transposed operands are read with
+/// swapped strides by the packed kernel, so they are never materialized. With beta = 0 C is
+/// overwritten and its previous content, even NaN, is ignored.
+#[allow(non_snake_case)]
+pub fn gemm<A: Operand, B: Operand>(trans_a: Transpose, trans_b: Transpose, alpha: f64, A: &A, B: &B, beta: f64, C: &mut Matrix) {
+    gemm_view(trans_a, trans_b, alpha, A, B, beta, &mut C.view_mut())
+}
+
+/// Compute C = alpha * op(A) * op(B) + beta * C on a block of a bigger matrix, see gemm
+#[allow(non_snake_case)]
+pub fn gemm_view<A: Operand, B: Operand>(trans_a: Transpose, trans_b: Transpose, alpha: f64, A: &A, B: &B, beta: f64, C: &mut MatrixViewMut) {
+    let (m, k, rsa, csa) = op(A, trans_a);
+    let (kb, n, rsb, csb) = op(B, trans_b);
+
+    assert_eq!(k, kb);
+    assert_eq!(m, C.rows);
+    assert_eq!(n, C.columns);
+
+    unsafe {
+        gemm_strided(m, n, k, alpha,
+                     A.as_ptr(), rsa, csa,
+                     B.as_ptr(), rsb, csb,
+                     beta, C.as_mut_ptr(), C.ld, 1);
+    }
+}
+
+/// Compute C = alpha * A * B + beta * C on strided storage
+///
+/// A is a (m x k), B a (k x n) and C a (m x n) matrix, the element (i, j) of X is stored at
+/// x[i * rsx + j * csx], see packed::gemm. This is the common part of gemm_view and the CBLAS
+/// interface, which passes column major and transposed operands as strides.
+///
+/// With alpha = 1 and row major operands the product is computed by the tuned kernel, see
+/// autotune::mult_view. Every other case uses the packed kernel, which applies alpha and reads
+/// transposed operands through the strides, so they are never materialized.
+pub unsafe fn gemm_strided(m: usize, n: usize, k: usize, alpha: f64,
+                           a: *const f64, rsa: usize, csa: usize,
+                           b: *const f64, rsb: usize, csb: usize,
+                           beta: f64, c: *mut f64, rsc: usize, csc: usize) {
+    if beta != 1. {
+        for i in 0..m {
+            for j in 0..n {
+                let c = c.offset((i * rsc + j * csc) as isize);
+                // with beta = 0 C is not read, so NaN in C does not propagate
+                *c = if beta == 0. { 0. } else { beta * *c };
+            }
+        }
+    }
+
+    if alpha == 0. || k == 0 || m == 0 || n == 0 {
+        return;
+    }
+
+    if alpha == 1. && csa == 1 && csb == 1 && csc == 1 && rsa >= k && rsb >= n && rsc >= n {
+        // the views span from the first element to the last element of the last row
+        let a = MatrixView::new(m, k, rsa, std::slice::from_raw_parts(a, (m - 1) * rsa + k));
+        let b = MatrixView::new(k, n, rsb, std::slice::from_raw_parts(b, (k - 1) * rsb + n));
+        let c = std::slice::from_raw_parts_mut(c, (m - 1) * rsc + n);
+
+        autotune::mult_view(a, b, &mut MatrixViewMut::new(m, n, rsc, c));
+        return;
+    }
+
+    packed::gemm(m, n, k, alpha, a, rsa, csa, b, rsb, csb, c, rsc, csc);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std;
+
+    fn transpose(matrix: &Matrix) -> Matrix {
+        let mut ret = Matrix::zero(matrix.columns, matrix.rows);
+        for i in 0..matrix.rows {
+            for j in 0..matrix.columns {
+                ret[(j, i)] = matrix[(i, j)];
+            }
+        }
+        ret
+    }
+
+    /// alpha * op(a) * op(b) + beta * c computed with the naive kernel
+    fn reference(trans_a: Transpose, trans_b: Transpose, alpha: f64, a: &Matrix, b: &Matrix, beta: f64, c: &Matrix) -> Matrix {
+        let a = if trans_a == Transpose::Trans { transpose(a) } else { a.clone() };
+        let b = if trans_b == Transpose::Trans { transpose(b) } else { b.clone() };
+
+        let mut ab = Matrix::zero(a.rows, b.columns);
+        naive::mult(&a, &b, &mut ab);
+
+        let data = ab.data.iter().zip(c.data.iter()).map(|(ab, c)| alpha * ab + beta * c).collect();
+        Matrix::new(c.rows, c.columns, data)
+    }
+
+    #[test]
+    fn test_transposes() {
+        let (m, k, n) = (9, 6, 7);
+
+        for &trans_a in &[Transpose::NoTrans, Transpose::Trans] {
+            for &trans_b in &[Transpose::NoTrans, Transpose::Trans] {
+                let a = if trans_a == Transpose::Trans { Matrix::<f64>::random(k, m) } else { Matrix::<f64>::random(m, k) };
+                let b = if trans_b == Transpose::Trans { Matrix::<f64>::random(n, k) } else { Matrix::<f64>::random(k, n) };
+                let mut c = Matrix::<f64>::random(m, n);
+
+                let compare = reference(trans_a, trans_b, 2., &a, &b, 0.5, &c);
+                gemm(trans_a, trans_b, 2., &a, &b, 0.5, &mut c);
+
+                assert_eq!(c, compare, "{:?} {:?}", trans_a, trans_b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_beta() {
+        let a = Matrix::<f64>::random(5, 3);
+        let b = Matrix::<f64>::random(3, 4);
+
+        // beta = 0 must not read C
+        let mut c = Matrix::new(5, 4, vec![std::f64::NAN; 20]);
+        gemm(Transpose::NoTrans, Transpose::NoTrans, 1., &a, &b, 0., &mut c);
+
+        let mut compare = Matrix::<f64>::zero(5, 4);
+        naive::mult(&a, &b, &mut compare);
+        assert_eq!(&c.data[..], &compare.data[..]);
+
+        // alpha = 0 only scales C
+        gemm(Transpose::NoTrans, Transpose::NoTrans, 0., &a, &b, 2., &mut c);
+        compare.data.iter_mut().for_each(|x| *x *= 2.);
+        assert_eq!(&c.data[..], &compare.data[..]);
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_operands() {
+        let a = Matrix::<f64>::random(6, 8);
+        let b = Matrix::<f64>::random(8, 5);
+        let bT = TransposedMatrix::from(b.clone());
+
+        let mut compare = Matrix::<f64>::zero(6, 5);
+        naive::mult(&a, &b, &mut compare);
+
+        let mut c = Matrix::zero(6, 5);
+        gemm(Transpose::NoTrans, Transpose::NoTrans, 1., &a, &bT, 0., &mut c);
+        assert_eq!(c, compare);
+
+        // the views of the upper left (3 x 4) and (4 x 5) blocks
+        let mut c = Matrix::<f64>::zero(8, 8);
+        gemm_view(Transpose::NoTrans, Transpose::NoTrans, 1., &a.submatrix(0, 0, 3, 4), &b.submatrix(0, 0, 4, 5),
+                  0., &mut c.submatrix_mut(1, 1, 3, 5));
+
+        let mut compare = Matrix::<f64>::zero(3, 5);
+        naive::mult(&a.submatrix(0, 0, 3, 4).to_matrix(), &b.submatrix(0, 0, 4, 5).to_matrix(), &mut compare);
+        assert_eq!(c.submatrix(1, 1, 3, 5).to_matrix(), compare);
+    }
+}
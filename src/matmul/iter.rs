@@ -5,7 +5,7 @@ use super::macros;
 use super::*;
 
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
+pub fn mult<T: Element>(A: &Matrix<T>, B: &TransposedMatrix<T>, C: &mut Matrix<T>) {
     assert_eq!(A.columns, B.rows);
     assert_eq!(A.rows, C.rows);
     assert_eq!(B.columns, C.columns);
@@ -18,8 +18,8 @@ pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
             .chunks(a_columns)
             .zip(B.data.chunks(a_columns).cycle().skip(i))
             .map(|(row, column)| {
-                row.iter().zip(column).map(|(a, b)| a * b).fold(
-                    0.0,
+                row.iter().zip(column).map(|(&a, &b)| a * b).fold(
+                    T::zero(),
                     |acc, item| {
                         acc + item
                     },
@@ -27,8 +27,10 @@ pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
             })
             .enumerate()
             .map(|(ind, result)| ((ind, (ind + i) % c_rows), result))
-            .for_each(|(index, result)| unsafe { *C.get_unchecked_mut(index) = result });
+            .for_each(|(index, result)| unsafe { *C.get_unchecked_mut(index) += result });
     }
 }
 
+kernel!(Iter, "iter", (Matrix, TransposedMatrix, Matrix), |m, k, n| m == n);
+
 generate_tests!(Matrix, TransposedMatrix, Matrix);
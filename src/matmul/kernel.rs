@@ -0,0 +1,358 @@
+
+use std::fmt;
+
+use super::matrix::*;
+use super::tuning::block_sizes;
+
+/// The storage layout of a kernel operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutKind {
+    /// `Matrix`
+    RowMajor,
+    /// `TransposedMatrix`
+    ColumnMajor,
+    /// `TileMatrix`
+    Tiled,
+    /// `MortonMatrix`
+    Morton,
+    /// `HilbertMatrix`
+    Hilbert,
+}
+
+impl LayoutKind {
+    /// Whether the layout is stored in blocks of the tile size of `Operands`
+    pub fn uses_tile(&self) -> bool {
+        match *self {
+            LayoutKind::Tiled | LayoutKind::Morton | LayoutKind::Hilbert => true,
+            LayoutKind::RowMajor | LayoutKind::ColumnMajor => false,
+        }
+    }
+}
+
+impl fmt::Display for LayoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayoutKind::RowMajor => write!(f, "row major"),
+            LayoutKind::ColumnMajor => write!(f, "column major"),
+            LayoutKind::Tiled => write!(f, "tiled"),
+            LayoutKind::Morton => write!(f, "morton"),
+            LayoutKind::Hilbert => write!(f, "hilbert"),
+        }
+    }
+}
+
+/// A matrix multiplication kernel
+///
+/// Every kernel computes C += A * B, where A is a (m x k), B a (k x n) and C a (m x n) matrix.
+/// The operand types determine the layouts the kernel works on.
+pub trait MatMul<A, B, C> {
+    /// The name used to select this kernel at runtime
+    fn name(&self) -> &'static str;
+
+    /// The layouts of A, B and C
+    fn layouts(&self) -> (LayoutKind, LayoutKind, LayoutKind);
+
+    /// Check the preconditions of this kernel for the given problem size
+    ///
+    /// Kernels assert these conditions in mult, so a kernel must not be called if this returns
+    /// false.
+    fn supports(&self, m: usize, k: usize, n: usize) -> bool;
+
+    /// Compute C += A * B
+    #[allow(non_snake_case)]
+    fn mult(&self, A: &A, B: &B, C: &mut C);
+
+    /// Compute C += A * B after checking the shapes and the preconditions of this kernel
+    #[allow(non_snake_case)]
+    fn try_mult(&self, A: &A, B: &B, C: &mut C) -> Result<(), MatrixError>
+        where A: Shape, B: Shape, C: Shape
+    {
+        let (m, k, n) = check_shapes(A.shape(), B.shape(), C.shape())?;
+
+        if !self.supports(m, k, n) {
+            return Err(MatrixError::UnsupportedDimension { m: m, k: k, n: n });
+        }
+
+        self.mult(A, B, C);
+        Ok(())
+    }
+}
+
+/// The type erased form of `MatMul` which is used by the registry
+///
+/// Each kernel is implemented for exactly one combination of layouts, so the operands are
+/// passed as `Operands` which provide all layouts.
+pub trait Kernel: Sync {
+    /// The name used to select this kernel at runtime
+    fn name(&self) -> &'static str;
+
+    /// The layouts of A, B and C
+    fn layouts(&self) -> (LayoutKind, LayoutKind, LayoutKind);
+
+    /// Check the preconditions of this kernel for the given problem size
+    fn supports(&self, m: usize, k: usize, n: usize) -> bool;
+
+    /// Compute C += A * B on the operands in the layouts of this kernel
+    fn run(&self, operands: &mut Operands);
+
+    /// Compute C += A * B on borrowed row major matrices
+    ///
+    /// Only the operands which the kernel needs in another layout are converted, with blocks of
+    /// size tile. Kernels registered with `kernel!` work on A, B and C directly if they use row
+    /// major matrices, the default copies the operands into `Operands`.
+    #[allow(non_snake_case)]
+    fn mult_row_major(&self, A: &Matrix, B: &Matrix, C: &mut Matrix, tile: usize) {
+        let mut operands = Operands::new(A.clone(), B.clone());
+        operands.set_tile(tile);
+        self.run(&mut operands);
+
+        let result = operands.result(self.layouts().2);
+        C.data.iter_mut().zip(result.data.iter()).for_each(|(c, r)| *c += *r);
+    }
+
+    /// Run the kernel if it supports the size of the operands
+    fn try_run(&self, operands: &mut Operands) -> Result<(), MatrixError> {
+        let (m, k, n) = (operands.m, operands.k, operands.n);
+
+        if !self.supports(m, k, n) {
+            return Err(MatrixError::UnsupportedDimension { m: m, k: k, n: n });
+        }
+
+        self.run(operands);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Kernel({})", self.name())
+    }
+}
+
+/// The type erased form of `MatMul` on views which is used by the registry
+///
+/// Only kernels on row major matrices can work on blocks of a bigger matrix without copying
+/// them, kernels which need another layout are not listed in `VIEW_KERNELS`.
+pub trait ViewKernel: Sync {
+    /// The name used to select this kernel at runtime
+    fn name(&self) -> &'static str;
+
+    /// Check the preconditions of this kernel for the given problem size
+    fn supports(&self, m: usize, k: usize, n: usize) -> bool;
+
+    /// Compute C += A * B on the views
+    #[allow(non_snake_case)]
+    fn mult_view(&self, A: MatrixView, B: MatrixView, C: &mut MatrixViewMut);
+
+    /// Compute C += A * B on the views after checking the shapes and the preconditions
+    #[allow(non_snake_case)]
+    fn try_mult_view(&self, A: MatrixView, B: MatrixView, C: &mut MatrixViewMut) -> Result<(), MatrixError> {
+        let (m, k, n) = check_shapes(A.shape(), B.shape(), C.shape())?;
+
+        if !self.supports(m, k, n) {
+            return Err(MatrixError::UnsupportedDimension { m: m, k: k, n: n });
+        }
+
+        self.mult_view(A, B, C);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ViewKernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ViewKernel({})", self.name())
+    }
+}
+
+/// The operands of a multiplication in every layout a kernel might need
+///
+/// The conversions are done in `prepare`, so they are not part of the runtime of a kernel.
+#[derive(Debug, Clone)]
+pub struct Operands {
+    pub m: usize,
+    pub k: usize,
+    pub n: usize,
+    a: Matrix,
+    b: Matrix,
+    c: Matrix,
+    b_transposed: Option<TransposedMatrix>,
+    a_tiled: Option<TileMatrix>,
+    b_tiled: Option<TileMatrix>,
+    c_tiled: Option<TileMatrix>,
+    a_morton: Option<MortonMatrix>,
+    b_morton: Option<MortonMatrix>,
+    c_morton: Option<MortonMatrix>,
+    a_hilbert: Option<HilbertMatrix>,
+    b_hilbert: Option<HilbertMatrix>,
+    c_hilbert: Option<HilbertMatrix>,
+    tile: usize,
+}
+
+impl Operands {
+    /// Create the operands for A * B, C is initialized to zero
+    #[allow(non_snake_case)]
+    pub fn try_new(A: Matrix, B: Matrix) -> Result<Self, MatrixError> {
+        if A.columns != B.rows {
+            return Err(MatrixError::ShapeMismatch { expected: (A.columns, B.columns), actual: (B.rows, B.columns) });
+        }
+
+        let c = Matrix::zero(A.rows, B.columns);
+
+        Ok(Self {
+            m: A.rows,
+            k: A.columns,
+            n: B.columns,
+            a: A,
+            b: B,
+            c: c,
+            b_transposed: None,
+            a_tiled: None,
+            b_tiled: None,
+            c_tiled: None,
+            a_morton: None,
+            b_morton: None,
+            c_morton: None,
+            a_hilbert: None,
+            b_hilbert: None,
+            c_hilbert: None,
+            tile: block_sizes::<f64>().tile,
+        })
+    }
+
+    /// Create the operands for A * B, panics if the shapes do not match
+    #[allow(non_snake_case)]
+    pub fn new(A: Matrix, B: Matrix) -> Self {
+        Self::try_new(A, B).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Set the block size of the blocked layouts, this drops already converted blocked operands
+    pub fn set_tile(&mut self, tile: usize) {
+        assert!(tile > 0, "the block size must be positive");
+
+        if tile != self.tile {
+            self.tile = tile;
+            self.a_tiled = None;
+            self.b_tiled = None;
+            self.c_tiled = None;
+            self.a_morton = None;
+            self.b_morton = None;
+            self.c_morton = None;
+            self.a_hilbert = None;
+            self.b_hilbert = None;
+            self.c_hilbert = None;
+        }
+    }
+
+    /// The block size of the blocked layouts
+    pub fn tile(&self) -> usize {
+        self.tile
+    }
+
+    /// Convert the operands into the given layouts
+    pub fn prepare(&mut self, (a, b, c): (LayoutKind, LayoutKind, LayoutKind)) {
+        if a == LayoutKind::Tiled && self.a_tiled.is_none() {
+            self.a_tiled = Some(TileMatrix::with_block_size(self.a.clone(), self.tile));
+        }
+        if b == LayoutKind::Tiled && self.b_tiled.is_none() {
+            self.b_tiled = Some(TileMatrix::with_block_size(self.b.clone(), self.tile));
+        }
+        if b == LayoutKind::ColumnMajor && self.b_transposed.is_none() {
+            self.b_transposed = Some(TransposedMatrix::from(self.b.clone()));
+        }
+        if c == LayoutKind::Tiled && self.c_tiled.is_none() {
+            self.c_tiled = Some(TileMatrix::with_block_size(self.c.clone(), self.tile));
+        }
+        if a == LayoutKind::Morton && self.a_morton.is_none() {
+            self.a_morton = Some(CurveMatrix::with_block_size(self.a.clone(), self.tile));
+        }
+        if b == LayoutKind::Morton && self.b_morton.is_none() {
+            self.b_morton = Some(CurveMatrix::with_block_size(self.b.clone(), self.tile));
+        }
+        if c == LayoutKind::Morton && self.c_morton.is_none() {
+            self.c_morton = Some(CurveMatrix::with_block_size(self.c.clone(), self.tile));
+        }
+        if a == LayoutKind::Hilbert && self.a_hilbert.is_none() {
+            self.a_hilbert = Some(CurveMatrix::with_block_size(self.a.clone(), self.tile));
+        }
+        if b == LayoutKind::Hilbert && self.b_hilbert.is_none() {
+            self.b_hilbert = Some(CurveMatrix::with_block_size(self.b.clone(), self.tile));
+        }
+        if c == LayoutKind::Hilbert && self.c_hilbert.is_none() {
+            self.c_hilbert = Some(CurveMatrix::with_block_size(self.c.clone(), self.tile));
+        }
+    }
+
+    /// Reset C to zero in all layouts
+    pub fn reset(&mut self) {
+        self.c.reset();
+        if let Some(ref mut c) = self.c_tiled {
+            c.reset();
+        }
+        if let Some(ref mut c) = self.c_morton {
+            c.reset();
+        }
+        if let Some(ref mut c) = self.c_hilbert {
+            c.reset();
+        }
+    }
+
+    /// The input A as row major matrix
+    pub fn a(&self) -> &Matrix {
+        &self.a
+    }
+
+    /// The input B as row major matrix
+    pub fn b(&self) -> &Matrix {
+        &self.b
+    }
+
+    /// The result C in the given layout converted to a row major matrix
+    pub fn result(&self, layout: LayoutKind) -> Matrix {
+        match layout {
+            LayoutKind::RowMajor => self.c.clone(),
+            LayoutKind::Tiled => Matrix::from(self.c_tiled.clone().expect("operands are not prepared")),
+            LayoutKind::Morton => Matrix::from(self.c_morton.clone().expect("operands are not prepared")),
+            LayoutKind::Hilbert => Matrix::from(self.c_hilbert.clone().expect("operands are not prepared")),
+            LayoutKind::ColumnMajor => panic!("no kernel writes a column major result"),
+        }
+    }
+}
+
+/// Access the operands in the layouts of a kernel
+pub trait Select<A, B, C> {
+    fn select(&mut self) -> (&A, &B, &mut C);
+}
+
+impl Select<Matrix, Matrix, Matrix> for Operands {
+    fn select(&mut self) -> (&Matrix, &Matrix, &mut Matrix) {
+        (&self.a, &self.b, &mut self.c)
+    }
+}
+
+impl Select<Matrix, TransposedMatrix, Matrix> for Operands {
+    fn select(&mut self) -> (&Matrix, &TransposedMatrix, &mut Matrix) {
+        self.prepare((LayoutKind::RowMajor, LayoutKind::ColumnMajor, LayoutKind::RowMajor));
+        (&self.a, self.b_transposed.as_ref().unwrap(), &mut self.c)
+    }
+}
+
+impl Select<TileMatrix, TileMatrix, TileMatrix> for Operands {
+    fn select(&mut self) -> (&TileMatrix, &TileMatrix, &mut TileMatrix) {
+        self.prepare((LayoutKind::Tiled, LayoutKind::Tiled, LayoutKind::Tiled));
+        (self.a_tiled.as_ref().unwrap(), self.b_tiled.as_ref().unwrap(), self.c_tiled.as_mut().unwrap())
+    }
+}
+
+impl Select<MortonMatrix, MortonMatrix, MortonMatrix> for Operands {
+    fn select(&mut self) -> (&MortonMatrix, &MortonMatrix, &mut MortonMatrix) {
+        self.prepare((LayoutKind::Morton, LayoutKind::Morton, LayoutKind::Morton));
+        (self.a_morton.as_ref().unwrap(), self.b_morton.as_ref().unwrap(), self.c_morton.as_mut().unwrap())
+    }
+}
+
+impl Select<HilbertMatrix, HilbertMatrix, HilbertMatrix> for Operands {
+    fn select(&mut self) -> (&HilbertMatrix, &HilbertMatrix, &mut HilbertMatrix) {
+        self.prepare((LayoutKind::Hilbert, LayoutKind::Hilbert, LayoutKind::Hilbert));
+        (self.a_hilbert.as_ref().unwrap(), self.b_hilbert.as_ref().unwrap(), self.c_hilbert.as_mut().unwrap())
+    }
+}
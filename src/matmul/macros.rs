@@ -8,9 +8,245 @@ macro_rules! new_matrix {
     }};
     (TransposedMatrix, $mat:expr) => {{
         TransposedMatrix::from($mat)
+    }};
+    (MortonMatrix, $mat:expr) => {{
+        MortonMatrix::from($mat)
+    }};
+    (HilbertMatrix, $mat:expr) => {{
+        HilbertMatrix::from($mat)
     }}
 }
 
+macro_rules! layout_of {
+    (Matrix) => { super::kernel::LayoutKind::RowMajor };
+    (TransposedMatrix) => { super::kernel::LayoutKind::ColumnMajor };
+    (TileMatrix) => { super::kernel::LayoutKind::Tiled };
+    (MortonMatrix) => { super::kernel::LayoutKind::Morton };
+    (HilbertMatrix) => { super::kernel::LayoutKind::Hilbert };
+}
+
+/// Convert a row major matrix into the given layout with blocks of the given size
+macro_rules! convert_layout {
+    (TransposedMatrix, $matrix:expr, $tile:expr) => {
+        TransposedMatrix::from($matrix.clone())
+    };
+    (TileMatrix, $matrix:expr, $tile:expr) => {
+        TileMatrix::with_block_size($matrix.clone(), $tile)
+    };
+    (MortonMatrix, $matrix:expr, $tile:expr) => {
+        MortonMatrix::with_block_size($matrix.clone(), $tile)
+    };
+    (HilbertMatrix, $matrix:expr, $tile:expr) => {
+        HilbertMatrix::with_block_size($matrix.clone(), $tile)
+    };
+}
+
+/// Borrow a row major input operand, other layouts are converted
+macro_rules! borrow_layout {
+    (Matrix, $matrix:expr, $tile:expr) => {
+        ::std::borrow::Cow::Borrowed($matrix)
+    };
+    ($mat_type:ident, $matrix:expr, $tile:expr) => {
+        ::std::borrow::Cow::Owned::<$mat_type>(convert_layout!($mat_type, $matrix, $tile))
+    };
+}
+
+/// Call mult with a row major C, other layouts of C are converted and copied back
+macro_rules! mult_into {
+    (Matrix, $a:expr, $b:expr, $c:expr, $tile:expr) => {
+        mult($a, $b, $c)
+    };
+    ($mat_type:ident, $a:expr, $b:expr, $c:expr, $tile:expr) => {{
+        let mut converted = convert_layout!($mat_type, $c, $tile);
+        mult($a, $b, &mut converted);
+
+        let result = Matrix::from(converted);
+        $c.data.iter_mut().zip(result.data.iter()).for_each(|(c, r)| *c = *r);
+    }};
+}
+
+/// Assert that two matrices have the same shape and all elements are close
+///
+/// The tolerance defaults to `Tolerance::default()`, a failure reports the worst element, see
+/// `worst_offender`. Further arguments are formatted into the message like in `assert!`.
+#[macro_export]
+macro_rules! assert_matrix_close {
+    ($left:expr, $right:expr) => {
+        assert_matrix_close!($left, $right, $crate::matmul::Tolerance::default());
+    };
+    ($left:expr, $right:expr, $tolerance:expr) => {
+        assert_matrix_close!($left, $right, $tolerance, "");
+    };
+    ($left:expr, $right:expr, $tolerance:expr, $($arg:tt)+) => {
+        match $crate::matmul::worst_offender(&$left, &$right, $tolerance) {
+            Ok(None) => {},
+            Ok(Some(offender)) => panic!("assertion failed: the matrices differ at {} {}", offender, format_args!($($arg)+)),
+            Err(error) => panic!("assertion failed: {} {}", error, format_args!($($arg)+)),
+        }
+    };
+}
+
+/// Register the `mult` function of the current module as kernel
+///
+/// This creates the unit struct `$kernel` which implements `MatMul` and `Kernel`, the closure
+/// like expression decides whether the kernel supports a (m x k) * (k x n) multiplication.
+/// `MatMul` is implemented for all element types, unless the kernel is restricted to f64, and the
+/// expression may use the element type `T`, e.g. `T::CHUNK_SIZE`. The registry always uses f64.
+#[macro_export]
+macro_rules! kernel {
+    ($kernel:ident, $name:expr, |$m:ident, $k:ident, $n:ident| $supports:expr) => {
+        kernel!($kernel, $name, (Matrix, Matrix, Matrix), |$m, $k, $n| $supports);
+    };
+    ($kernel:ident, $name:expr, ($mat_type_A:ident, $mat_type_B:ident, $mat_type_C:ident), |$m:ident, $k:ident, $n:ident| $supports:expr) => {
+        #[derive(Debug, Clone, Copy)]
+        pub struct $kernel;
+
+        #[allow(non_snake_case)]
+        impl<T: Element> super::kernel::MatMul<$mat_type_A<T>, $mat_type_B<T>, $mat_type_C<T>> for $kernel {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn layouts(&self) -> (super::kernel::LayoutKind, super::kernel::LayoutKind, super::kernel::LayoutKind) {
+                (layout_of!($mat_type_A), layout_of!($mat_type_B), layout_of!($mat_type_C))
+            }
+
+            #[allow(unused_variables)]
+            fn supports(&self, $m: usize, $k: usize, $n: usize) -> bool {
+                $supports
+            }
+
+            fn mult(&self, A: &$mat_type_A<T>, B: &$mat_type_B<T>, C: &mut $mat_type_C<T>) {
+                mult(A, B, C)
+            }
+        }
+
+        kernel!(@registry $kernel, ($mat_type_A, $mat_type_B, $mat_type_C));
+    };
+    ($kernel:ident, $name:expr, f64, ($mat_type_A:ident, $mat_type_B:ident, $mat_type_C:ident), |$m:ident, $k:ident, $n:ident| $supports:expr) => {
+        #[derive(Debug, Clone, Copy)]
+        pub struct $kernel;
+
+        #[allow(non_snake_case)]
+        impl super::kernel::MatMul<$mat_type_A, $mat_type_B, $mat_type_C> for $kernel {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn layouts(&self) -> (super::kernel::LayoutKind, super::kernel::LayoutKind, super::kernel::LayoutKind) {
+                (layout_of!($mat_type_A), layout_of!($mat_type_B), layout_of!($mat_type_C))
+            }
+
+            #[allow(unused_variables)]
+            fn supports(&self, $m: usize, $k: usize, $n: usize) -> bool {
+                $supports
+            }
+
+            fn mult(&self, A: &$mat_type_A, B: &$mat_type_B, C: &mut $mat_type_C) {
+                mult(A, B, C)
+            }
+        }
+
+        kernel!(@registry $kernel, ($mat_type_A, $mat_type_B, $mat_type_C));
+    };
+    (@registry $kernel:ident, ($mat_type_A:ident, $mat_type_B:ident, $mat_type_C:ident)) => {
+        impl super::kernel::Kernel for $kernel {
+            fn name(&self) -> &'static str {
+                super::kernel::MatMul::<$mat_type_A, $mat_type_B, $mat_type_C>::name(self)
+            }
+
+            fn layouts(&self) -> (super::kernel::LayoutKind, super::kernel::LayoutKind, super::kernel::LayoutKind) {
+                super::kernel::MatMul::<$mat_type_A, $mat_type_B, $mat_type_C>::layouts(self)
+            }
+
+            fn supports(&self, m: usize, k: usize, n: usize) -> bool {
+                super::kernel::MatMul::<$mat_type_A, $mat_type_B, $mat_type_C>::supports(self, m, k, n)
+            }
+
+            fn run(&self, operands: &mut super::kernel::Operands) {
+                let (a, b, c) = super::kernel::Select::<$mat_type_A, $mat_type_B, $mat_type_C>::select(operands);
+                mult(a, b, c);
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn mult_row_major(&self, A: &Matrix, B: &Matrix, C: &mut Matrix, tile: usize) {
+                let a = borrow_layout!($mat_type_A, A, tile);
+                let b = borrow_layout!($mat_type_B, B, tile);
+                mult_into!($mat_type_C, &*a, &*b, C, tile);
+            }
+        }
+    };
+}
+
+/// Implement `MatMul` on views for a kernel registered with `kernel!` on row major matrices
+///
+/// The current module has to provide `mult_view`, name, layouts and supports are the ones of the
+/// row major kernel. The f64 kernel also implements `ViewKernel`, so it can be listed in
+/// `VIEW_KERNELS`.
+#[macro_export]
+macro_rules! view_kernel {
+    ($kernel:ident) => {
+        #[allow(non_snake_case)]
+        impl<'a, T: Element> super::kernel::MatMul<MatrixView<'a, T>, MatrixView<'a, T>, MatrixViewMut<'a, T>> for $kernel {
+            fn name(&self) -> &'static str {
+                super::kernel::MatMul::<Matrix<T>, Matrix<T>, Matrix<T>>::name(self)
+            }
+
+            fn layouts(&self) -> (super::kernel::LayoutKind, super::kernel::LayoutKind, super::kernel::LayoutKind) {
+                super::kernel::MatMul::<Matrix<T>, Matrix<T>, Matrix<T>>::layouts(self)
+            }
+
+            fn supports(&self, m: usize, k: usize, n: usize) -> bool {
+                super::kernel::MatMul::<Matrix<T>, Matrix<T>, Matrix<T>>::supports(self, m, k, n)
+            }
+
+            fn mult(&self, A: &MatrixView<'a, T>, B: &MatrixView<'a, T>, C: &mut MatrixViewMut<'a, T>) {
+                mult_view(*A, *B, C)
+            }
+        }
+
+        view_kernel!(@registry $kernel);
+    };
+    ($kernel:ident, f64) => {
+        #[allow(non_snake_case)]
+        impl<'a> super::kernel::MatMul<MatrixView<'a>, MatrixView<'a>, MatrixViewMut<'a>> for $kernel {
+            fn name(&self) -> &'static str {
+                super::kernel::MatMul::<Matrix, Matrix, Matrix>::name(self)
+            }
+
+            fn layouts(&self) -> (super::kernel::LayoutKind, super::kernel::LayoutKind, super::kernel::LayoutKind) {
+                super::kernel::MatMul::<Matrix, Matrix, Matrix>::layouts(self)
+            }
+
+            fn supports(&self, m: usize, k: usize, n: usize) -> bool {
+                super::kernel::MatMul::<Matrix, Matrix, Matrix>::supports(self, m, k, n)
+            }
+
+            fn mult(&self, A: &MatrixView<'a>, B: &MatrixView<'a>, C: &mut MatrixViewMut<'a>) {
+                mult_view(*A, *B, C)
+            }
+        }
+
+        view_kernel!(@registry $kernel);
+    };
+    (@registry $kernel:ident) => {
+        impl super::kernel::ViewKernel for $kernel {
+            fn name(&self) -> &'static str {
+                super::kernel::MatMul::<Matrix, Matrix, Matrix>::name(self)
+            }
+
+            fn supports(&self, m: usize, k: usize, n: usize) -> bool {
+                super::kernel::MatMul::<Matrix, Matrix, Matrix>::supports(self, m, k, n)
+            }
+
+            #[allow(non_snake_case)]
+            fn mult_view(&self, A: MatrixView, B: MatrixView, C: &mut MatrixViewMut) {
+                mult_view(A, B, C)
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! generate_tests {
     () => {
@@ -27,19 +263,23 @@ macro_rules! generate_tests {
             use self::simd::x86::avx::f64x4;
 
 
+            use std;
+
             use super::mult;
-            use super::super::{ Matrix, TileMatrix, TransposedMatrix };
+            use super::super::{ Matrix, TileMatrix, TransposedMatrix, MortonMatrix, HilbertMatrix };
+
+            use measure::{bench, matmul_flop};
 
             extern crate test;
 
             #[test]
             fn test_mult_4x4_4x4() {
-                let a = new_matrix!($mat_type_A, Matrix::new_aligned(4, 4, vec![f64x4::splat(1.); 4]));
-                let b = new_matrix!($mat_type_B, Matrix::new_aligned(4, 4, vec![f64x4::splat(1.); 4]));
-                let mut c = new_matrix!($mat_type_C, Matrix::zero(4, 4));
+                let a = new_matrix!($mat_type_A, Matrix::<f64>::new_aligned(4, 4, vec![f64x4::splat(1.); 4]));
+                let b = new_matrix!($mat_type_B, Matrix::<f64>::new_aligned(4, 4, vec![f64x4::splat(1.); 4]));
+                let mut c = new_matrix!($mat_type_C, Matrix::<f64>::zero(4, 4));
 
                 mult(&a, &b, &mut c);
-                assert_eq!(c, new_matrix!($mat_type_C, Matrix::new_aligned(4, 4, vec![f64x4::splat(4.); 4])));
+                assert_eq!(c, new_matrix!($mat_type_C, Matrix::<f64>::new_aligned(4, 4, vec![f64x4::splat(4.); 4])));
             }
 /*
             #[test]
@@ -61,20 +301,17 @@ macro_rules! generate_tests {
             }*/
 
             #[bench]
-            fn bench_256x256_256x256(bencher: &mut test::Bencher) {
-                let n = 512;
-                let a = new_matrix!($mat_type_A, Matrix::random(n, n));
-                let b = new_matrix!($mat_type_B, Matrix::random(n, n));
-                let mut c = new_matrix!($mat_type_C, Matrix::zero(n, n));
-
-                bencher.iter(|| {
-                    mult(&a, &b, &mut c);
-                });
-
-                //println!();
-                //println!("FLOP: {}", 2*n*n*n);
-                //println!("ns/iter: {}", bencher.summary.unwrap().mean);
-                //println!("FLOP/s: {}", 2*n*n*n as f64 / bencher.summary.unwrap().mean * 1_000_000_000 as f64)
+            fn bench_256x256_256x256(_: &mut test::Bencher) {
+                let n = 256;
+                let a = new_matrix!($mat_type_A, Matrix::<f64>::random(n, n));
+                let b = new_matrix!($mat_type_B, Matrix::<f64>::random(n, n));
+                let mut c = new_matrix!($mat_type_C, Matrix::<f64>::zero(n, n));
+                let zero = c.clone();
+
+                // the kernels accumulate into C, so it is reset after every run
+                bench(&format!("{} {}x{}x{}", module_path!(), n, n, n), matmul_flop(n, n, n),
+                      (4 * n * n * std::mem::size_of::<f64>()) as u64,
+                      &mut c, |c| mult(&a, &b, c), |c| c.clone_from(&zero));
             }
         }
     }
@@ -0,0 +1,324 @@
+use std;
+
+use std::fmt;
+
+use std::ops::Index;
+
+use super::element::Complex;
+
+use super::error::MatrixError;
+
+use super::traits::Shape;
+
+/// The distance between two elements, used to compare the results of different kernels
+///
+/// Kernels which reorder the summation (simd, rayon, Strassen) do not produce bitwise identical
+/// results on floating point data, so they have to be compared with a Tolerance.
+pub trait ApproxEq: Copy {
+    /// The absolute difference |self - other|
+    fn abs_diff(self, other: Self) -> f64;
+
+    /// The absolute value, relative differences are taken with respect to it
+    fn magnitude(self) -> f64;
+
+    /// The number of representable values between self and other
+    fn ulps(self, other: Self) -> u64;
+
+    /// The machine epsilon, zero for exact types
+    const EPSILON: f64;
+}
+
+/// The distance of two lexicographically ordered integers as unsigned number
+fn distance(a: i64, b: i64) -> u64 {
+    if a >= b { a.wrapping_sub(b) as u64 } else { b.wrapping_sub(a) as u64 }
+}
+
+/// Map the bits of a float to an integer which is ordered like the float
+macro_rules! ordered {
+    ($value:expr, $bits:ty, $min:expr) => {{
+        let bits = unsafe { std::mem::transmute::<_, $bits>($value) };
+        if bits < 0 { $min - bits } else { bits }
+    }}
+}
+
+impl ApproxEq for f64 {
+    #[inline]
+    fn abs_diff(self, other: f64) -> f64 {
+        (self - other).abs()
+    }
+
+    #[inline]
+    fn magnitude(self) -> f64 {
+        self.abs()
+    }
+
+    fn ulps(self, other: f64) -> u64 {
+        if self.is_nan() || other.is_nan() {
+            return std::u64::MAX;
+        }
+        distance(ordered!(self, i64, std::i64::MIN), ordered!(other, i64, std::i64::MIN))
+    }
+
+    const EPSILON: f64 = std::f64::EPSILON;
+}
+
+impl ApproxEq for f32 {
+    #[inline]
+    fn abs_diff(self, other: f32) -> f64 {
+        (self as f64 - other as f64).abs()
+    }
+
+    #[inline]
+    fn magnitude(self) -> f64 {
+        self.abs() as f64
+    }
+
+    fn ulps(self, other: f32) -> u64 {
+        if self.is_nan() || other.is_nan() {
+            return std::u64::MAX;
+        }
+        distance(ordered!(self, i32, std::i32::MIN) as i64, ordered!(other, i32, std::i32::MIN) as i64)
+    }
+
+    const EPSILON: f64 = std::f32::EPSILON as f64;
+}
+
+macro_rules! impl_approx_eq_int {
+    ($($t:ty),*) => {
+        $(
+            impl ApproxEq for $t {
+                #[inline]
+                fn abs_diff(self, other: $t) -> f64 {
+                    self.ulps(other) as f64
+                }
+
+                #[inline]
+                fn magnitude(self) -> f64 {
+                    (self as f64).abs()
+                }
+
+                /// Every integer is representable, so this is the difference
+                #[inline]
+                fn ulps(self, other: $t) -> u64 {
+                    distance(self as i64, other as i64)
+                }
+
+                const EPSILON: f64 = 0.;
+            }
+        )*
+    }
+}
+
+impl_approx_eq_int!(i64, i32);
+
+impl<T: ApproxEq> ApproxEq for Complex<T> {
+    /// The larger difference of the real and imaginary parts
+    fn abs_diff(self, other: Self) -> f64 {
+        self.re.abs_diff(other.re).max(self.im.abs_diff(other.im))
+    }
+
+    fn magnitude(self) -> f64 {
+        self.re.magnitude().max(self.im.magnitude())
+    }
+
+    fn ulps(self, other: Self) -> u64 {
+        std::cmp::max(self.re.ulps(other.re), self.im.ulps(other.im))
+    }
+
+    const EPSILON: f64 = T::EPSILON;
+}
+
+/// The allowed difference of two elements
+///
+/// Two elements are close if any one of the three tolerances holds, so a relative tolerance
+/// should be combined with an absolute one if results close to zero are expected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// The allowed absolute difference
+    pub absolute: f64,
+    /// The allowed difference relative to the larger magnitude of both elements
+    pub relative: f64,
+    /// The allowed number of representable values between both elements
+    pub ulps: u64,
+}
+
+impl Tolerance {
+    /// Only identical elements are close
+    pub fn exact() -> Self {
+        Self::new(0., 0., 0)
+    }
+
+    pub fn new(absolute: f64, relative: f64, ulps: u64) -> Self {
+        Self {
+            absolute: absolute,
+            relative: relative,
+            ulps: ulps,
+        }
+    }
+
+    /// Check whether a and b are close
+    pub fn holds<T: ApproxEq>(&self, a: T, b: T) -> bool {
+        let difference = a.abs_diff(b);
+
+        difference <= self.absolute
+            || difference <= self.relative * a.magnitude().max(b.magnitude())
+            || a.ulps(b) <= self.ulps
+    }
+}
+
+impl Default for Tolerance {
+    /// A few rounding errors of f64, which also allows a few ulps in f32
+    fn default() -> Self {
+        Self::new(0., 1e-12, 4)
+    }
+}
+
+/// The element of two matrices which violates a tolerance the most
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Offender<T> {
+    pub row: usize,
+    pub column: usize,
+    pub left: T,
+    pub right: T,
+    /// The absolute difference of left and right
+    pub difference: f64,
+    /// The number of representable values between left and right
+    pub ulps: u64,
+}
+
+impl<T: fmt::Display> fmt::Display for Offender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}): {} != {}, difference {:e} ({} ulps)",
+               self.row, self.column, self.left, self.right, self.difference, self.ulps)
+    }
+}
+
+/// Find the element with the largest difference among the elements which are not close
+///
+/// Returns Ok(None) if all elements are close and an error if the shapes differ. Any matrix type
+/// which is indexed by (row, column) can be compared, also with a different layout.
+pub fn worst_offender<T, A, B>(a: &A, b: &B, tolerance: Tolerance) -> Result<Option<Offender<T>>, MatrixError>
+    where T: ApproxEq, A: Shape + Index<(usize, usize), Output = T>, B: Shape + Index<(usize, usize), Output = T>
+{
+    if a.shape() != b.shape() {
+        return Err(MatrixError::ShapeMismatch { expected: a.shape(), actual: b.shape() });
+    }
+
+    let (rows, columns) = a.shape();
+    let mut worst: Option<Offender<T>> = None;
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (left, right) = (a[(row, column)], b[(row, column)]);
+            if tolerance.holds(left, right) {
+                continue;
+            }
+
+            let difference = left.abs_diff(right);
+            // NaN is worse than any difference
+            let worse = match worst {
+                Some(ref offender) => !(difference <= offender.difference),
+                None => true,
+            };
+
+            if worse {
+                worst = Some(Offender {
+                    row: row,
+                    column: column,
+                    left: left,
+                    right: right,
+                    difference: difference,
+                    ulps: left.ulps(right),
+                });
+            }
+        }
+    }
+
+    Ok(worst)
+}
+
+/// Check whether a and b have the same shape and all elements are close
+pub fn approx_eq<T, A, B>(a: &A, b: &B, tolerance: Tolerance) -> bool
+    where T: ApproxEq, A: Shape + Index<(usize, usize), Output = T>, B: Shape + Index<(usize, usize), Output = T>
+{
+    match worst_offender(a, b, tolerance) {
+        Ok(None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::*;
+
+    #[test]
+    fn test_elements() {
+        assert_eq!(1f64.ulps(1.), 0);
+        assert_eq!(1f64.ulps(1. + std::f64::EPSILON), 1);
+        assert_eq!(1f32.ulps(1. + std::f32::EPSILON), 1);
+        assert_eq!(0f64.ulps(-0.), 0);
+        // the smallest positive and negative subnormals are two values apart
+        assert_eq!((-5e-324f64).ulps(5e-324), 2);
+        assert_eq!(std::f64::NAN.ulps(1.), std::u64::MAX);
+        assert_eq!(3i32.ulps(-4), 7);
+        assert_eq!(Complex::new(1., 2.).abs_diff(Complex::new(1.5, 1.)), 1.);
+
+        assert!(Tolerance::new(0.1, 0., 0).holds(1., 1.05));
+        assert!(!Tolerance::new(0.01, 0., 0).holds(1., 1.05));
+        assert!(Tolerance::new(0., 0.1, 0).holds(100., 105.));
+        assert!(Tolerance::new(0., 0., 2).holds(1., 1. + 2. * std::f64::EPSILON));
+        assert!(!Tolerance::default().holds(std::f64::NAN, std::f64::NAN));
+        assert!(Tolerance::exact().holds(2, 2));
+        assert!(!Tolerance::exact().holds(2, 3));
+    }
+
+    #[test]
+    fn test_matrices() {
+        let a = Matrix::<f64>::new(2, 3, vec![1., 2., 3., 4., 5., 6.]);
+        let mut b = a.clone();
+
+        assert_eq!(worst_offender(&a, &b, Tolerance::exact()), Ok(None));
+
+        b[(0, 1)] += 1e-14;
+        b[(1, 2)] += 0.5;
+        b[(1, 0)] += 0.25;
+        assert!(!approx_eq(&a, &b, Tolerance::default()));
+        assert!(approx_eq(&a, &b, Tolerance::new(0.5, 0., 0)));
+
+        let offender = worst_offender(&a, &b, Tolerance::default()).unwrap().unwrap();
+        assert_eq!((offender.row, offender.column, offender.left, offender.right), (1, 2, 6., 6.5));
+        assert_eq!(offender.difference, 0.5);
+
+        // the tolerance of 1e-14 is smaller than a relative error of 1e-12
+        b[(1, 2)] = 6.;
+        b[(1, 0)] = 4.;
+        assert!(approx_eq(&a, &b, Tolerance::default()));
+        assert!(!approx_eq(&a, &b, Tolerance::exact()));
+
+        // different layouts are compared element by element
+        assert!(approx_eq(&a, &TransposedMatrix::from(a.clone()), Tolerance::exact()));
+        assert_eq!(worst_offender(&a, &a.clone().transpose(), Tolerance::exact()),
+                   Err(MatrixError::ShapeMismatch { expected: (2, 3), actual: (3, 2) }));
+    }
+
+    #[test]
+    fn test_macro() {
+        let a = Matrix::<f64>::random(5, 5);
+        let b = &a * (1. + std::f64::EPSILON);
+
+        assert_matrix_close!(a, b);
+        assert_matrix_close!(a, b, Tolerance::new(0., 0., 1));
+        assert_matrix_close!(a, b, Tolerance::new(1e-10, 0., 0), "5x5 matrices");
+    }
+
+    #[test]
+    #[should_panic(expected = "(1, 1): 1 != 2")]
+    fn test_macro_panics() {
+        let a = Matrix::<f64>::new(2, 2, vec![0., 0., 0., 1.]);
+        let b = Matrix::<f64>::new(2, 2, vec![0., 0., 0., 2.]);
+
+        assert_matrix_close!(a, b);
+    }
+}
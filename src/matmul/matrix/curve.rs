@@ -0,0 +1,342 @@
+
+use std;
+
+use std::fmt;
+
+use std::marker::PhantomData;
+
+use std::ops::{Index, IndexMut};
+
+use std::cmp::{PartialEq, Eq};
+
+use std::convert::From;
+
+use super::element::Element;
+
+use super::standard::Matrix;
+
+use super::traits::Shape;
+
+use aligned::AlignedBuffer;
+
+use super::super::tuning::block_sizes;
+
+/// The order in which the blocks of a CurveMatrix are stored
+///
+/// The curves visit every quadrant of the block grid completely before moving on to the next
+/// one, on every level. So every quadrant is stored contiguously, which recursive kernels exploit.
+pub trait Curve: fmt::Debug + Clone + Copy + PartialEq + Send + Sync + 'static {
+    /// The position of block (row, column) on the curve through a grid of 2^order x 2^order blocks
+    fn index(order: u32, row: usize, column: usize) -> usize;
+}
+
+/// The Z curve, the quadrants are visited in the order 11, 12, 21, 22 on every level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Morton;
+
+impl Curve for Morton {
+    fn index(order: u32, row: usize, column: usize) -> usize {
+        // interleave the bits, the row bit is the higher one of every pair
+        (0..order).fold(0, |index, bit| {
+            index | ((row >> bit) & 1) << (2 * bit + 1) | ((column >> bit) & 1) << (2 * bit)
+        })
+    }
+}
+
+/// The Hilbert curve, consecutive blocks are always neighbours in the grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hilbert;
+
+impl Curve for Hilbert {
+    fn index(order: u32, row: usize, column: usize) -> usize {
+        let n = 1 << order;
+        let (mut x, mut y) = (column, row);
+        let mut index = 0;
+
+        let mut s = n / 2;
+        while s > 0 {
+            let rx = if x & s > 0 { 1 } else { 0 };
+            let ry = if y & s > 0 { 1 } else { 0 };
+            index += s * s * ((3 * rx) ^ ry);
+
+            // rotate the quadrant, so the sub curve starts and ends next to its neighbours
+            if ry == 0 {
+                if rx == 1 {
+                    x = n - 1 - x;
+                    y = n - 1 - y;
+                }
+                std::mem::swap(&mut x, &mut y);
+            }
+
+            s /= 2;
+        }
+
+        index
+    }
+}
+
+/// A rust Matrix stored in square blocks along a space filling curve
+///
+/// # Properties
+/// ## Members
+/// This matrix consists of the following members:
+///
+/// rows:       usize       The number of rows
+/// columns:    usize       The number of columns
+/// data:       AlignedBuffer<T>    The blocks in the order of the curve O, every block is row major
+///
+/// The block grid is padded to 2^order x 2^order blocks, the padding is zero. With O = Morton
+/// this is the Z-order layout, see also the MortonMatrix and HilbertMatrix aliases.
+///
+/// ## Methods
+/// This struct only implements methods to create, alter and index itself. Operations have to be
+/// implemented somewhere else.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CurveMatrix<T = f64, O = Morton> {
+    pub rows: usize,
+    pub columns: usize,
+    pub data: AlignedBuffer<T>,
+    block_size: usize,
+    order: u32,
+    curve: PhantomData<O>,
+}
+
+/// A matrix in Z-order
+pub type MortonMatrix<T = f64> = CurveMatrix<T, Morton>;
+
+/// A matrix in Hilbert order
+pub type HilbertMatrix<T = f64> = CurveMatrix<T, Hilbert>;
+
+impl<T: Element, O: Curve> CurveMatrix<T, O> {
+    /// Create a zero matrix with square blocks of size bs
+    ///
+    /// Matrices smaller than a block are stored in a single block of their size.
+    pub fn zero_with_block_size(rows: usize, columns: usize, bs: usize) -> Self {
+        assert!(bs > 0, "the block size must be positive");
+
+        let bs = std::cmp::max(1, std::cmp::min(bs, std::cmp::max(rows, columns)));
+        let blocks = (std::cmp::max(rows, columns) + bs - 1) / bs;
+        let order = blocks.next_power_of_two().trailing_zeros();
+
+        Self {
+            rows: rows,
+            columns: columns,
+            data: AlignedBuffer::from_elem(T::zero(), (1 << (2 * order)) * bs * bs),
+            block_size: bs,
+            order: order,
+            curve: PhantomData,
+        }
+    }
+
+    /// Copy matrix into square blocks of size bs
+    pub fn with_block_size(matrix: Matrix<T>, bs: usize) -> Self {
+        let mut ret = Self::zero_with_block_size(matrix.rows, matrix.columns, bs);
+
+        for i in 0..matrix.rows {
+            for j in 0..matrix.columns {
+                ret[(i, j)] = matrix[(i, j)];
+            }
+        }
+
+        ret
+    }
+
+    /// Create a zero matrix with blocks of tuning::block_sizes::<T>().tile
+    pub fn zero(rows: usize, columns: usize) -> Self {
+        Self::zero_with_block_size(rows, columns, block_sizes::<T>().tile)
+    }
+
+    /// Create a "random" matrix, the content is the one of Matrix::random
+    pub fn random(rows: usize, columns: usize) -> Self {
+        Self::from(Matrix::random(rows, columns))
+    }
+
+    /// Reset all entries to zero
+    pub fn reset(&mut self) {
+        for v in &mut self.data {
+            *v = T::zero();
+        }
+    }
+
+    /// The number of rows and columns of a block
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The block grid has 2^order x 2^order blocks
+    pub fn order(&self) -> u32 {
+        self.order
+    }
+
+    /// The elements of block (row, column), row major
+    pub fn block(&self, row: usize, column: usize) -> &[T] {
+        let len = self.block_size * self.block_size;
+        let start = O::index(self.order, row, column) * len;
+        &self.data[start..start + len]
+    }
+
+    /// The elements of block (row, column), row major
+    pub fn block_mut(&mut self, row: usize, column: usize) -> &mut [T] {
+        let len = self.block_size * self.block_size;
+        let start = O::index(self.order, row, column) * len;
+        &mut self.data[start..start + len]
+    }
+
+    /// The position of element (row, column) in data
+    #[inline]
+    fn offset(&self, row: usize, column: usize) -> usize {
+        let bs = self.block_size;
+        O::index(self.order, row / bs, column / bs) * bs * bs + (row % bs) * bs + column % bs
+    }
+}
+
+impl<T: Element, O: Curve> Eq for CurveMatrix<T, O> {}
+
+impl<T: Element, O: Curve> Shape for CurveMatrix<T, O> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+}
+
+impl<T: Element, O: Curve> Index<(usize, usize)> for CurveMatrix<T, O> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        assert!(row < self.rows && column < self.columns, "index ({}, {}) out of bounds", row, column);
+        &self.data[self.offset(row, column)]
+    }
+}
+
+impl<T: Element, O: Curve> IndexMut<(usize, usize)> for CurveMatrix<T, O> {
+    #[inline]
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        assert!(row < self.rows && column < self.columns, "index ({}, {}) out of bounds", row, column);
+        let offset = self.offset(row, column);
+        &mut self.data[offset]
+    }
+}
+
+impl<T: Element, O: Curve> From<Matrix<T>> for CurveMatrix<T, O> {
+    /// Split the matrix into square blocks of tuning::block_sizes::<T>().tile
+    fn from(matrix: Matrix<T>) -> Self {
+        Self::with_block_size(matrix, block_sizes::<T>().tile)
+    }
+}
+
+impl<T: Element, O: Curve> From<CurveMatrix<T, O>> for Matrix<T> {
+    fn from(curve: CurveMatrix<T, O>) -> Self {
+        let mut matrix = Matrix::zero(curve.rows, curve.columns);
+
+        for i in 0..curve.rows {
+            for j in 0..curve.columns {
+                matrix[(i, j)] = curve[(i, j)];
+            }
+        }
+
+        matrix
+    }
+}
+
+impl<T: Element, O: Curve> fmt::Display for CurveMatrix<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in 0..self.rows {
+            for j in 0..self.columns {
+                write!(f, "{:>8}, ", self[(i, j)])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The curve visits every block once and every quadrant contiguously
+    fn check_curve<O: Curve>(order: u32) {
+        let n = 1 << order;
+        let mut visited = vec![None; n * n];
+
+        for row in 0..n {
+            for column in 0..n {
+                let index = O::index(order, row, column);
+                assert!(visited[index].is_none(), "block {} is visited twice", index);
+                visited[index] = Some((row, column));
+            }
+        }
+
+        // the quadrants of size 2^level start at multiples of their length
+        for level in 0..order {
+            let size = 1 << level;
+            for row in (0..n).step_by(size) {
+                for column in (0..n).step_by(size) {
+                    let first = O::index(order, row, column) / (size * size);
+                    for i in 0..size {
+                        for j in 0..size {
+                            assert_eq!(O::index(order, row + i, column + j) / (size * size), first);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_curves() {
+        for order in 0..5 {
+            check_curve::<Morton>(order);
+            check_curve::<Hilbert>(order);
+        }
+
+        assert_eq!((0..4).map(|q| Morton::index(1, q / 2, q % 2)).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        assert_eq!(Morton::index(2, 2, 1), 9);
+
+        // consecutive blocks on the Hilbert curve are neighbours
+        let order = 4;
+        let n = 1 << order;
+        let mut position = vec![(0, 0); n * n];
+        for row in 0..n {
+            for column in 0..n {
+                position[Hilbert::index(order, row, column)] = (row as isize, column as isize);
+            }
+        }
+        for pair in position.windows(2) {
+            let ((r1, c1), (r2, c2)) = (pair[0], pair[1]);
+            assert_eq!((r1 - r2).abs() + (c1 - c2).abs(), 1);
+        }
+    }
+
+    #[test]
+    fn test_conversion() {
+        for &(rows, columns, bs) in &[(4, 4, 2), (13, 7, 4), (7, 13, 4), (3, 3, 16), (33, 65, 8)] {
+            let matrix = Matrix::<f64>::random(rows, columns);
+
+            let morton = MortonMatrix::with_block_size(matrix.clone(), bs);
+            let hilbert = HilbertMatrix::with_block_size(matrix.clone(), bs);
+
+            for i in 0..rows {
+                for j in 0..columns {
+                    assert_eq!(morton[(i, j)], matrix[(i, j)]);
+                    assert_eq!(hilbert[(i, j)], matrix[(i, j)]);
+                }
+            }
+
+            assert_eq!(Matrix::from(morton), matrix);
+            assert_eq!(Matrix::from(hilbert), matrix);
+        }
+
+        let morton = MortonMatrix::<f64>::zero_with_block_size(13, 7, 4);
+        assert_eq!(morton.block_size(), 4);
+        assert_eq!(morton.order(), 2);
+        assert_eq!(morton.data.len(), 16 * 16);
+
+        // a 2 x 2 matrix in blocks of 1 is stored in Z-order
+        let z = MortonMatrix::with_block_size(Matrix::new(2, 2, vec![1., 2., 3., 4.]), 1);
+        assert_eq!(z.data, vec![1., 2., 3., 4.]);
+        let z = MortonMatrix::with_block_size(Matrix::new(4, 1, vec![1., 2., 3., 4.]), 1);
+        assert_eq!(z.block(2, 0), &[3.]);
+        assert_eq!(z.data.iter().filter(|&&x| x != 0.).count(), 4);
+    }
+}
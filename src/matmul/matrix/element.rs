@@ -0,0 +1,214 @@
+
+use std;
+
+use std::fmt;
+
+use std::ops::{Add, Sub, Mul, Neg, AddAssign};
+
+use triades::{SimdCapable, SimdItem};
+
+use super::approx::ApproxEq;
+
+/// The element type of a matrix
+///
+/// Every element type has a simd representation via SimdCapable, which is used by the vectorized
+/// kernels. Types without a native simd type, like Complex, use themselves with a chunk size of 1.
+pub trait Element: SimdCapable + ApproxEq + Copy + PartialEq + fmt::Debug + fmt::Display + Send + Sync + 'static
+    + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Neg<Output=Self> + AddAssign
+{
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    /// Convert a small integer, it is used to fill "random" matrices
+    fn from_usize(value: usize) -> Self;
+
+    /// Convert a float, integer types round it down, see generate
+    ///
+    /// Rounding down maps a uniform float in [low, high) with integer bounds to a uniform integer
+    /// in low..high.
+    fn from_f64(value: f64) -> Self;
+
+    /// Random elements are drawn from [-RANDOM_BOUND, RANDOM_BOUND)
+    ///
+    /// This is 1 for floats. Integer types use small integers instead, so random matrices are
+    /// not almost all zero and their products are exact.
+    const RANDOM_BOUND: f64;
+}
+
+macro_rules! impl_element {
+    ($bound:expr, $convert:expr, $($t:ty),*) => {
+        $(
+            impl Element for $t {
+                #[inline]
+                fn zero() -> Self {
+                    0 as $t
+                }
+
+                #[inline]
+                fn one() -> Self {
+                    1 as $t
+                }
+
+                #[inline]
+                fn from_usize(value: usize) -> Self {
+                    value as $t
+                }
+
+                #[inline]
+                fn from_f64(value: f64) -> Self {
+                    ($convert)(value) as $t
+                }
+
+                const RANDOM_BOUND: f64 = $bound;
+            }
+        )*
+    }
+}
+
+impl_element!(1., |value: f64| value, f64, f32);
+impl_element!(8., |value: f64| value.floor(), i64, i32);
+
+/// A complex number
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex<T> {
+    pub re: T,
+    pub im: T,
+}
+
+impl<T> Complex<T> {
+    pub fn new(re: T, im: T) -> Self {
+        Self {
+            re: re,
+            im: im,
+        }
+    }
+}
+
+impl<T: Add<Output=T>> Add for Complex<T> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl<T: Sub<Output=T>> Sub for Complex<T> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl<T: Copy + Add<Output=T> + Sub<Output=T> + Mul<Output=T>> Mul for Complex<T> {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, other: Self) -> Self {
+        Self::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+}
+
+impl<T: Neg<Output=T>> Neg for Complex<T> {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self {
+        Self::new(-self.re, -self.im)
+    }
+}
+
+impl<T: Copy + Add<Output=T>> AddAssign for Complex<T> {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        self.re = self.re + other.re;
+        self.im = self.im + other.im;
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Complex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // format the number as a whole, so width and alignment apply to both parts
+        let s = format!("{}+{}i", self.re, self.im);
+        f.pad(&s)
+    }
+}
+
+impl<T: Copy> SimdItem for Complex<T> {
+    type Elem = Complex<T>;
+
+    fn store (self, array: &mut [Complex<T>], idx: usize) {
+        array[idx] = self;
+    }
+}
+
+impl<T> SimdCapable for Complex<T>
+    where T: Copy + fmt::Debug + Send + Sync + Add<Output=T> + Sub<Output=T> + Mul<Output=T>
+{
+    type SimdType = Complex<T>;
+    type ArrayType = [Complex<T>; 1];
+    const CHUNK_SIZE: usize = 1;
+
+    fn load (array: &[Self], idx: usize) -> Self::SimdType {
+        array[idx]
+    }
+
+    fn splat (value: Self) -> Self::SimdType {
+        value
+    }
+}
+
+impl<T> Element for Complex<T>
+    where T: Element
+{
+    #[inline]
+    fn zero() -> Self {
+        Self::new(T::zero(), T::zero())
+    }
+
+    #[inline]
+    fn one() -> Self {
+        Self::new(T::one(), T::zero())
+    }
+
+    #[inline]
+    fn from_usize(value: usize) -> Self {
+        Self::new(T::from_usize(value), T::zero())
+    }
+
+    #[inline]
+    fn from_f64(value: f64) -> Self {
+        Self::new(T::from_f64(value), T::zero())
+    }
+
+    const RANDOM_BOUND: f64 = T::RANDOM_BOUND;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complex() {
+        let a = Complex::new(1., 2.);
+        let b = Complex::new(3., -1.);
+
+        assert_eq!(a + b, Complex::new(4., 1.));
+        assert_eq!(a - b, Complex::new(-2., 3.));
+        assert_eq!(a * b, Complex::new(5., 5.));
+        assert_eq!(-a, Complex::new(-1., -2.));
+        assert_eq!(format!("{:>8}", a), "    1+2i");
+        assert_eq!(Complex::<f64>::one() * a, a);
+    }
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(f64::from_f64(-0.5), -0.5);
+        assert_eq!(i32::from_f64(-0.5), -1);
+        assert_eq!(i64::from_f64(7.99), 7);
+        assert_eq!(Complex::<i32>::from_f64(-2.5), Complex::new(-3, 0));
+    }
+}
@@ -0,0 +1,95 @@
+use std::error::Error;
+
+use std::fmt;
+
+/// The reasons why a matrix can not be created or multiplied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixError {
+    /// An operand has the shape actual instead of expected, both are (rows, columns)
+    ShapeMismatch { expected: (usize, usize), actual: (usize, usize) },
+    /// The data of a matrix is not aligned to the simd type
+    Misaligned,
+    /// The data contains actual instead of expected elements
+    DataLengthMismatch { expected: usize, actual: usize },
+    /// A kernel does not support a (m x k) * (k x n) multiplication
+    UnsupportedDimension { m: usize, k: usize, n: usize },
+    /// A kernel needs another layout than row major and can not work on views
+    UnsupportedView,
+    /// A tiled matrix needs tiles with at least one row and column
+    InvalidTile,
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatrixError::ShapeMismatch { expected, actual } =>
+                write!(f, "expected a {}x{} matrix, got {}x{}", expected.0, expected.1, actual.0, actual.1),
+            MatrixError::Misaligned => write!(f, "the matrix is not aligned to the simd type"),
+            MatrixError::DataLengthMismatch { expected, actual } =>
+                write!(f, "expected {} elements, got {}", expected, actual),
+            MatrixError::UnsupportedDimension { m, k, n } =>
+                write!(f, "the kernel does not support a {}x{}x{} multiplication", m, k, n),
+            MatrixError::UnsupportedView => write!(f, "the kernel does not work on views"),
+            MatrixError::InvalidTile => write!(f, "the tile size must be at least 1"),
+        }
+    }
+}
+
+impl Error for MatrixError {
+    fn description(&self) -> &str {
+        match *self {
+            MatrixError::ShapeMismatch { .. } => "shape mismatch",
+            MatrixError::Misaligned => "misaligned matrix",
+            MatrixError::DataLengthMismatch { .. } => "data length mismatch",
+            MatrixError::UnsupportedDimension { .. } => "unsupported dimension",
+            MatrixError::UnsupportedView => "unsupported view",
+            MatrixError::InvalidTile => "invalid tile size",
+        }
+    }
+}
+
+/// Check that C = A * B is defined for operands of the given (rows, columns) and return (m, k, n)
+pub fn check_shapes(a: (usize, usize), b: (usize, usize), c: (usize, usize)) -> Result<(usize, usize, usize), MatrixError> {
+    let (m, k, n) = (a.0, a.1, b.1);
+
+    if b.0 != k {
+        return Err(MatrixError::ShapeMismatch { expected: (k, n), actual: b });
+    }
+    if c != (m, n) {
+        return Err(MatrixError::ShapeMismatch { expected: (m, n), actual: c });
+    }
+
+    Ok((m, k, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::*;
+
+    #[test]
+    fn test_check_shapes() {
+        assert_eq!(check_shapes((2, 3), (3, 4), (2, 4)), Ok((2, 3, 4)));
+        assert_eq!(check_shapes((2, 3), (4, 4), (2, 4)), Err(MatrixError::ShapeMismatch { expected: (3, 4), actual: (4, 4) }));
+        assert_eq!(check_shapes((2, 3), (3, 4), (4, 2)), Err(MatrixError::ShapeMismatch { expected: (2, 4), actual: (4, 2) }));
+
+        let error = MatrixError::ShapeMismatch { expected: (3, 4), actual: (4, 4) };
+        assert_eq!(format!("{}", error), "expected a 3x4 matrix, got 4x4");
+    }
+
+    #[test]
+    fn test_construction() {
+        assert!(Matrix::<f64>::try_new(2, 2, vec![1., 2., 3., 4.]).is_ok());
+        assert_eq!(Matrix::<f64>::try_new(2, 3, vec![1., 2.]), Err(MatrixError::DataLengthMismatch { expected: 6, actual: 2 }));
+
+        let blocks = vec![Matrix::<f64>::zero(2, 2), Matrix::zero(2, 3)];
+        assert_eq!(TileMatrix::try_new(2, 1, blocks), Err(MatrixError::ShapeMismatch { expected: (2, 2), actual: (2, 3) }));
+        assert_eq!(TileMatrix::<f64>::try_new(2, 2, vec![Matrix::zero(2, 2)]), Err(MatrixError::DataLengthMismatch { expected: 4, actual: 1 }));
+        assert!(TileMatrix::<f64>::try_new(0, 0, vec![]).is_err());
+        assert!(TileMatrix::try_new(1, 2, vec![Matrix::<f64>::zero(2, 2); 2]).is_ok());
+
+        assert_eq!(SimdMatrix::try_from(Matrix::<f64>::zero(3, 3)).err(), Some(MatrixError::Misaligned));
+        assert!(SimdMatrix::try_from(Matrix::<f64>::zero(3, 4)).is_ok());
+    }
+}
@@ -0,0 +1,871 @@
+//! Reading and writing matrices in Matrix Market, NumPy and raw binary files
+//!
+//! The formats are exchanged with other tools, so all of them are written in row major order
+//! (column major for Matrix Market arrays, as the format requires) regardless of the layout of the
+//! matrix. Every matrix type which implements Storable can be written and read.
+//!
+//! # Raw binary
+//! The raw format is a 24 byte header followed by the elements in row major order, everything
+//! is little endian:
+//!
+//! ```text
+//! bytes 0..4      b"RMAT"
+//! byte 4          the version, 1
+//! byte 5          the element type, see FileElement::CODE
+//! bytes 6..8      reserved, zero
+//! bytes 8..16     rows as u64
+//! bytes 16..24    columns as u64
+//! ```
+
+use std;
+
+use std::fs::File;
+
+use std::io::{Read, Write, BufReader};
+
+use std::path::Path;
+
+use std::str::FromStr;
+
+use super::element::Element;
+
+use super::layout::{Layout, RowMajor, ColMajor};
+
+use super::standard::Matrix;
+
+use super::tiled::TileMatrix;
+
+use super::traits::Shape;
+
+/// The magic string of .npy files
+const NPY_MAGIC: &'static [u8] = b"\x93NUMPY";
+
+/// The magic string of raw files
+const RAW_MAGIC: &'static [u8] = b"RMAT";
+
+/// The size of the header of raw files
+const RAW_HEADER: usize = 24;
+
+/// The signatures of the zip records used by .npz files
+const ZIP_LOCAL: u64 = 0x04034b50;
+const ZIP_CENTRAL: u64 = 0x02014b50;
+const ZIP_END: u64 = 0x06054b50;
+
+/// The most elements of a matrix read from a Matrix Market coordinate file, 2 GiB of f64
+///
+/// Unlike arrays the entries of a coordinate file do not bound the size of the dense matrix they
+/// are read into, so without a limit a header of a few bytes could request any amount of memory.
+const MTX_MAX_ELEMENTS: usize = 1 << 28;
+
+/// The zip date 1980-01-01, there is no clock involved, so archives are reproducible
+const ZIP_DATE: u64 = 0x21;
+
+/// Append the size lowest bytes of value in little endian order
+fn push_le(out: &mut Vec<u8>, mut value: u64, size: usize) {
+    for _ in 0..size {
+        out.push(value as u8);
+        value >>= 8;
+    }
+}
+
+/// Read a little endian number
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64)
+}
+
+/// Element types which can be stored in files
+pub trait FileElement: Element + FromStr {
+    /// The NumPy dtype
+    const DESCR: &'static str;
+    /// The Matrix Market field
+    const FIELD: &'static str;
+    /// The element type of raw files
+    const CODE: u8;
+
+    /// Append the little endian representation
+    fn write_le(self, out: &mut Vec<u8>);
+
+    /// Read the little endian representation from the first std::mem::size_of::<Self>() bytes
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_file_element {
+    ($t:ty, $bits:ty, $descr:expr, $field:expr, $code:expr, float) => {
+        impl FileElement for $t {
+            const DESCR: &'static str = $descr;
+            const FIELD: &'static str = $field;
+            const CODE: u8 = $code;
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                let bits = unsafe { std::mem::transmute::<$t, $bits>(self) };
+                push_le(out, bits as u64, std::mem::size_of::<$t>());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                let bits = read_le(&bytes[..std::mem::size_of::<$t>()]) as $bits;
+                unsafe { std::mem::transmute::<$bits, $t>(bits) }
+            }
+        }
+    };
+    ($t:ty, $bits:ty, $descr:expr, $field:expr, $code:expr, integer) => {
+        impl FileElement for $t {
+            const DESCR: &'static str = $descr;
+            const FIELD: &'static str = $field;
+            const CODE: u8 = $code;
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                push_le(out, self as $bits as u64, std::mem::size_of::<$t>());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                read_le(&bytes[..std::mem::size_of::<$t>()]) as $bits as $t
+            }
+        }
+    };
+}
+
+impl_file_element!(f64, u64, "<f8", "real", 1, float);
+impl_file_element!(f32, u32, "<f4", "real", 2, float);
+impl_file_element!(i64, u64, "<i8", "integer", 3, integer);
+impl_file_element!(i32, u32, "<i4", "integer", 4, integer);
+
+/// Matrix types which can be written to and read from files
+pub trait Storable<T: FileElement>: Shape + Sized {
+    /// Element (row, column)
+    fn get(&self, row: usize, column: usize) -> T;
+
+    /// Convert a matrix which was read from a file
+    fn from_matrix(matrix: Matrix<T>) -> Self;
+}
+
+impl<T: FileElement, L: Layout> Storable<T> for Matrix<T, L> {
+    fn get(&self, row: usize, column: usize) -> T {
+        self[(row, column)]
+    }
+
+    fn from_matrix(matrix: Matrix<T>) -> Self {
+        matrix.to_layout()
+    }
+}
+
+impl<T: FileElement> Storable<T> for TileMatrix<T> {
+    fn get(&self, row: usize, column: usize) -> T {
+        let (rows, columns) = (self.data[0].rows, self.data[0].columns);
+        self[(row / rows, column / columns)][(row % rows, column % columns)]
+    }
+
+    /// Split the matrix into blocks of tuning::block_sizes::<T>().tile
+    fn from_matrix(matrix: Matrix<T>) -> Self {
+        TileMatrix::from(matrix)
+    }
+}
+
+/// Parse the next whitespace separated token
+fn next<'a, F: FromStr, I: Iterator<Item = &'a str>>(tokens: &mut I, what: &str) -> Result<F, String> {
+    let token = tokens.next().ok_or_else(|| format!("the file ends before the {}", what))?;
+    token.parse().map_err(|_| format!("invalid {} '{}'", what, token))
+}
+
+fn write_all<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), String> {
+    writer.write_all(bytes).map_err(|e| format!("could not write the matrix: {}", e))
+}
+
+fn read_all<R: Read>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|e| format!("could not read the matrix: {}", e))?;
+    Ok(bytes)
+}
+
+/// The number of bytes of a (rows x columns) matrix of T
+///
+/// The dimensions come from untrusted headers, so an overflow is an error and not a panic.
+fn data_size<T>(rows: usize, columns: usize) -> Result<usize, String> {
+    rows.checked_mul(columns)
+        .and_then(|elements| elements.checked_mul(std::mem::size_of::<T>()))
+        .and_then(|size| if size <= std::isize::MAX as usize { Some(size) } else { None })
+        .ok_or_else(|| format!("a {}x{} matrix is too large", rows, columns))
+}
+
+/// Write the matrix as dense Matrix Market array
+pub fn write_mtx<T: FileElement, M: Storable<T>, W: Write>(matrix: &M, mut writer: W) -> Result<(), String> {
+    let (rows, columns) = matrix.shape();
+    let mut out = format!("%%MatrixMarket matrix array {} general\n{} {}\n", T::FIELD, rows, columns);
+
+    for j in 0..columns {
+        for i in 0..rows {
+            out.push_str(&format!("{}\n", matrix.get(i, j)));
+        }
+    }
+
+    write_all(&mut writer, out.as_bytes())
+}
+
+/// Write the non zero elements of the matrix in Matrix Market coordinate format
+pub fn write_mtx_coordinate<T: FileElement, M: Storable<T>, W: Write>(matrix: &M, mut writer: W) -> Result<(), String> {
+    let (rows, columns) = matrix.shape();
+    let mut entries = String::new();
+    let mut count = 0;
+
+    for i in 0..rows {
+        for j in 0..columns {
+            let value = matrix.get(i, j);
+            if value != T::zero() {
+                entries.push_str(&format!("{} {} {}\n", i + 1, j + 1, value));
+                count += 1;
+            }
+        }
+    }
+
+    let header = format!("%%MatrixMarket matrix coordinate {} general\n{} {} {}\n", T::FIELD, rows, columns, count);
+    write_all(&mut writer, header.as_bytes())?;
+    write_all(&mut writer, entries.as_bytes())
+}
+
+/// Read a Matrix Market file in array or coordinate format
+///
+/// The fields real, integer and pattern and the general, symmetric and skew-symmetric
+/// matrices are supported. The values are parsed as T, so an integer matrix can not be read from a
+/// real file.
+pub fn read_mtx<T: FileElement, M: Storable<T>, R: Read>(mut reader: R) -> Result<M, String> {
+    let bytes = read_all(&mut reader)?;
+    let content = std::str::from_utf8(&bytes).map_err(|_| "the file is not valid UTF-8".to_string())?;
+    let mut lines = content.lines();
+
+    let header = lines.next().unwrap_or("").to_lowercase();
+    let header = header.split_whitespace().collect::<Vec<_>>();
+    if header.len() != 5 || header[0] != "%%matrixmarket" || header[1] != "matrix" {
+        return Err("the file has no Matrix Market header".to_string());
+    }
+
+    let (format, field, symmetry) = (header[2], header[3], header[4]);
+    let coordinate = match format {
+        "array" => false,
+        "coordinate" => true,
+        _ => return Err(format!("unknown format '{}'", format)),
+    };
+    match field {
+        "real" | "double" | "integer" => {},
+        "pattern" if coordinate => {},
+        _ => return Err(format!("the field '{}' is not supported", field)),
+    }
+    let sign = match symmetry {
+        "general" => None,
+        "symmetric" => Some(T::one()),
+        "skew-symmetric" => Some(-T::one()),
+        _ => return Err(format!("the symmetry '{}' is not supported", symmetry)),
+    };
+
+    let mut tokens = lines
+        .filter(|line| !line.starts_with('%'))
+        .flat_map(|line| line.split_whitespace());
+
+    let rows: usize = next(&mut tokens, "number of rows")?;
+    let columns: usize = next(&mut tokens, "number of columns")?;
+    if sign.is_some() && rows != columns {
+        return Err(format!("a symmetric matrix has to be square, not {}x{}", rows, columns));
+    }
+
+    data_size::<T>(rows, columns)?;
+
+    // every value of an array and every entry takes at least one byte, so a bad header is detected
+    // before the matrix is allocated
+    let entries: usize = if coordinate {
+        if rows * columns > MTX_MAX_ELEMENTS {
+            return Err(format!("a {}x{} coordinate matrix is too large, at most {} elements are supported",
+                               rows, columns, MTX_MAX_ELEMENTS));
+        }
+        next(&mut tokens, "number of entries")?
+    }
+    else {
+        match sign {
+            None => rows * columns,
+            Some(sign) => if sign == T::one() { rows * (rows + 1) / 2 } else { rows * rows.saturating_sub(1) / 2 },
+        }
+    };
+    if entries > content.len() {
+        return Err(format!("the file is too short for {} entries of a {}x{} matrix", entries, rows, columns));
+    }
+
+    let mut matrix = Matrix::<T>::zero(rows, columns);
+
+    if coordinate {
+        for _ in 0..entries {
+            let i: usize = next(&mut tokens, "row")?;
+            let j: usize = next(&mut tokens, "column")?;
+            if i == 0 || i > rows || j == 0 || j > columns {
+                return Err(format!("the entry ({}, {}) is outside of the {}x{} matrix", i, j, rows, columns));
+            }
+            let value = if field == "pattern" { T::one() } else { next(&mut tokens, "value")? };
+
+            matrix[(i - 1, j - 1)] = value;
+            if let Some(sign) = sign {
+                if i != j {
+                    matrix[(j - 1, i - 1)] = sign * value;
+                }
+            }
+        }
+    }
+    else {
+        for j in 0..columns {
+            // symmetric arrays only contain the lower triangle, skew-symmetric ones without the diagonal
+            let first = match sign {
+                None => 0,
+                Some(sign) => if sign == T::one() { j } else { j + 1 },
+            };
+
+            for i in first..rows {
+                let value = next(&mut tokens, "value")?;
+                matrix[(i, j)] = value;
+                if let Some(sign) = sign {
+                    matrix[(j, i)] = sign * value;
+                }
+            }
+        }
+    }
+
+    Ok(M::from_matrix(matrix))
+}
+
+/// The .npy representation of the matrix, version 1.0 in C order
+fn npy_bytes<T: FileElement, M: Storable<T>>(matrix: &M) -> Vec<u8> {
+    let (rows, columns) = matrix.shape();
+
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}", T::DESCR, rows, columns);
+    // the data starts at a multiple of 64 bytes, the header ends with a newline
+    while (NPY_MAGIC.len() + 4 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(NPY_MAGIC.len() + 4 + header.len() + rows * columns * std::mem::size_of::<T>());
+    out.extend_from_slice(NPY_MAGIC);
+    out.extend_from_slice(&[1, 0]);
+    push_le(&mut out, header.len() as u64, 2);
+    out.extend_from_slice(header.as_bytes());
+
+    for i in 0..rows {
+        for j in 0..columns {
+            matrix.get(i, j).write_le(&mut out);
+        }
+    }
+
+    out
+}
+
+/// The value of key in the header of a .npy file
+fn npy_value<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let pattern = format!("'{}':", key);
+    header.find(pattern.as_str())
+        .map(|start| header[start + pattern.len()..].trim_left())
+        .ok_or_else(|| format!("the header has no {}", key))
+}
+
+/// Parse the content of a .npy file
+fn parse_npy<T: FileElement, M: Storable<T>>(bytes: &[u8]) -> Result<M, String> {
+    if bytes.len() < 10 || &bytes[..NPY_MAGIC.len()] != NPY_MAGIC {
+        return Err("the file is not a .npy file".to_string());
+    }
+
+    let (length, start) = match bytes[6] {
+        1 => (read_le(&bytes[8..10]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (read_le(&bytes[8..12]) as usize, 12),
+        version => return Err(format!("the .npy version {} is not supported", version)),
+    };
+    if length > bytes.len() - start {
+        return Err("the header is truncated".to_string());
+    }
+    let header = std::str::from_utf8(&bytes[start..start + length]).map_err(|_| "the header is not valid UTF-8".to_string())?;
+
+    let descr = npy_value(header, "descr")?.trim_left_matches(|c: char| c == '\'' || c == '"');
+    let descr = &descr[..descr.find(|c: char| c == '\'' || c == '"').unwrap_or(0)];
+    if descr != T::DESCR {
+        return Err(format!("expected the dtype {}, the file contains {}", T::DESCR, descr));
+    }
+
+    let fortran_order = npy_value(header, "fortran_order")?.starts_with("True");
+
+    let shape = npy_value(header, "shape")?;
+    let shape = shape.trim_left_matches('(');
+    let shape = shape[..shape.find(')').unwrap_or(0)]
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>().map_err(|_| format!("invalid shape '{}'", s)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // vectors are read as a single row
+    let (rows, columns) = match shape.len() {
+        1 => (1, shape[0]),
+        2 => (shape[0], shape[1]),
+        _ => return Err(format!("expected a 2 dimensional array, got {} dimensions", shape.len())),
+    };
+
+    let size = std::mem::size_of::<T>();
+    let data = &bytes[start + length..];
+    if data.len() < data_size::<T>(rows, columns)? {
+        return Err(format!("expected {} elements, the file contains {}", rows * columns, data.len() / size));
+    }
+
+    let values = data.chunks(size).take(rows * columns).map(T::read_le).collect::<Vec<_>>();
+    let matrix = if fortran_order {
+        Matrix::<T, ColMajor>::new(rows, columns, values).to_layout::<RowMajor>()
+    }
+    else {
+        Matrix::<T>::new(rows, columns, values)
+    };
+
+    Ok(M::from_matrix(matrix))
+}
+
+/// Write the matrix as .npy file, which numpy.load reads
+pub fn write_npy<T: FileElement, M: Storable<T>, W: Write>(matrix: &M, mut writer: W) -> Result<(), String> {
+    write_all(&mut writer, &npy_bytes(matrix))
+}
+
+/// Read a .npy file with the dtype T::DESCR in C or Fortran order
+pub fn read_npy<T: FileElement, M: Storable<T>, R: Read>(mut reader: R) -> Result<M, String> {
+    parse_npy(&read_all(&mut reader)?)
+}
+
+/// The CRC-32 checksum of zip archives
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// Write the matrices as .npz archive, which numpy.load reads like the result of numpy.savez
+///
+/// Every matrix is stored uncompressed as name.npy.
+pub fn write_npz<T: FileElement, M: Storable<T>, W: Write>(arrays: &[(&str, &M)], mut writer: W) -> Result<(), String> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+
+    for &(name, matrix) in arrays {
+        let data = npy_bytes(matrix);
+        let name = format!("{}.npy", name);
+        let offset = out.len();
+
+        if data.len() >= std::u32::MAX as usize || offset >= std::u32::MAX as usize {
+            return Err("archives larger than 4 GB are not supported".to_string());
+        }
+
+        // the fields shared by the local and the central header: version needed, flags, method,
+        // time, date, crc, sizes and the length of the name and the extra field
+        let mut common = Vec::new();
+        for &(value, size) in &[(20, 2), (0, 2), (0, 2), (0, 2), (ZIP_DATE, 2), (crc32(&data) as u64, 4),
+                                (data.len() as u64, 4), (data.len() as u64, 4), (name.len() as u64, 2), (0, 2)] {
+            push_le(&mut common, value, size);
+        }
+
+        push_le(&mut out, ZIP_LOCAL, 4);
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&data);
+
+        push_le(&mut central, ZIP_CENTRAL, 4);
+        push_le(&mut central, 20, 2);
+        central.extend_from_slice(&common);
+        // comment length, disk, internal and external attributes
+        for &(value, size) in &[(0, 2), (0, 2), (0, 2), (0, 4), (offset as u64, 4)] {
+            push_le(&mut central, value, size);
+        }
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let offset = out.len();
+    let central_size = central.len();
+    out.extend_from_slice(&central);
+
+    push_le(&mut out, ZIP_END, 4);
+    for &(value, size) in &[(0, 2), (0, 2), (arrays.len() as u64, 2), (arrays.len() as u64, 2),
+                            (central_size as u64, 4), (offset as u64, 4), (0, 2)] {
+        push_le(&mut out, value, size);
+    }
+
+    write_all(&mut writer, &out)
+}
+
+/// The sizes of a zip entry from its zip64 extra field
+fn zip64_size(extra: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+
+    while pos + 4 <= extra.len() {
+        let (id, length) = (read_le(&extra[pos..pos + 2]), read_le(&extra[pos + 2..pos + 4]) as usize);
+        if id == 1 && length >= 16 && pos + 4 + length <= extra.len() {
+            // the uncompressed size comes first, it is the compressed size of stored entries
+            return Some(read_le(&extra[pos + 4..pos + 12]) as usize);
+        }
+        pos += 4 + length;
+    }
+
+    None
+}
+
+/// Read the matrices of a .npz archive written by numpy.savez or write_npz
+///
+/// The names do not contain the .npy extension. Compressed archives of numpy.savez_compressed
+/// are not supported.
+pub fn read_npz<T: FileElement, M: Storable<T>, R: Read>(mut reader: R) -> Result<Vec<(String, M)>, String> {
+    let bytes = read_all(&mut reader)?;
+    let mut arrays = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        if pos + 30 > bytes.len() {
+            return Err("the archive is truncated".to_string());
+        }
+
+        let header = &bytes[pos..pos + 30];
+        match read_le(&header[..4]) {
+            ZIP_LOCAL => {},
+            ZIP_CENTRAL | ZIP_END => return Ok(arrays),
+            _ => return Err("the file is not a .npz archive".to_string()),
+        }
+
+        let (flags, method) = (read_le(&header[6..8]), read_le(&header[8..10]));
+        if method != 0 {
+            return Err("compressed archives are not supported".to_string());
+        }
+        if flags & 8 != 0 {
+            return Err("archives with data descriptors are not supported".to_string());
+        }
+
+        let crc = read_le(&header[14..18]) as u32;
+        let size = read_le(&header[18..22]) as usize;
+        let (name_length, extra_length) = (read_le(&header[26..28]) as usize, read_le(&header[28..30]) as usize);
+
+        let start = pos + 30 + name_length + extra_length;
+        if start > bytes.len() {
+            return Err("the archive is truncated".to_string());
+        }
+        let name = String::from_utf8_lossy(&bytes[pos + 30..pos + 30 + name_length]).into_owned();
+
+        // numpy writes zip64 entries, their size is in the extra field
+        let size = if size == std::u32::MAX as usize {
+            zip64_size(&bytes[pos + 30 + name_length..start]).ok_or_else(|| format!("{} has no zip64 size", name))?
+        }
+        else {
+            size
+        };
+
+        // the size comes from the archive, so it must not be added to start before the check
+        if size > bytes.len() - start {
+            return Err(format!("{} is truncated", name));
+        }
+        let data = &bytes[start..start + size];
+        if crc32(data) != crc {
+            return Err(format!("{} is corrupted", name));
+        }
+
+        let matrix = parse_npy(data).map_err(|e| format!("{}: {}", name, e))?;
+        let name = if name.ends_with(".npy") { name[..name.len() - 4].to_string() } else { name };
+        arrays.push((name, matrix));
+
+        pos = start + size;
+    }
+}
+
+/// Write the matrix in the raw format, see the module documentation
+pub fn write_raw<T: FileElement, M: Storable<T>, W: Write>(matrix: &M, mut writer: W) -> Result<(), String> {
+    let (rows, columns) = matrix.shape();
+
+    let mut out = Vec::with_capacity(RAW_HEADER + rows * columns * std::mem::size_of::<T>());
+    out.extend_from_slice(RAW_MAGIC);
+    out.extend_from_slice(&[1, T::CODE, 0, 0]);
+    push_le(&mut out, rows as u64, 8);
+    push_le(&mut out, columns as u64, 8);
+
+    for i in 0..rows {
+        for j in 0..columns {
+            matrix.get(i, j).write_le(&mut out);
+        }
+    }
+
+    write_all(&mut writer, &out)
+}
+
+/// Read a matrix in the raw format, the element type has to be T
+pub fn read_raw<T: FileElement, M: Storable<T>, R: Read>(mut reader: R) -> Result<M, String> {
+    let bytes = read_all(&mut reader)?;
+
+    if bytes.len() < RAW_HEADER || &bytes[..4] != RAW_MAGIC {
+        return Err("the file is not a raw matrix".to_string());
+    }
+    if bytes[4] != 1 {
+        return Err(format!("the raw version {} is not supported", bytes[4]));
+    }
+    if bytes[5] != T::CODE {
+        return Err(format!("expected the element type {}, the file contains {}", T::CODE, bytes[5]));
+    }
+
+    let rows = read_le(&bytes[8..16]) as usize;
+    let columns = read_le(&bytes[16..24]) as usize;
+    let size = std::mem::size_of::<T>();
+    let data = &bytes[RAW_HEADER..];
+
+    if data.len() != data_size::<T>(rows, columns)? {
+        return Err(format!("expected {} elements, the file contains {}", rows * columns, data.len() / size));
+    }
+
+    let values = data.chunks(size).map(T::read_le).collect();
+    Ok(M::from_matrix(Matrix::new(rows, columns, values)))
+}
+
+/// The extension of path, lowercase
+fn extension(path: &str) -> String {
+    Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase()
+}
+
+/// Write the matrix to path, the format is chosen by the extension
+///
+/// .mtx is a Matrix Market array, .npy and .npz (with the array "matrix") are NumPy files,
+/// .bin and .raw the raw format.
+pub fn save<T: FileElement, M: Storable<T>>(matrix: &M, path: &str) -> Result<(), String> {
+    // the matrix is encoded first, so an unknown extension does not leave an empty file behind
+    let mut bytes = Vec::new();
+
+    match extension(path).as_str() {
+        "mtx" => write_mtx(matrix, &mut bytes),
+        "npy" => write_npy(matrix, &mut bytes),
+        "npz" => write_npz(&[("matrix", matrix)], &mut bytes),
+        "bin" | "raw" => write_raw(matrix, &mut bytes),
+        other => Err(format!("unknown matrix file extension '{}'", other)),
+    }.map_err(|e| format!("{}: {}", path, e))?;
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(&bytes))
+        .map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+/// Read a matrix from path, the format is chosen by the extension like in save
+///
+/// The first array of a .npz archive is read.
+pub fn load<T: FileElement, M: Storable<T>>(path: &str) -> Result<M, String> {
+    let file = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+    let reader = BufReader::new(file);
+
+    match extension(path).as_str() {
+        "mtx" => read_mtx(reader),
+        "npy" => read_npy(reader),
+        "npz" => read_npz(reader).and_then(|arrays| {
+            arrays.into_iter().next().map(|(_, matrix)| matrix).ok_or_else(|| "the archive is empty".to_string())
+        }),
+        "bin" | "raw" => read_raw(reader),
+        other => Err(format!("unknown matrix file extension '{}'", other)),
+    }.map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate libc;
+
+    use super::*;
+
+    use super::super::*;
+
+    use super::super::generate::identity;
+
+    fn sample() -> Matrix {
+        Generator::new(5).uniform(5, 3, -10., 10.)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let matrix = sample();
+
+        let mut mtx = Vec::new();
+        write_mtx(&matrix, &mut mtx).unwrap();
+        assert_eq!(read_mtx::<f64, Matrix, _>(&mtx[..]), Ok(matrix.clone()));
+
+        let mut coordinate = Vec::new();
+        write_mtx_coordinate(&identity::<f64, RowMajor>(4), &mut coordinate).unwrap();
+        assert!(String::from_utf8_lossy(&coordinate).contains("4 4 4\n"));
+        assert_eq!(read_mtx::<f64, Matrix, _>(&coordinate[..]), Ok(identity(4)));
+
+        let mut npy = Vec::new();
+        write_npy(&matrix, &mut npy).unwrap();
+        assert_eq!(npy.len() % 64, (5 * 3 * 8) % 64);
+        assert_eq!(read_npy::<f64, Matrix, _>(&npy[..]), Ok(matrix.clone()));
+
+        let mut raw = Vec::new();
+        write_raw(&matrix, &mut raw).unwrap();
+        assert_eq!(raw.len(), 24 + 5 * 3 * 8);
+        assert_eq!(read_raw::<f64, Matrix, _>(&raw[..]), Ok(matrix.clone()));
+
+        let mut npz = Vec::new();
+        let other = identity::<f64, RowMajor>(3);
+        write_npz(&[("a", &matrix), ("b", &other)], &mut npz).unwrap();
+        assert_eq!(read_npz::<f64, Matrix, _>(&npz[..]),
+                   Ok(vec![("a".to_string(), matrix.clone()), ("b".to_string(), other)]));
+    }
+
+    #[test]
+    fn test_types() {
+        // the file content does not depend on the layout
+        let matrix = sample();
+        let transposed = TransposedMatrix::from(matrix.clone());
+
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        write_npy(&matrix, &mut a).unwrap();
+        write_npy(&transposed, &mut b).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(read_npy::<f64, TransposedMatrix, _>(&a[..]), Ok(transposed));
+
+        let large: Matrix = Generator::new(6).uniform(40, 24, -1., 1.);
+        let tiled = TileMatrix::with_block_size(large.clone(), 8);
+        let mut raw = Vec::new();
+        write_raw(&tiled, &mut raw).unwrap();
+        assert_eq!(read_raw::<f64, Matrix, _>(&raw[..]), Ok(large.clone()));
+        assert_eq!(Matrix::from(read_raw::<f64, TileMatrix, _>(&raw[..]).unwrap()), large);
+
+        let ints = Matrix::<i32>::new(2, 2, vec![-1, 2, 3, -4]);
+        let mut mtx = Vec::new();
+        write_mtx(&ints, &mut mtx).unwrap();
+        assert!(String::from_utf8_lossy(&mtx).starts_with("%%MatrixMarket matrix array integer general\n2 2\n-1\n3\n"));
+        assert_eq!(read_mtx::<i32, Matrix<i32>, _>(&mtx[..]), Ok(ints.clone()));
+
+        let mut npy = Vec::new();
+        write_npy(&ints, &mut npy).unwrap();
+        assert!(read_npy::<f64, Matrix, _>(&npy[..]).unwrap_err().contains("<i4"));
+        assert_eq!(read_npy::<i32, Matrix<i32>, _>(&npy[..]), Ok(ints));
+    }
+
+    #[test]
+    fn test_mtx() {
+        let symmetric = "%%MatrixMarket matrix array real symmetric\n% a comment\n3 3\n1\n2\n3\n4\n5\n6\n";
+        let expected = Matrix::<f64>::new(3, 3, vec![1., 2., 3., 2., 4., 5., 3., 5., 6.]);
+        assert_eq!(read_mtx::<f64, Matrix, _>(symmetric.as_bytes()), Ok(expected));
+
+        let skew = "%%MatrixMarket matrix coordinate integer skew-symmetric\n2 2 1\n2 1 7\n";
+        assert_eq!(read_mtx::<i64, Matrix<i64>, _>(skew.as_bytes()), Ok(Matrix::new(2, 2, vec![0, -7, 7, 0])));
+
+        let pattern = "%%MatrixMarket matrix coordinate pattern general\n2 3 2\n1 3\n2 1\n";
+        assert_eq!(read_mtx::<f64, Matrix, _>(pattern.as_bytes()), Ok(Matrix::new(2, 3, vec![0., 0., 1., 1., 0., 0.])));
+
+        let errors = [
+            ("2 2\n1\n2\n3\n4\n", "header"),
+            ("%%MatrixMarket matrix array complex general\n1 1\n1 0\n", "complex"),
+            ("%%MatrixMarket matrix array real general\n2 2\n1\n2\n3\n", "ends"),
+            ("%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1.0\n", "outside"),
+            ("%%MatrixMarket matrix array real general\n1 1\nx\n", "invalid value"),
+        ];
+        for &(content, message) in &errors {
+            let error = read_mtx::<f64, Matrix, _>(content.as_bytes()).unwrap_err();
+            assert!(error.contains(message), "{}: {}", message, error);
+        }
+    }
+
+    #[test]
+    fn test_npy() {
+        // a Fortran ordered array written by numpy 2.0 with a version 2 header
+        let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }\n";
+        let mut bytes = b"\x93NUMPY\x02\x00".to_vec();
+        push_le(&mut bytes, header.len() as u64, 4);
+        bytes.extend_from_slice(header.as_bytes());
+        for &value in &[1f64, 4., 2., 5., 3., 6.] {
+            value.write_le(&mut bytes);
+        }
+
+        let expected = Matrix::<f64>::new(2, 3, vec![1., 2., 3., 4., 5., 6.]);
+        assert_eq!(read_npy::<f64, Matrix, _>(&bytes[..]), Ok(expected));
+
+        assert!(read_npy::<f64, Matrix, _>(&bytes[..bytes.len() - 8]).unwrap_err().contains("elements"));
+        assert!(read_npy::<f64, Matrix, _>(&b"NUMPY"[..]).is_err());
+
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_zip64() {
+        // numpy.savez marks the sizes as zip64 and stores them in the extra field
+        let mut data = Vec::new();
+        write_npy(&sample(), &mut data).unwrap();
+        let name = b"x.npy";
+
+        let mut bytes = Vec::new();
+        for &(value, size) in &[(ZIP_LOCAL, 4), (45, 2), (0, 2), (0, 2), (0, 2), (ZIP_DATE, 2), (crc32(&data) as u64, 4),
+                                (0xFFFFFFFF, 4), (0xFFFFFFFF, 4), (name.len() as u64, 2), (20, 2)] {
+            push_le(&mut bytes, value, size);
+        }
+        bytes.extend_from_slice(name);
+        for &(value, size) in &[(1, 2), (16, 2), (data.len() as u64, 8), (data.len() as u64, 8)] {
+            push_le(&mut bytes, value, size);
+        }
+        bytes.extend_from_slice(&data);
+        push_le(&mut bytes, ZIP_END, 4);
+        bytes.extend_from_slice(&[0; 26]);
+
+        assert_eq!(read_npz::<f64, Matrix, _>(&bytes[..]), Ok(vec![("x".to_string(), sample())]));
+
+        // a flipped bit is detected
+        let last = bytes.len() - 40;
+        bytes[last] ^= 1;
+        assert!(read_npz::<f64, Matrix, _>(&bytes[..]).unwrap_err().contains("corrupted"));
+    }
+
+    #[test]
+    fn test_bad_headers() {
+        // dimensions whose size overflows or which do not match the data are errors, not panics
+        for &(rows, columns) in &[(std::u64::MAX, 2), (1 << 40, 1 << 40), (1 << 20, 1 << 20)] {
+            let mut raw = RAW_MAGIC.to_vec();
+            raw.extend_from_slice(&[1, <f64 as FileElement>::CODE, 0, 0]);
+            push_le(&mut raw, rows, 8);
+            push_le(&mut raw, columns, 8);
+            assert!(read_raw::<f64, Matrix, _>(&raw[..]).is_err(), "{}x{}", rows, columns);
+        }
+
+        let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (4294967296, 4294967296), }\n";
+        let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+        push_le(&mut npy, header.len() as u64, 2);
+        npy.extend_from_slice(header.as_bytes());
+        assert!(read_npy::<f64, Matrix, _>(&npy[..]).unwrap_err().contains("too large"));
+
+        let mut npz = Vec::new();
+        for &(value, size) in &[(ZIP_LOCAL, 4), (45, 2), (0, 2), (0, 2), (0, 2), (ZIP_DATE, 2), (0, 4),
+                                (0xFFFFFFFF, 4), (0xFFFFFFFF, 4), (5, 2), (20, 2)] {
+            push_le(&mut npz, value, size);
+        }
+        npz.extend_from_slice(b"x.npy");
+        for &(value, size) in &[(1, 2), (16, 2), (std::u64::MAX, 8), (std::u64::MAX, 8)] {
+            push_le(&mut npz, value, size);
+        }
+        assert!(read_npz::<f64, Matrix, _>(&npz[..]).unwrap_err().contains("truncated"));
+
+        // the array is not allocated before the values are known to fit into the file
+        let mtx = "%%MatrixMarket matrix array real general\n100000 100000\n1\n";
+        assert!(read_mtx::<f64, Matrix, _>(mtx.as_bytes()).unwrap_err().contains("too short"));
+        let mtx = "%%MatrixMarket matrix coordinate real general\n18446744073709551615 2 0\n";
+        assert!(read_mtx::<f64, Matrix, _>(mtx.as_bytes()).unwrap_err().contains("too large"));
+        let mtx = "%%MatrixMarket matrix coordinate real general\n100000 100000 0\n";
+        assert!(read_mtx::<f64, Matrix, _>(mtx.as_bytes()).unwrap_err().contains("too large"));
+        let mtx = "%%MatrixMarket matrix coordinate real general\n10 10 100000\n1 1 1\n";
+        assert!(read_mtx::<f64, Matrix, _>(mtx.as_bytes()).unwrap_err().contains("too short"));
+    }
+
+    #[test]
+    fn test_files() {
+        let matrix = sample();
+        let dir = std::env::temp_dir();
+
+        for extension in &["mtx", "npy", "npz", "bin"] {
+            // concurrent test runs must not share the files
+            let path = dir.join(format!("rust_matrix_file_test_{}.{}", unsafe { libc::getpid() }, extension));
+            let path = path.to_str().unwrap();
+
+            save(&matrix, path).unwrap();
+            assert_eq!(load::<f64, Matrix>(path), Ok(matrix.clone()), "{}", extension);
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(save(&matrix, "matrix.txt").unwrap_err().contains("extension"));
+        assert!(load::<f64, Matrix>("/nonexistent/matrix.npy").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Random and structured test matrices
+//!
+//! All generators fill the matrix element by element in row major order through (row, column),
+//! so the same seed produces the same matrix in every layout. Other matrix types are created with
+//! their From<Matrix> implementations, e.g. `TileMatrix::from(generator.uniform(n, n, -1., 1.))`.
+
+extern crate rand;
+
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+
+use std;
+
+use std::fmt;
+
+use super::element::Element;
+
+use super::layout::{Layout, RowMajor};
+
+use super::standard::Matrix;
+
+/// A seeded source of random matrices
+///
+/// The sequence only depends on the seed, so a failing comparison can be reproduced by reusing
+/// the seed of the failing run.
+#[derive(Clone)]
+pub struct Generator {
+    seed: u64,
+    rng: XorShiftRng,
+}
+
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Generator({})", self.seed)
+    }
+}
+
+/// Spread the bits of a seed, xorshift must not start from zero
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        let mut state = seed;
+        let (a, b) = (split_mix(&mut state), split_mix(&mut state) | 1);
+
+        Self {
+            seed: seed,
+            rng: XorShiftRng::from_seed([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32]),
+        }
+    }
+
+    /// The seed this generator was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A uniformly distributed number in [low, high)
+    fn next_uniform(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.rng.gen::<f64>()
+    }
+
+    /// A normally distributed number, with the Box-Muller transform
+    fn next_normal(&mut self, mean: f64, std_dev: f64) -> f64 {
+        // 1 - [0, 1) is never zero, so the logarithm is finite
+        let u = 1. - self.rng.gen::<f64>();
+        let v = self.rng.gen::<f64>();
+
+        mean + std_dev * (-2. * u.ln()).sqrt() * (2. * std::f64::consts::PI * v).cos()
+    }
+
+    /// A matrix with elements uniformly distributed in [low, high)
+    pub fn uniform<T: Element, L: Layout>(&mut self, rows: usize, columns: usize, low: f64, high: f64) -> Matrix<T, L> {
+        from_fn(rows, columns, |_, _| T::from_f64(self.next_uniform(low, high)))
+    }
+
+    /// A matrix with normally distributed elements
+    pub fn normal<T: Element, L: Layout>(&mut self, rows: usize, columns: usize, mean: f64, std_dev: f64) -> Matrix<T, L> {
+        from_fn(rows, columns, |_, _| T::from_f64(self.next_normal(mean, std_dev)))
+    }
+
+    /// A random symmetric positive definite matrix
+    ///
+    /// This is B * B^T with B uniform in [-1, 1), the diagonal is increased until the matrix is
+    /// strictly diagonally dominant.
+    pub fn spd<T: Element, L: Layout>(&mut self, n: usize) -> Matrix<T, L> {
+        let b: Matrix<f64> = self.uniform(n, n, -1., 1.);
+        let gram: Matrix<f64> = from_fn(n, n, |i, j| (0..n).fold(0., |sum, k| sum + b[(i, k)] * b[(j, k)]));
+
+        from_fn(n, n, |i, j| {
+            if i == j {
+                let off_diagonal = (0..n).filter(|&k| k != i).fold(0., |sum, k| sum + gram[(i, k)].abs());
+                T::from_f64(gram[(i, i)] + off_diagonal + 1.)
+            }
+            else {
+                T::from_f64(gram[(i, j)])
+            }
+        })
+    }
+
+    /// A (rows x columns) matrix with uniform elements in [-T::RANDOM_BOUND, T::RANDOM_BOUND) on
+    /// the lower sub- and upper superdiagonals and the diagonal, the other elements are zero
+    pub fn banded<T: Element, L: Layout>(&mut self, rows: usize, columns: usize, lower: usize, upper: usize) -> Matrix<T, L> {
+        from_fn(rows, columns, |i, j| {
+            if j + lower >= i && j <= i + upper {
+                T::from_f64(self.next_uniform(-T::RANDOM_BOUND, T::RANDOM_BOUND))
+            }
+            else {
+                T::zero()
+            }
+        })
+    }
+}
+
+/// Fill a matrix with f(row, column), the elements are visited row by row
+pub fn from_fn<T, L, F>(rows: usize, columns: usize, mut f: F) -> Matrix<T, L>
+    where T: Element, L: Layout, F: FnMut(usize, usize) -> T
+{
+    let mut ret = Matrix::zero(rows, columns);
+
+    for i in 0..rows {
+        for j in 0..columns {
+            ret[(i, j)] = f(i, j);
+        }
+    }
+
+    ret
+}
+
+/// The n x n identity
+pub fn identity<T: Element, L: Layout>(n: usize) -> Matrix<T, L> {
+    from_fn(n, n, |i, j| if i == j { T::one() } else { T::zero() })
+}
+
+/// A square matrix with the given diagonal
+pub fn diagonal<T: Element, L: Layout>(values: &[T]) -> Matrix<T, L> {
+    from_fn(values.len(), values.len(), |i, j| if i == j { values[i] } else { T::zero() })
+}
+
+/// The n x n Hilbert matrix 1 / (i + j + 1), which is notoriously ill-conditioned
+pub fn hilbert<T: Element, L: Layout>(n: usize) -> Matrix<T, L> {
+    from_fn(n, n, |i, j| T::from_f64(1. / (i + j + 1) as f64))
+}
+
+/// A row major uniform matrix in [-T::RANDOM_BOUND, T::RANDOM_BOUND) for the given seed
+///
+/// This is [-1, 1) for floats and the integers -8..8 for integer types.
+pub fn random<T: Element>(rows: usize, columns: usize, seed: u64) -> Matrix<T, RowMajor> {
+    Generator::new(seed).uniform(rows, columns, -T::RANDOM_BOUND, T::RANDOM_BOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::*;
+
+    use super::super::super::naive;
+
+    #[test]
+    fn test_seeds() {
+        let a: Matrix = Generator::new(42).uniform(7, 5, -1., 1.);
+        assert_eq!(a, Generator::new(42).uniform(7, 5, -1., 1.));
+        assert!(a != Generator::new(43).uniform(7, 5, -1., 1.));
+        assert!(a.data.iter().all(|&x| x >= -1. && x < 1.));
+
+        // the content does not depend on the layout
+        let t: TransposedMatrix = Generator::new(42).uniform(7, 5, -1., 1.);
+        assert_eq!(Matrix::from(t), a);
+        let s: Matrix = Generator::new(42).uniform(8, 8, -1., 1.);
+        assert_eq!(Matrix::from(TileMatrix::with_block_size(s.clone(), 4)), s);
+
+        // two matrices from one generator differ
+        let mut generator = Generator::new(0);
+        let b: Matrix = generator.normal(10, 10, 0., 1.);
+        let c: Matrix = generator.normal(10, 10, 0., 1.);
+        assert!(b != c);
+        assert_eq!(generator.seed(), 0);
+        assert_eq!(random::<f64>(10, 10, 3), random(10, 10, 3));
+    }
+
+    #[test]
+    fn test_integers() {
+        // integer elements are rounded down, so every integer in the range occurs
+        let a: Matrix<i32> = Generator::new(5).uniform(20, 20, -1., 1.);
+        assert!(a.data.iter().all(|&x| x == -1 || x == 0));
+        assert!(a.data.iter().any(|&x| x == -1) && a.data.iter().any(|&x| x == 0));
+
+        let r = random::<i32>(20, 20, 5);
+        assert!(r.data.iter().all(|&x| x >= -8 && x < 8));
+        assert!(r.data.iter().filter(|&&x| x == 0).count() < 100);
+        assert!(r.data.iter().any(|&x| x == -8) && r.data.iter().any(|&x| x == 7));
+    }
+
+    #[test]
+    fn test_normal() {
+        let n = 200;
+        let m: Matrix = Generator::new(1).normal(n, n, 2., 3.);
+
+        let mean = m.data.iter().sum::<f64>() / (n * n) as f64;
+        let variance = m.data.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n * n) as f64;
+
+        assert!((mean - 2.).abs() < 0.1, "mean {}", mean);
+        assert!((variance.sqrt() - 3.).abs() < 0.1, "standard deviation {}", variance.sqrt());
+    }
+
+    #[test]
+    fn test_structured() {
+        let i: Matrix = identity(3);
+        assert_eq!(i, Matrix::new(3, 3, vec![1., 0., 0., 0., 1., 0., 0., 0., 1.]));
+        assert_eq!(diagonal::<i32, RowMajor>(&[1, 2]), Matrix::new(2, 2, vec![1, 0, 0, 2]));
+        assert_eq!(Matrix::from(diagonal::<f64, ColMajor>(&[1., 2.])), diagonal::<f64, RowMajor>(&[1., 2.]));
+
+        let h: Matrix = hilbert(3);
+        assert_eq!(h[(1, 2)], 0.25);
+        assert_eq!(h[(2, 1)], 0.25);
+
+        let mut generator = Generator::new(7);
+
+        let spd: Matrix = generator.spd(20);
+        for i in 0..20 {
+            let off_diagonal = (0..20).filter(|&j| j != i).map(|j| spd[(i, j)].abs()).sum::<f64>();
+            assert!(spd[(i, i)] > off_diagonal);
+            for j in 0..20 {
+                assert_eq!(spd[(i, j)], spd[(j, i)]);
+            }
+        }
+
+        let band: Matrix = generator.banded(6, 8, 1, 2);
+        for i in 0..6 {
+            for j in 0..8 {
+                let inside = j + 1 >= i && j <= i + 2;
+                assert_eq!(band[(i, j)] != 0., inside, "({}, {})", i, j);
+            }
+        }
+
+        // A * I = A
+        let a: Matrix = generator.uniform(5, 5, -1., 1.);
+        let mut c = Matrix::zero(5, 5);
+        naive::mult(&a, &identity::<f64, RowMajor>(5), &mut c);
+        assert_eq!(c, a);
+    }
+}
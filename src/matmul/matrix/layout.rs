@@ -0,0 +1,183 @@
+use std;
+
+use std::fmt;
+
+use super::element::Element;
+
+use super::super::tuning::block_sizes;
+
+/// The order in which the elements of a Matrix are stored
+///
+/// The layout is a type parameter of Matrix, so all storage orders share one implementation and
+/// the offset computation is resolved at compile time. The tile size of Tiled is only known at
+/// runtime, it is stored in the matrix and passed to every method, the dense layouts ignore it.
+///
+/// The index based kernels, e.g. naive and blocked, are generic over the layouts of all operands,
+/// the packed kernel over the dense layouts. The simd, rayon, recursive and asm kernels depend on
+/// contiguous rows in their inner loops and keep their concrete operand types.
+pub trait Layout: fmt::Debug + Clone + Copy + PartialEq + Eq + Send + Sync + 'static {
+    /// The position of element (row, column) of a (rows x columns) matrix in its data
+    fn offset(rows: usize, columns: usize, tile: usize, row: usize, column: usize) -> usize;
+
+    /// The length of the contiguous lines, which decides whether the lines are aligned
+    ///
+    /// If the lines have different lengths, this is a length all of them are a multiple of.
+    fn line_length(rows: usize, columns: usize, tile: usize) -> usize;
+
+    /// Whether the layout is stored in tiles, the tile size is ignored otherwise
+    fn uses_tile() -> bool {
+        false
+    }
+
+    /// The tile size of new matrices of T
+    fn default_tile<T: Element>() -> usize {
+        0
+    }
+}
+
+/// A layout with constant distances between two rows and two columns
+///
+/// Only dense matrices can be read with strides, e.g. by gemm and the packed kernel, and be
+/// reinterpreted as their transpose without moving any element.
+pub trait Dense: Layout {
+    /// The layout which stores the transposed matrix in the same data
+    type Transposed: Dense;
+
+    /// The distance between two rows and two columns of a (rows x columns) matrix in elements
+    fn strides(rows: usize, columns: usize) -> (usize, usize);
+}
+
+/// The rows are stored one after the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowMajor;
+
+/// The columns are stored one after the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColMajor;
+
+/// The matrix is split into square tiles, which are stored row by row and are row major
+///
+/// The tiles in the last row and column are cut at the border of the matrix, so the data is not
+/// padded and contains rows * columns elements like the dense layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tiled;
+
+impl Layout for RowMajor {
+    #[inline]
+    fn offset(_rows: usize, columns: usize, _tile: usize, row: usize, column: usize) -> usize {
+        row * columns + column
+    }
+
+    #[inline]
+    fn line_length(_rows: usize, columns: usize, _tile: usize) -> usize {
+        columns
+    }
+}
+
+impl Dense for RowMajor {
+    type Transposed = ColMajor;
+
+    #[inline]
+    fn strides(_rows: usize, columns: usize) -> (usize, usize) {
+        (columns, 1)
+    }
+}
+
+impl Layout for ColMajor {
+    #[inline]
+    fn offset(rows: usize, _columns: usize, _tile: usize, row: usize, column: usize) -> usize {
+        row + column * rows
+    }
+
+    #[inline]
+    fn line_length(rows: usize, _columns: usize, _tile: usize) -> usize {
+        rows
+    }
+}
+
+impl Dense for ColMajor {
+    type Transposed = RowMajor;
+
+    #[inline]
+    fn strides(rows: usize, _columns: usize) -> (usize, usize) {
+        (1, rows)
+    }
+}
+
+/// The greatest common divisor of a and b
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl Layout for Tiled {
+    #[inline]
+    fn offset(rows: usize, columns: usize, tile: usize, row: usize, column: usize) -> usize {
+        // the first row and column of the tile and its size, which is smaller at the border
+        let (first_row, first_column) = (row / tile * tile, column / tile * tile);
+        let height = std::cmp::min(tile, rows - first_row);
+        let width = std::cmp::min(tile, columns - first_column);
+
+        first_row * columns + first_column * height + (row - first_row) * width + column - first_column
+    }
+
+    /// The rows of the tiles are tile long, the ones of the last tile column columns % tile
+    #[inline]
+    fn line_length(_rows: usize, columns: usize, tile: usize) -> usize {
+        gcd(tile, columns)
+    }
+
+    fn uses_tile() -> bool {
+        true
+    }
+
+    fn default_tile<T: Element>() -> usize {
+        block_sizes::<T>().tile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::error::MatrixError;
+    use super::super::standard::Matrix;
+    use aligned::AlignedBuffer;
+
+    #[test]
+    fn test_offsets() {
+        // a 5x3 matrix in 2x2 tiles, the tiles of the last row and column are cut
+        let offsets = (0..5).map(|i| (0..3).map(|j| Tiled::offset(5, 3, 2, i, j)).collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![vec![0, 1, 4], vec![2, 3, 5], vec![6, 7, 10], vec![8, 9, 11], vec![12, 13, 14]]);
+
+        // every element has its own position
+        let mut all = offsets.iter().flat_map(|row| row.iter().cloned()).collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, (0..15).collect::<Vec<_>>());
+
+        assert_eq!(RowMajor::offset(5, 3, 0, 4, 1), ColMajor::offset(3, 5, 0, 1, 4));
+        assert_eq!(Tiled::line_length(8, 12, 8), 4);
+        assert_eq!(Tiled::line_length(8, 16, 8), 8);
+    }
+
+    #[test]
+    fn test_tiled_matrix() {
+        let matrix = Matrix::<f64>::new(5, 3, (0..15).map(|i| i as f64).collect());
+        let tiled = matrix.to_layout_with_tile::<Tiled>(2);
+
+        assert_eq!(tiled.tile(), 2);
+        assert_eq!(tiled.data, vec![0., 1., 3., 4., 2., 5., 6., 7., 9., 10., 8., 11., 12., 13., 14.]);
+        for i in 0..5 {
+            for j in 0..3 {
+                assert_eq!(tiled[(i, j)], matrix[(i, j)]);
+            }
+        }
+        assert_eq!(tiled.to_layout::<RowMajor>(), matrix);
+
+        // new matrices use the tuned tile size, the dense layouts do not store one
+        assert_eq!(Matrix::<f64, Tiled>::zero(4, 4).tile(), block_sizes::<f64>().tile);
+        assert_eq!(matrix.tile(), 0);
+
+        let result = Matrix::<f64, Tiled>::try_from_buffer_with_tile(2, 2, 0, AlignedBuffer::from(vec![0.; 4]));
+        assert_eq!(result, Err(MatrixError::InvalidTile));
+    }
+}
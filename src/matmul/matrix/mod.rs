@@ -1,6 +1,18 @@
 pub mod traits;
 pub use self::traits::*;
 
+pub mod error;
+pub use self::error::{MatrixError, check_shapes};
+
+pub mod approx;
+pub use self::approx::{ApproxEq, Tolerance, Offender, worst_offender, approx_eq};
+
+pub mod element;
+pub use self::element::{Element, Complex};
+
+pub mod layout;
+pub use self::layout::{Layout, Dense, RowMajor, ColMajor, Tiled};
+
 pub mod standard;
 pub use self::standard::Matrix;
 
@@ -10,5 +22,17 @@ pub use self::tiled::TileMatrix;
 pub mod transposed;
 pub use self::transposed::TransposedMatrix;
 
+pub mod curve;
+pub use self::curve::{Curve, Morton, Hilbert, CurveMatrix, MortonMatrix, HilbertMatrix};
+
+pub mod view;
+pub use self::view::{MatrixView, MatrixViewMut};
+
 pub mod simd;
-pub use self::simd::SimdMatrix;
\ No newline at end of file
+pub use self::simd::SimdMatrix;
+
+pub mod generate;
+pub use self::generate::Generator;
+
+pub mod file;
+pub use self::file::{FileElement, Storable, read_mtx, write_mtx, write_mtx_coordinate, read_npy, write_npy, read_npz, write_npz, read_raw, write_raw, save, load};
@@ -1,9 +1,6 @@
 
 extern crate rand;
 
-extern crate simd;
-use self::simd::x86::avx::f64x4;
-
 use std;
 
 use std::fmt;
@@ -16,8 +13,12 @@ use std::convert::From;
 
 use super::traits::*;
 use super::Matrix;
+use super::element::Element;
+use super::error::MatrixError;
+
+use aligned::AlignedBuffer;
 
-/// A rust Matrix containing f64x4
+/// A rust Matrix containing T::SimdType, e.g. f64x4
 ///
 /// This is a row major matrix!
 ///
@@ -27,40 +28,40 @@ use super::Matrix;
 ///
 /// rows:       usize       The number of rows
 /// columns:    usize       The number of columns
-/// data:       Vec<f64x4>    The data stored in this matrix
+/// data:       AlignedBuffer<T::SimdType>    The data stored in this matrix
 ///
 /// data is allocated on the heap because rust has a limit of 2MB on its stack.
 ///
 /// ## Alignment
-/// If the matrix reports alignment via is_aligned(), data's content is aligned to 32 bytes and can
-/// be used as the f64x4 by simple pointer casting.
+/// If the matrix reports alignment via is_aligned(), data's content is aligned to the simd type of
+/// T (32 bytes for f64x4) and can be used as T::SimdType by simple pointer casting.
 ///
 /// ## Methods
 /// This struct only implements methods to create, alter and index itself. Operations have to be
 /// implemented somewhere else.
 ///
 #[derive(Debug, Clone)]
-pub struct SimdMatrix {
+pub struct SimdMatrix<T: Element = f64> {
     pub rows: usize,
     pub columns: usize,
     aligned: bool,
-    pub data: Vec<f64x4>, // force heap allocation
+    pub data: AlignedBuffer<T::SimdType>,
 }
 
-impl SimdMatrix {
+impl<T: Element> SimdMatrix<T> {
     /// Create a new, unaligned matrix from the given parts
-    pub fn new(rows: usize, columns: usize, data: Vec<f64x4>) -> Self {
+    pub fn new(rows: usize, columns: usize, data: Vec<T::SimdType>) -> Self {
         Self {
             rows: rows,
             columns: columns,
-            data: data,
+            data: AlignedBuffer::from(data),
             aligned: true,
         }
     }
 
     /// Reset all entries to zero
     pub fn reset(&mut self) {
-        let zero = f64x4::splat(0.0);
+        let zero = T::splat(T::zero());
         for v in &mut self.data {
             *v = zero;
         }
@@ -70,129 +71,149 @@ impl SimdMatrix {
     pub fn is_aligned(&self) -> bool {
         self.aligned
     }
+
+    /// The data as T, a simd vector consists of T::CHUNK_SIZE consecutive elements
+    pub fn elements(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr() as *const T, self.data.len() * T::CHUNK_SIZE) }
+    }
 }
 
 
-impl IndexUnchecked<isize> for SimdMatrix {
+impl<T: Element> IndexUnchecked<isize> for SimdMatrix<T> {
     #[inline]
-    unsafe fn get_unchecked(&self, index: isize) -> &f64x4 {
+    unsafe fn get_unchecked(&self, index: isize) -> &T::SimdType {
         &*self.data.as_ptr().offset(index as isize)
     }
+}
 
+impl<T: Element> IndexUncheckedMut<isize> for SimdMatrix<T> {
     #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: isize) -> &mut f64x4 {
+    unsafe fn get_unchecked_mut(&mut self, index: isize) -> &mut T::SimdType {
         &mut *self.data.as_mut_ptr().offset(index as isize)
     }
 }
 
-impl IndexUnchecked<usize> for SimdMatrix {
+impl<T: Element> IndexUnchecked<usize> for SimdMatrix<T> {
     #[inline]
-    unsafe fn get_unchecked(&self, index: usize) -> &f64x4 {
+    unsafe fn get_unchecked(&self, index: usize) -> &T::SimdType {
         &*self.data.as_ptr().offset(index as isize)
     }
+}
 
+impl<T: Element> IndexUncheckedMut<usize> for SimdMatrix<T> {
     #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut f64x4 {
+    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T::SimdType {
         &mut *self.data.as_mut_ptr().offset(index as isize)
     }
 }
 
-impl IndexUnchecked<(usize, usize)> for SimdMatrix {
+impl<T: Element> IndexUnchecked<(usize, usize)> for SimdMatrix<T> {
     #[inline]
-    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &f64x4 {
+    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &T::SimdType {
         let index = row * self.columns + column;
         &*self.data.as_ptr().offset(index as isize)
     }
+}
 
+impl<T: Element> IndexUncheckedMut<(usize, usize)> for SimdMatrix<T> {
     #[inline]
-    unsafe fn get_unchecked_mut(&mut self, (row, column): (usize, usize)) -> &mut f64x4 {
+    unsafe fn get_unchecked_mut(&mut self, (row, column): (usize, usize)) -> &mut T::SimdType {
         let index = row * self.columns + column;
         &mut *self.data.as_mut_ptr().offset(index as isize)
     }
 }
 
-impl Eq for SimdMatrix {}
+impl<T: Element> Eq for SimdMatrix<T> {}
 
-impl PartialEq<SimdMatrix> for SimdMatrix {
-    fn eq(&self, other: &SimdMatrix) -> bool {
+impl<T: Element> PartialEq<SimdMatrix<T>> for SimdMatrix<T> {
+    fn eq(&self, other: &SimdMatrix<T>) -> bool {
         self.columns == other.columns &&
-            self.rows == other.rows //&&
-            //self.data.iter().zip(other.data).all(|(lhs, rhs)| (lhs.eq(rhs)).all())
+            self.rows == other.rows &&
+            self.elements() == other.elements()
     }
 }
 
-impl Index<isize> for SimdMatrix {
-    type Output = f64x4;
+impl<T: Element> Index<isize> for SimdMatrix<T> {
+    type Output = T::SimdType;
 
     #[inline]
-    fn index(&self, index: isize) -> &f64x4 {
+    fn index(&self, index: isize) -> &T::SimdType {
         &self.data[index as usize]
     }
 }
 
-impl Index<i32> for SimdMatrix {
-    type Output = f64x4;
+impl<T: Element> Index<i32> for SimdMatrix<T> {
+    type Output = T::SimdType;
 
     #[inline]
-    fn index(&self, index: i32) -> &f64x4 {
+    fn index(&self, index: i32) -> &T::SimdType {
         &self.data[index as usize]
     }
 }
 
-impl Index<usize> for SimdMatrix {
-    type Output = f64x4;
+impl<T: Element> Index<usize> for SimdMatrix<T> {
+    type Output = T::SimdType;
 
     #[inline]
-    fn index(&self, index: usize) -> &f64x4 {
+    fn index(&self, index: usize) -> &T::SimdType {
         &self.data[index as usize]
     }
 }
 
-impl Index<(usize, usize)> for SimdMatrix {
-    type Output = f64x4;
+impl<T: Element> Index<(usize, usize)> for SimdMatrix<T> {
+    type Output = T::SimdType;
 
     #[inline]
-    fn index(&self, (row, column): (usize, usize)) -> &f64x4 {
+    fn index(&self, (row, column): (usize, usize)) -> &T::SimdType {
         &self.data[row * self.columns + column]
     }
 }
 
-impl IndexMut<isize> for SimdMatrix {
+impl<T: Element> IndexMut<isize> for SimdMatrix<T> {
    #[inline]
-    fn index_mut(&mut self, index: isize) -> &mut f64x4 {
+    fn index_mut(&mut self, index: isize) -> &mut T::SimdType {
         &mut self.data[index as usize]
     }
 }
 
-impl IndexMut<usize> for SimdMatrix {
+impl<T: Element> IndexMut<usize> for SimdMatrix<T> {
     #[inline]
-    fn index_mut(&mut self, index: usize) -> &mut f64x4 {
+    fn index_mut(&mut self, index: usize) -> &mut T::SimdType {
         &mut self.data[index as usize]
     }
 }
 
-impl IndexMut<(usize, usize)> for SimdMatrix {
+impl<T: Element> IndexMut<(usize, usize)> for SimdMatrix<T> {
     #[inline]
-    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut f64x4 {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T::SimdType {
         &mut self.data[row * self.columns + column]
     }
 }
 
-impl From<Matrix> for SimdMatrix {
-    fn from(matrix: Matrix) -> Self {
-        assert!(matrix.is_aligned());
+impl<T: Element> SimdMatrix<T> {
+    /// Reinterpret the data of an aligned matrix as simd vectors
+    pub fn try_from(matrix: Matrix<T>) -> Result<Self, MatrixError> {
+        if !matrix.is_aligned() {
+            return Err(MatrixError::Misaligned);
+        }
 
-         let vec = unsafe {
-             let ret = Vec::from_raw_parts(matrix.data.as_ptr() as *mut f64x4, matrix.data.len() / 4, matrix.data.capacity() / 4);
-             std::mem::forget(matrix.data);
-             ret
-         };
+        Ok(Self {
+            rows: matrix.rows,
+            columns: matrix.columns / T::CHUNK_SIZE,
+            data: unsafe { matrix.data.cast() },
+            aligned: true,
+        })
+    }
+}
 
-        Self::new(matrix.rows, matrix.columns / 4, vec)
+impl<T: Element> From<Matrix<T>> for SimdMatrix<T> {
+    /// Panics if the matrix is not aligned
+    fn from(matrix: Matrix<T>) -> Self {
+        Self::try_from(matrix).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
-impl fmt::Display for SimdMatrix {
+impl<T: Element> fmt::Display for SimdMatrix<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{}", std::iter::repeat("-").take(self.columns * 14 + 3).collect::<String>())?;
         self.data.chunks(self.columns).for_each(|row| {
@@ -206,3 +227,19 @@ impl fmt::Display for SimdMatrix {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq() {
+        let a = SimdMatrix::from(Matrix::<f64>::random(4, 8));
+        let mut b = a.clone();
+        assert_eq!(a, b);
+        assert_eq!(a.elements().len(), 32);
+
+        b = SimdMatrix::from(Matrix::<f64>::zero(4, 8));
+        assert!(a != b);
+    }
+}
@@ -1,22 +1,33 @@
 
 extern crate rand;
 
-extern crate simd;
-use self::simd::x86::avx::f64x4;
-
 use std;
 
 use std::fmt;
 
+use std::marker::PhantomData;
+
 use std::ops::{Index, IndexMut};
 
 use std::cmp::{PartialEq, Eq};
 
 use super::traits::*;
 
+use super::element::Element;
+
+use super::error::MatrixError;
+
+use super::layout::{Layout, Dense, RowMajor};
+
+use affinity::first_touch;
+
+use aligned::AlignedBuffer;
+
 /// A rust Matrix
 ///
-/// This is a row major matrix!
+/// The storage order is given by the layout L, this is a row major matrix by default!
+/// TransposedMatrix is the column major variant, Matrix<T, Tiled> stores square tiles of the size
+/// tile().
 ///
 /// # Properties
 /// ## Members
@@ -24,95 +35,137 @@ use super::traits::*;
 ///
 /// rows:       usize       The number of rows
 /// columns:    usize       The number of columns
-/// data:       Vec<f64>    The data stored in this matrix
+/// data:       AlignedBuffer<T>    The data stored in this matrix, in the order of L
+/// tile:       usize       The edge length of the tiles of Tiled, zero for the dense layouts
 ///
-/// data is allocated on the heap because rust has a limit of 2MB on its stack.
+/// data is allocated on the heap because rust has a limit of 2MB on its stack, with the alignment
+/// of aligned::alignment().
 ///
 /// ## Alignment
-/// If the matrix reports alignment via is_aligned(), data's content is aligned to 32 bytes and can
-/// be used as the f64x4 by simple pointer casting.
+/// If the matrix reports alignment via is_aligned(), data's content is aligned to the simd type of
+/// T (32 bytes for f64x4) and can be used as T::SimdType by simple pointer casting. The contiguous
+/// lines (rows for RowMajor, columns for ColMajor) are aligned if their length is a multiple of
+/// T::CHUNK_SIZE.
 ///
 /// ## Methods
-/// This struct only implements methods to create, alter and index itself. Operations have to be
-/// implemented somewhere else.
+/// This struct only implements methods to create, alter and index itself. The arithmetic operators
+/// are implemented in matmul::ops, other operations have to be implemented somewhere else.
 ///
 /// # Examples
 ///
 /// ```
-/// assert(Matrix::zero(4,4).is_aligned())
-/// assert(!(Matrix::zero(5,5).is_aligned()))
+/// use rust::matmul::Matrix;
+///
+/// assert!(Matrix::<f64>::zero(4, 4).is_aligned());
+/// assert!(!Matrix::<f64>::zero(5, 5).is_aligned());
 /// ```
 #[derive(Debug, PartialEq, Clone)]
-pub struct Matrix {
+pub struct Matrix<T = f64, L = RowMajor> {
     pub rows: usize,
     pub columns: usize,
     aligned: bool,
-    pub data: Vec<f64>, // force heap allocation
+    pub data: AlignedBuffer<T>,
+    tile: usize,
+    layout: PhantomData<L>,
 }
 
-impl Matrix {
-    /// Create a new, unaligned matrix from the given parts
-    pub fn new(rows: usize, columns: usize, data: Vec<f64>) -> Self {
-        Self {
+impl<T: Element, L: Layout> Matrix<T, L> {
+    /// Create a matrix with tiles of the given size from an aligned buffer
+    ///
+    /// The buffer must contain rows * columns elements in the order of L, the tile size is only
+    /// used by Tiled and has to be at least one there. The matrix is aligned if its lines are a
+    /// multiple of T::CHUNK_SIZE long and the buffer is aligned to T::SimdType.
+    pub fn try_from_buffer_with_tile(rows: usize, columns: usize, tile: usize, data: AlignedBuffer<T>) -> Result<Self, MatrixError> {
+        if data.len() != rows * columns {
+            return Err(MatrixError::DataLengthMismatch { expected: rows * columns, actual: data.len() });
+        }
+        if L::uses_tile() && tile == 0 {
+            return Err(MatrixError::InvalidTile);
+        }
+
+        let tile = if L::uses_tile() { tile } else { 0 };
+        let aligned = L::line_length(rows, columns, tile) % T::CHUNK_SIZE == 0
+            && data.alignment() % std::mem::size_of::<T::SimdType>() == 0;
+
+        Ok(Self {
             rows: rows,
             columns: columns,
+            aligned: aligned,
             data: data,
-            aligned: false,
-        }
+            tile: tile,
+            layout: PhantomData,
+        })
     }
 
-    /// Create a new, aligned matrix
+    /// Create a matrix from an aligned buffer, which must contain rows * columns elements
     ///
-    /// data is of type Vec<f64x4> in order to ensure it is correctly aligned and is casted to
-    /// Vec<f64> during construction.
-    pub fn new_aligned(rows: usize, columns: usize, data: Vec<f64x4>) -> Self {
-
-        let vec = unsafe {
-            let ret = Vec::from_raw_parts(data.as_ptr() as *mut f64, data.len() * 4, data.capacity() * 4);
-            std::mem::forget(data);
-            ret
+    /// Tiled matrices use the tile size of tuning::block_sizes.
+    pub fn try_from_buffer(rows: usize, columns: usize, data: AlignedBuffer<T>) -> Result<Self, MatrixError> {
+        Self::try_from_buffer_with_tile(rows, columns, L::default_tile::<T>(), data)
+    }
+
+    /// Create a matrix from an aligned buffer, panics if the length does not match
+    pub fn from_buffer(rows: usize, columns: usize, data: AlignedBuffer<T>) -> Self {
+        Self::try_from_buffer(rows, columns, data).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Create a new matrix from the given parts, data is copied into an aligned buffer
+    pub fn try_new(rows: usize, columns: usize, data: Vec<T>) -> Result<Self, MatrixError> {
+        Self::try_from_buffer(rows, columns, AlignedBuffer::from(data))
+    }
+
+    /// Create a new matrix from the given parts, panics if data does not contain rows * columns
+    /// elements
+    pub fn new(rows: usize, columns: usize, data: Vec<T>) -> Self {
+        Self::try_new(rows, columns, data).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Create a new matrix from simd vectors
+    ///
+    /// data is of type Vec<T::SimdType>, its elements are copied into an aligned buffer.
+    pub fn new_aligned(rows: usize, columns: usize, data: Vec<T::SimdType>) -> Self {
+        let elements = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const T, data.len() * T::CHUNK_SIZE)
         };
 
-        Self {
-            rows: rows,
-            columns: columns,
-            data: vec,
-            aligned: true,
-        }
+        Self::from_buffer(rows, columns, AlignedBuffer::from_slice(elements))
     }
 
     /// Create a zero matrix
     ///
-    /// This matrix is aligned if columns is a multiple of 4.
+    /// This matrix is aligned if its lines are a multiple of T::CHUNK_SIZE long.
+    /// Large matrices are initialized by the rayon workers, see affinity::first_touch.
     ///
     pub fn zero(rows: usize, columns: usize) -> Self {
-        if columns % 4 == 0 {
-            Self::new_aligned(rows, columns, vec![f64x4::splat(0.); rows * columns / 4])
-        }
-        else {
-            Self::new(rows, columns, vec![0 as f64; rows * columns])
-        }
+        Self::zero_with_tile(rows, columns, L::default_tile::<T>())
+    }
+
+    /// Create a zero matrix with tiles of the given size, see zero
+    pub fn zero_with_tile(rows: usize, columns: usize, tile: usize) -> Self {
+        Self::try_from_buffer_with_tile(rows, columns, tile, first_touch(rows * columns, |_| T::zero()))
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
     /// Create a "random" matrix
     ///
-    /// This matrix is aligned if columns is a multiple of 4.
-    /// The data itself is not random but 0..rows*columns if not aligned and 0..rows * columns / 4
-    /// with each value occurring 4 times if aligned
+    /// This matrix is aligned if its lines are a multiple of T::CHUNK_SIZE long.
+    /// The data itself is not random but 0..rows*columns if not aligned and
+    /// 0..rows * columns / T::CHUNK_SIZE with each value occurring T::CHUNK_SIZE times if aligned.
+    /// The small integers are exact in every element type, use generate::Generator for random data.
     ///
     pub fn random(rows: usize, columns: usize) -> Self {
-        if columns % 4 == 0 {
-            Self::new_aligned(rows, columns, (0..rows * columns / 4).map(|i| f64x4::splat(i as f64)).collect())
+        if L::line_length(rows, columns, L::default_tile::<T>()) % T::CHUNK_SIZE == 0 {
+            Self::from_buffer(rows, columns, first_touch(rows * columns, |i| T::from_usize(i / T::CHUNK_SIZE)))
         }
         else {
-            Self::new(rows, columns, (0..rows*columns).map(|i| i as f64).collect())
+            Self::from_buffer(rows, columns, first_touch(rows * columns, |i| T::from_usize(i)))
         }
     }
 
     /// Reset all entries to zero
     pub fn reset(&mut self) {
         for v in &mut self.data {
-            *v = 0.;
+            *v = T::zero();
         }
     }
 
@@ -120,116 +173,180 @@ impl Matrix {
     pub fn is_aligned(&self) -> bool {
         self.aligned
     }
+
+    /// The edge length of the tiles of a Tiled matrix, zero for the dense layouts
+    pub fn tile(&self) -> usize {
+        self.tile
+    }
+
+    /// Copy the matrix into the layout M
+    pub fn to_layout<M: Layout>(&self) -> Matrix<T, M> {
+        self.to_layout_with_tile(M::default_tile::<T>())
+    }
+
+    /// Copy the matrix into the layout M with tiles of the given size
+    pub fn to_layout_with_tile<M: Layout>(&self, tile: usize) -> Matrix<T, M> {
+        let mut ret = Matrix::<T, M>::zero_with_tile(self.rows, self.columns, tile);
+
+        for i in 0..self.rows {
+            for j in 0..self.columns {
+                ret[(i, j)] = self[(i, j)];
+            }
+        }
+
+        ret
+    }
+
+    /// The position of element (row, column) in data
+    #[inline]
+    fn offset(&self, row: usize, column: usize) -> usize {
+        L::offset(self.rows, self.columns, self.tile, row, column)
+    }
+}
+
+impl<T: Element, L: Dense> Matrix<T, L> {
+    /// Reinterpret the data as the transposed matrix in the transposed layout
+    ///
+    /// This does not move any element, a (m x n) row major matrix is the column major storage of
+    /// its (n x m) transpose and vice versa.
+    pub fn transpose(self) -> Matrix<T, L::Transposed> {
+        Matrix {
+            rows: self.columns,
+            columns: self.rows,
+            aligned: self.aligned,
+            data: self.data,
+            tile: 0,
+            layout: PhantomData,
+        }
+    }
+
+    /// The distance between two rows and two columns in elements
+    pub fn strides(&self) -> (usize, usize) {
+        L::strides(self.rows, self.columns)
+    }
 }
 
 
-impl IndexUnchecked<isize> for Matrix {
+impl<T: Element, L: Layout> IndexUnchecked<isize> for Matrix<T, L> {
     #[inline]
-    unsafe fn get_unchecked(&self, index: isize) -> &f64 {
+    unsafe fn get_unchecked(&self, index: isize) -> &T {
         &*self.data.as_ptr().offset(index as isize)
     }
+}
 
+impl<T: Element, L: Layout> IndexUncheckedMut<isize> for Matrix<T, L> {
     #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: isize) -> &mut f64 {
+    unsafe fn get_unchecked_mut(&mut self, index: isize) -> &mut T {
         &mut *self.data.as_mut_ptr().offset(index as isize)
     }
 }
 
-impl IndexUnchecked<usize> for Matrix {
+impl<T: Element, L: Layout> IndexUnchecked<usize> for Matrix<T, L> {
     #[inline]
-    unsafe fn get_unchecked(&self, index: usize) -> &f64 {
+    unsafe fn get_unchecked(&self, index: usize) -> &T {
         &*self.data.as_ptr().offset(index as isize)
     }
+}
 
+impl<T: Element, L: Layout> IndexUncheckedMut<usize> for Matrix<T, L> {
     #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut f64 {
+    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
         &mut *self.data.as_mut_ptr().offset(index as isize)
     }
 }
 
-impl IndexUnchecked<(usize, usize)> for Matrix {
+impl<T: Element, L: Layout> IndexUnchecked<(usize, usize)> for Matrix<T, L> {
     #[inline]
-    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &f64 {
-        let index = row * self.columns + column;
+    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &T {
+        let index = self.offset(row, column);
         &*self.data.as_ptr().offset(index as isize)
     }
+}
 
+impl<T: Element, L: Layout> IndexUncheckedMut<(usize, usize)> for Matrix<T, L> {
     #[inline]
-    unsafe fn get_unchecked_mut(&mut self, (row, column): (usize, usize)) -> &mut f64 {
-        let index = row * self.columns + column;
+    unsafe fn get_unchecked_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        let index = self.offset(row, column);
         &mut *self.data.as_mut_ptr().offset(index as isize)
     }
 }
 
-impl Eq for Matrix {}
+impl<T: Element, L: Layout> Eq for Matrix<T, L> {}
 
-impl Index<isize> for Matrix {
-    type Output = f64;
+impl<T: Element, L: Layout> Shape for Matrix<T, L> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+}
+
+impl<T: Element, L: Layout> Index<isize> for Matrix<T, L> {
+    type Output = T;
 
     #[inline]
-    fn index(&self, index: isize) -> &f64 {
+    fn index(&self, index: isize) -> &T {
         &self.data[index as usize]
     }
 }
 
-impl Index<i32> for Matrix {
-    type Output = f64;
+impl<T: Element, L: Layout> Index<i32> for Matrix<T, L> {
+    type Output = T;
 
     #[inline]
-    fn index(&self, index: i32) -> &f64 {
+    fn index(&self, index: i32) -> &T {
         &self.data[index as usize]
     }
 }
 
-impl Index<usize> for Matrix {
-    type Output = f64;
+impl<T: Element, L: Layout> Index<usize> for Matrix<T, L> {
+    type Output = T;
 
     #[inline]
-    fn index(&self, index: usize) -> &f64 {
+    fn index(&self, index: usize) -> &T {
         &self.data[index as usize]
     }
 }
 
-impl Index<(usize, usize)> for Matrix {
-    type Output = f64;
+impl<T: Element, L: Layout> Index<(usize, usize)> for Matrix<T, L> {
+    type Output = T;
 
     #[inline]
-    fn index(&self, (row, column): (usize, usize)) -> &f64 {
-        &self.data[row * self.columns + column]
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        &self.data[self.offset(row, column)]
     }
 }
 
-impl IndexMut<isize> for Matrix {
+impl<T: Element, L: Layout> IndexMut<isize> for Matrix<T, L> {
    #[inline]
-    fn index_mut(&mut self, index: isize) -> &mut f64 {
+    fn index_mut(&mut self, index: isize) -> &mut T {
         &mut self.data[index as usize]
     }
 }
 
-impl IndexMut<usize> for Matrix {
+impl<T: Element, L: Layout> IndexMut<usize> for Matrix<T, L> {
     #[inline]
-    fn index_mut(&mut self, index: usize) -> &mut f64 {
+    fn index_mut(&mut self, index: usize) -> &mut T {
         &mut self.data[index as usize]
     }
 }
 
-impl IndexMut<(usize, usize)> for Matrix {
+impl<T: Element, L: Layout> IndexMut<(usize, usize)> for Matrix<T, L> {
     #[inline]
-    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut f64 {
-        &mut self.data[row * self.columns + column]
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        let offset = self.offset(row, column);
+        &mut self.data[offset]
     }
 }
 
-impl fmt::Display for Matrix {
+impl<T: Element, L: Layout> fmt::Display for Matrix<T, L> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{}", std::iter::repeat("-").take(self.columns * 14 + 3).collect::<String>())?;
-        self.data.chunks(self.columns).for_each(|row| {
-            write!(f, "| ");
-            for item in row {
-                write!(f, "{:>12}, ", item);
+        for i in 0..self.rows {
+            write!(f, "| ")?;
+            for j in 0..self.columns {
+                write!(f, "{:>12}, ", self[(i, j)])?;
             }
-            writeln!(f, "|");
-        });
+            writeln!(f, "|")?;
+        }
         writeln!(f, "{}", std::iter::repeat("-").take(self.columns * 14 + 3).collect::<String>())
 
     }
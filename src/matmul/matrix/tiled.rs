@@ -1,6 +1,14 @@
 
 use super::standard::Matrix;
 
+use super::element::Element;
+
+use super::error::MatrixError;
+
+use super::traits::Shape;
+
+use super::super::tuning::block_sizes;
+
 use std;
 
 use std::ops::{Index, IndexMut};
@@ -14,7 +22,9 @@ use std::fmt;
 
 /// A rust tiled Matrix
 ///
-/// This is a row major matrix!
+/// This is a row major matrix! The blocks are separate matrices, which the tiled, strassen and
+/// winograd kernels multiply as a whole. Matrix<T, Tiled> stores the tiles in one buffer instead,
+/// for the kernels which are generic over the layout.
 ///
 /// # Properties
 /// ## Members
@@ -24,45 +34,47 @@ use std::fmt;
 /// columns:        usize       The number of columns
 /// blocks_right:   usize       The number of blocks in the first dimension. The last block might be padded.
 /// blocks_down:    usize       The number of blocks in the second dimension. The last block might be padded.
-/// data:           Vec<Matrix> The matrix elements stored in this matrix
+/// data:           Vec<Matrix<T>> The matrix elements stored in this matrix
 ///
 /// data is allocated on the heap because rust has a limit of 2MB on its stack.
 ///
 /// ## Alignment
-/// If the matrix reports alignment via is_aligned(), data's content is aligned to 32 bytes and can
-/// be used as the f64x4 by simple pointer casting.
+/// If the matrix reports alignment via is_aligned(), data's content is aligned to the simd type of
+/// T (32 bytes for f64x4) and can be used as T::SimdType by simple pointer casting.
 ///
 /// ## Methods
-/// This struct only implements methods to create, alter and index itself. Operations have to be
-/// implemented somewhere else.
+/// This struct only implements methods to create, alter and index itself. The arithmetic operators
+/// are implemented in matmul::ops, other operations have to be implemented somewhere else.
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct TileMatrix {
+pub struct TileMatrix<T = f64> {
     pub rows: usize,
     pub columns: usize,
     pub blocks_right: usize,
     pub blocks_down: usize,
-    pub data: Vec<Matrix>, // force heap allocation
+    pub data: Vec<Matrix<T>>, // force heap allocation
     block_rows: usize,
     block_columns: usize,
 }
 
-impl TileMatrix {
-    const BLOCK_SIZE: usize = 128; // best for naive_simd algorithm
-
+impl<T: Element> TileMatrix<T> {
     /// Create a new tiled matrix from the given parts
-    pub fn new(blocks_right: usize, blocks_down: usize, data: Vec<Matrix>) -> Self {
-        assert_eq!(data.len(), blocks_right * blocks_down);
+    ///
+    /// data contains the blocks row by row, there has to be at least one and all have the same
+    /// size.
+    pub fn try_new(blocks_right: usize, blocks_down: usize, data: Vec<Matrix<T>>) -> Result<Self, MatrixError> {
+        if data.is_empty() || data.len() != blocks_right * blocks_down {
+            return Err(MatrixError::DataLengthMismatch { expected: blocks_right * blocks_down, actual: data.len() });
+        }
+
         let r = data[0].rows;
         let c = data[0].columns;
 
-        for matrix in &data {
-            assert_eq!(r, matrix.rows);
-            assert_eq!(c, matrix.columns);
+        if let Some(matrix) = data.iter().find(|matrix| matrix.rows != r || matrix.columns != c) {
+            return Err(MatrixError::ShapeMismatch { expected: (r, c), actual: (matrix.rows, matrix.columns) });
         }
 
-
-        Self {
+        Ok(Self {
             rows: r * blocks_down,
             columns: c * blocks_right,
             blocks_right: blocks_right,
@@ -70,10 +82,15 @@ impl TileMatrix {
             data: data,
             block_rows: r,
             block_columns: c,
-        }
+        })
+    }
+
+    /// Create a new tiled matrix from the given parts, panics if they do not fit together
+    pub fn new(blocks_right: usize, blocks_down: usize, data: Vec<Matrix<T>>) -> Self {
+        Self::try_new(blocks_right, blocks_down, data).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub fn new_with_size(rows: usize, columns: usize, blocks_right: usize, blocks_down: usize, data: Vec<Matrix>) -> Self {
+    pub fn new_with_size(rows: usize, columns: usize, blocks_right: usize, blocks_down: usize, data: Vec<Matrix<T>>) -> Self {
         let mut ret = Self::new(blocks_right, blocks_down, data);
         ret.rows = rows;
         ret.columns = columns;
@@ -82,7 +99,7 @@ impl TileMatrix {
 
     /// Create a zero matrix
     ///
-    /// This matrix is aligned if columns is a multiple of 4 * blocks_right.
+    /// This matrix is aligned if columns is a multiple of T::CHUNK_SIZE * blocks_right.
     ///
     pub fn zero(rows: usize, columns: usize, blocks_right: usize, blocks_down: usize) -> Self {
 
@@ -97,9 +114,8 @@ impl TileMatrix {
 
     /// Create a "random" matrix
     ///
-    /// This matrix is aligned if columns is a multiple of 4.
-    /// The data itself is not random but 0..rows*columns if not aligned and 0..rows * columns / 4
-    /// with each value occurring 4 times if aligned
+    /// This matrix is aligned if columns is a multiple of T::CHUNK_SIZE * blocks_right.
+    /// Every block is filled like Matrix::random
     ///
     pub fn random(rows: usize, columns: usize, blocks_right: usize, blocks_down: usize) -> Self {
 
@@ -127,121 +143,83 @@ impl TileMatrix {
 
 }
 
-pub trait IndexUnchecked<T> : Index<T> {
-    #[inline]
-    unsafe fn get_unchecked(&self, index: T) -> &Self::Output;
-
-    #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: T) -> &mut Self::Output;
-}
-
-impl IndexUnchecked<isize> for Matrix {
-    #[inline]
-    unsafe fn get_unchecked(&self, index: isize) -> &f64 {
-        &*self.data.as_ptr().offset(index as isize)
-    }
-
-    #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: isize) -> &mut f64 {
-        &mut *self.data.as_mut_ptr().offset(index as isize)
-    }
-}
-
-impl IndexUnchecked<usize> for Matrix {
-    #[inline]
-    unsafe fn get_unchecked(&self, index: usize) -> &f64 {
-        &*self.data.as_ptr().offset(index as isize)
-    }
-
-    #[inline]
-    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut f64 {
-        &mut *self.data.as_mut_ptr().offset(index as isize)
-    }
-}
-
-impl IndexUnchecked<(usize, usize)> for Matrix {
-    #[inline]
-    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &f64 {
-        let index = row * self.columns + column;
-        &*self.data.as_ptr().offset(index as isize)
-    }
+impl<T: Element> Eq for TileMatrix<T> {}
 
-    #[inline]
-    unsafe fn get_unchecked_mut(&mut self, (row, column): (usize, usize)) -> &mut f64 {
-        let index = row * self.columns + column;
-        &mut *self.data.as_mut_ptr().offset(index as isize)
+impl<T: Element> Shape for TileMatrix<T> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
     }
 }
 
-impl Eq for TileMatrix {}
-
-impl Index<isize> for TileMatrix {
-    type Output = Matrix;
+impl<T: Element> Index<isize> for TileMatrix<T> {
+    type Output = Matrix<T>;
 
     #[inline]
-    fn index(&self, index: isize) -> &Matrix {
+    fn index(&self, index: isize) -> &Matrix<T> {
         &self.data[index as usize]
     }
 }
 
-impl Index<i32> for TileMatrix {
-    type Output = Matrix;
+impl<T: Element> Index<i32> for TileMatrix<T> {
+    type Output = Matrix<T>;
 
     #[inline]
-    fn index(&self, index: i32) -> &Matrix {
+    fn index(&self, index: i32) -> &Matrix<T> {
         &self.data[index as usize]
     }
 }
 
-impl Index<usize> for TileMatrix {
-    type Output = Matrix;
+impl<T: Element> Index<usize> for TileMatrix<T> {
+    type Output = Matrix<T>;
 
     #[inline]
-    fn index(&self, index: usize) -> &Matrix {
+    fn index(&self, index: usize) -> &Matrix<T> {
         &self.data[index as usize]
     }
 }
 
-impl Index<(usize, usize)> for TileMatrix {
-    type Output = Matrix;
+impl<T: Element> Index<(usize, usize)> for TileMatrix<T> {
+    type Output = Matrix<T>;
 
     #[inline]
-    fn index(&self, (row, column): (usize, usize)) -> &Matrix {
+    fn index(&self, (row, column): (usize, usize)) -> &Matrix<T> {
         &self.data[row * self.blocks_right + column]
     }
 }
 
 
-impl IndexMut<isize> for TileMatrix {
+impl<T: Element> IndexMut<isize> for TileMatrix<T> {
    #[inline]
-    fn index_mut(&mut self, index: isize) -> &mut Matrix {
+    fn index_mut(&mut self, index: isize) -> &mut Matrix<T> {
         &mut self.data[index as usize]
     }
 }
 
-impl IndexMut<usize> for TileMatrix {
+impl<T: Element> IndexMut<usize> for TileMatrix<T> {
     #[inline]
-    fn index_mut(&mut self, index: usize) -> &mut Matrix {
+    fn index_mut(&mut self, index: usize) -> &mut Matrix<T> {
         &mut self.data[index as usize]
     }
 }
 
-impl IndexMut<(usize, usize)> for TileMatrix {
+impl<T: Element> IndexMut<(usize, usize)> for TileMatrix<T> {
     #[inline]
-    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut Matrix {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut Matrix<T> {
         &mut self.data[row * self.blocks_right + column]
     }
 }
 
-impl From<Matrix> for TileMatrix {
-    fn from(matrix: Matrix) -> Self {
-        if matrix.rows < Self::BLOCK_SIZE || matrix.columns < Self::BLOCK_SIZE {
+impl<T: Element> TileMatrix<T> {
+    /// Split the matrix into square blocks of size bs
+    ///
+    /// Matrices smaller than a block are not split.
+    pub fn with_block_size(matrix: Matrix<T>, bs: usize) -> Self {
+        if matrix.rows < bs || matrix.columns < bs {
             Self::new(1,1, vec![matrix])
         }
         else {
             let rows = matrix.rows;
             let columns = matrix.columns;
-            let bs = Self::BLOCK_SIZE;
 
             let blocks_down = if rows % bs == 0 { rows / bs } else { rows / bs + 1 };
             let blocks_right = if columns % bs == 0 { columns / bs } else { columns / bs + 1 };
@@ -285,7 +263,29 @@ impl From<Matrix> for TileMatrix {
     }
 }
 
-impl fmt::Display for TileMatrix {
+impl<T: Element> From<Matrix<T>> for TileMatrix<T> {
+    /// Split the matrix into square blocks of tuning::block_sizes::<T>().tile
+    fn from(matrix: Matrix<T>) -> Self {
+        Self::with_block_size(matrix, block_sizes::<T>().tile)
+    }
+}
+
+impl<T: Element> From<TileMatrix<T>> for Matrix<T> {
+    fn from(tiled: TileMatrix<T>) -> Self {
+        let mut matrix = Matrix::zero(tiled.rows, tiled.columns);
+
+        for i in 0..tiled.rows {
+            for j in 0..tiled.columns {
+                let block = &tiled[(i / tiled.block_rows, j / tiled.block_columns)];
+                matrix[(i, j)] = block[(i % tiled.block_rows, j % tiled.block_columns)];
+            }
+        }
+
+        matrix
+    }
+}
+
+impl<T: Element> fmt::Display for TileMatrix<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{}", std::iter::repeat("-").take(self.columns * 10 + 2 * self.blocks_right + 1 ).collect::<String>())?;
 
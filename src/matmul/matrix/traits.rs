@@ -4,7 +4,15 @@ use std::ops::{Index, IndexMut};
 pub trait IndexUnchecked<T> : Index<T> {
     #[inline]
     unsafe fn get_unchecked(&self, index: T) -> &Self::Output;
+}
 
+/// The mutable counterpart of IndexUnchecked, read only views only implement IndexUnchecked
+pub trait IndexUncheckedMut<T> : IndexUnchecked<T> + IndexMut<T> {
     #[inline]
     unsafe fn get_unchecked_mut(&mut self, index: T) -> &mut Self::Output;
-}
\ No newline at end of file
+}
+
+/// The number of rows and columns of a matrix
+pub trait Shape {
+    fn shape(&self) -> (usize, usize);
+}
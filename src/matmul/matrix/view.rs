@@ -0,0 +1,412 @@
+
+use std;
+
+use std::marker::PhantomData;
+
+use std::ops::{Index, IndexMut};
+
+use super::element::Element;
+
+use super::standard::Matrix;
+
+use super::traits::{Shape, IndexUnchecked, IndexUncheckedMut};
+
+/// A borrowed, read only block of a row major matrix
+///
+/// # Properties
+/// ## Members
+/// rows:       usize       The number of rows
+/// columns:    usize       The number of columns
+/// ld:         usize       The leading dimension, the distance between two rows in elements
+///
+/// Element (i, j) is stored at i * ld + j relative to the first element. A view of a whole Matrix
+/// has ld == columns, a submatrix keeps the leading dimension of its parent, so no data is copied.
+///
+/// ## Alignment
+/// If the view reports alignment via is_aligned(), every row starts at an address aligned to the
+/// simd type of T and can be used as T::SimdType by simple pointer casting.
+#[derive(Debug, Clone, Copy)]
+pub struct MatrixView<'a, T: 'a = f64> {
+    pub rows: usize,
+    pub columns: usize,
+    pub ld: usize,
+    ptr: *const T,
+    _marker: PhantomData<&'a T>,
+}
+
+/// A borrowed, mutable block of a row major matrix
+///
+/// This is the mutable counterpart of MatrixView. Splitting a view with split_rows or
+/// split_columns results in two disjoint views, which can be used by different threads.
+#[derive(Debug)]
+pub struct MatrixViewMut<'a, T: 'a = f64> {
+    pub rows: usize,
+    pub columns: usize,
+    pub ld: usize,
+    ptr: *mut T,
+    _marker: PhantomData<&'a mut T>,
+}
+
+// views behave like slices of T
+unsafe impl<'a, T: Sync> Send for MatrixView<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for MatrixView<'a, T> {}
+unsafe impl<'a, T: Send> Send for MatrixViewMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for MatrixViewMut<'a, T> {}
+
+/// The number of elements a (rows x columns) view with leading dimension ld spans
+fn span(rows: usize, columns: usize, ld: usize) -> usize {
+    if rows == 0 || columns == 0 { 0 } else { (rows - 1) * ld + columns }
+}
+
+fn is_aligned<T: Element>(ptr: *const T, ld: usize) -> bool {
+    ptr as usize % std::mem::align_of::<T::SimdType>() == 0 && ld % T::CHUNK_SIZE == 0
+}
+
+impl<'a, T: Element> MatrixView<'a, T> {
+    /// Create a view of a (rows x columns) matrix with leading dimension ld stored in data
+    pub fn new(rows: usize, columns: usize, ld: usize, data: &'a [T]) -> Self {
+        assert!(ld >= columns, "the leading dimension must be at least the number of columns");
+        assert!(data.len() >= span(rows, columns, ld), "data is too short for the view");
+
+        Self {
+            rows: rows,
+            columns: columns,
+            ld: ld,
+            ptr: data.as_ptr(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The view of the (rows x columns) block starting at (row, column)
+    pub fn submatrix(&self, row: usize, column: usize, rows: usize, columns: usize) -> MatrixView<'a, T> {
+        assert!(row + rows <= self.rows && column + columns <= self.columns, "the submatrix is out of bounds");
+
+        MatrixView {
+            rows: rows,
+            columns: columns,
+            ld: self.ld,
+            ptr: unsafe { self.ptr.offset((row * self.ld + column) as isize) },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Split the view into the rows before and after row
+    pub fn split_rows(&self, row: usize) -> (MatrixView<'a, T>, MatrixView<'a, T>) {
+        (self.submatrix(0, 0, row, self.columns), self.submatrix(row, 0, self.rows - row, self.columns))
+    }
+
+    /// Split the view into the columns before and after column
+    pub fn split_columns(&self, column: usize) -> (MatrixView<'a, T>, MatrixView<'a, T>) {
+        (self.submatrix(0, 0, self.rows, column), self.submatrix(0, column, self.rows, self.columns - column))
+    }
+
+    /// The elements of row i
+    pub fn row(&self, i: usize) -> &'a [T] {
+        assert!(i < self.rows);
+        unsafe { std::slice::from_raw_parts(self.ptr.offset((i * self.ld) as isize), self.columns) }
+    }
+
+    /// The pointer to the first element
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+
+    /// Check whether every row of this view is aligned and can be used in simd pointer casts
+    pub fn is_aligned(&self) -> bool {
+        is_aligned(self.ptr, self.ld)
+    }
+
+    /// Copy the view into a dense matrix
+    pub fn to_matrix(&self) -> Matrix<T> {
+        let mut data = Vec::with_capacity(self.rows * self.columns);
+        for i in 0..self.rows {
+            data.extend_from_slice(self.row(i));
+        }
+        Matrix::new(self.rows, self.columns, data)
+    }
+}
+
+impl<'a, T: Element> Shape for MatrixView<'a, T> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+}
+
+impl<'a, T: Element> Shape for MatrixViewMut<'a, T> {
+    fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+}
+
+impl<'a, T: Element> Index<(usize, usize)> for MatrixView<'a, T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        assert!(row < self.rows && column < self.columns, "index out of bounds");
+        unsafe { self.get_unchecked((row, column)) }
+    }
+}
+
+impl<'a, T: Element> IndexUnchecked<(usize, usize)> for MatrixView<'a, T> {
+    #[inline]
+    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &T {
+        &*self.ptr.offset((row * self.ld + column) as isize)
+    }
+}
+
+impl<'a, T: Element> MatrixViewMut<'a, T> {
+    /// Create a mutable view of a (rows x columns) matrix with leading dimension ld stored in data
+    pub fn new(rows: usize, columns: usize, ld: usize, data: &'a mut [T]) -> Self {
+        assert!(ld >= columns, "the leading dimension must be at least the number of columns");
+        assert!(data.len() >= span(rows, columns, ld), "data is too short for the view");
+
+        Self {
+            rows: rows,
+            columns: columns,
+            ld: ld,
+            ptr: data.as_mut_ptr(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// A read only view of the same block
+    pub fn as_view(&self) -> MatrixView<T> {
+        MatrixView {
+            rows: self.rows,
+            columns: self.columns,
+            ld: self.ld,
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// A shorter lived mutable view of the same block
+    pub fn reborrow(&mut self) -> MatrixViewMut<T> {
+        MatrixViewMut {
+            rows: self.rows,
+            columns: self.columns,
+            ld: self.ld,
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The mutable view of the (rows x columns) block starting at (row, column)
+    pub fn submatrix(self, row: usize, column: usize, rows: usize, columns: usize) -> MatrixViewMut<'a, T> {
+        assert!(row + rows <= self.rows && column + columns <= self.columns, "the submatrix is out of bounds");
+
+        MatrixViewMut {
+            rows: rows,
+            columns: columns,
+            ld: self.ld,
+            ptr: unsafe { self.ptr.offset((row * self.ld + column) as isize) },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Split the view into the disjoint rows before and after row
+    pub fn split_rows(self, row: usize) -> (MatrixViewMut<'a, T>, MatrixViewMut<'a, T>) {
+        assert!(row <= self.rows, "the split is out of bounds");
+
+        let (rows, columns, ld, ptr) = (self.rows, self.columns, self.ld, self.ptr);
+        let part = |offset: usize, rows: usize| MatrixViewMut {
+            rows: rows,
+            columns: columns,
+            ld: ld,
+            ptr: unsafe { ptr.offset(offset as isize) },
+            _marker: PhantomData,
+        };
+
+        (part(0, row), part(row * ld, rows - row))
+    }
+
+    /// Split the view into the disjoint columns before and after column
+    pub fn split_columns(self, column: usize) -> (MatrixViewMut<'a, T>, MatrixViewMut<'a, T>) {
+        assert!(column <= self.columns, "the split is out of bounds");
+
+        let (rows, columns, ld, ptr) = (self.rows, self.columns, self.ld, self.ptr);
+        let part = |offset: usize, columns: usize| MatrixViewMut {
+            rows: rows,
+            columns: columns,
+            ld: ld,
+            ptr: unsafe { ptr.offset(offset as isize) },
+            _marker: PhantomData,
+        };
+
+        (part(0, column), part(column, columns - column))
+    }
+
+    /// Split the view into its disjoint mutable rows, e.g. to distribute them over threads
+    pub fn into_rows(self) -> Vec<&'a mut [T]> {
+        (0..self.rows).map(|i| unsafe {
+            std::slice::from_raw_parts_mut(self.ptr.offset((i * self.ld) as isize), self.columns)
+        }).collect()
+    }
+
+    /// The elements of row i
+    pub fn row(&self, i: usize) -> &[T] {
+        assert!(i < self.rows);
+        unsafe { std::slice::from_raw_parts(self.ptr.offset((i * self.ld) as isize), self.columns) }
+    }
+
+    /// The mutable elements of row i
+    pub fn row_mut(&mut self, i: usize) -> &mut [T] {
+        assert!(i < self.rows);
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.offset((i * self.ld) as isize), self.columns) }
+    }
+
+    /// The pointer to the first element
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+
+    /// The mutable pointer to the first element
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr
+    }
+
+    /// Check whether every row of this view is aligned and can be used in simd pointer casts
+    pub fn is_aligned(&self) -> bool {
+        is_aligned(self.ptr as *const T, self.ld)
+    }
+
+    /// Set all entries of the view to zero, the elements between the rows are not touched
+    pub fn reset(&mut self) {
+        for i in 0..self.rows {
+            for v in self.row_mut(i) {
+                *v = T::zero();
+            }
+        }
+    }
+}
+
+impl<'a, T: Element> Index<(usize, usize)> for MatrixViewMut<'a, T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        assert!(row < self.rows && column < self.columns, "index out of bounds");
+        unsafe { self.get_unchecked((row, column)) }
+    }
+}
+
+impl<'a, T: Element> IndexMut<(usize, usize)> for MatrixViewMut<'a, T> {
+    #[inline]
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        assert!(row < self.rows && column < self.columns, "index out of bounds");
+        unsafe { self.get_unchecked_mut((row, column)) }
+    }
+}
+
+impl<'a, T: Element> IndexUnchecked<(usize, usize)> for MatrixViewMut<'a, T> {
+    #[inline]
+    unsafe fn get_unchecked(&self, (row, column): (usize, usize)) -> &T {
+        &*self.ptr.offset((row * self.ld + column) as isize)
+    }
+}
+
+impl<'a, T: Element> IndexUncheckedMut<(usize, usize)> for MatrixViewMut<'a, T> {
+    #[inline]
+    unsafe fn get_unchecked_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        &mut *self.ptr.offset((row * self.ld + column) as isize)
+    }
+}
+
+impl<T: Element> Matrix<T> {
+    /// A view of the whole matrix
+    pub fn view(&self) -> MatrixView<T> {
+        MatrixView::new(self.rows, self.columns, self.columns, &self.data)
+    }
+
+    /// A mutable view of the whole matrix
+    pub fn view_mut(&mut self) -> MatrixViewMut<T> {
+        let (rows, columns) = (self.rows, self.columns);
+        MatrixViewMut::new(rows, columns, columns, &mut self.data)
+    }
+
+    /// A view of the (rows x columns) block starting at (row, column)
+    pub fn submatrix(&self, row: usize, column: usize, rows: usize, columns: usize) -> MatrixView<T> {
+        self.view().submatrix(row, column, rows, columns)
+    }
+
+    /// A mutable view of the (rows x columns) block starting at (row, column)
+    pub fn submatrix_mut(&mut self, row: usize, column: usize, rows: usize, columns: usize) -> MatrixViewMut<T> {
+        self.view_mut().submatrix(row, column, rows, columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let matrix = Matrix::<f64>::new(3, 4, (0..12).map(|i| i as f64).collect());
+        let view = matrix.view();
+
+        assert_eq!((view.rows, view.columns, view.ld), (3, 4, 4));
+        assert_eq!(view[(2, 1)], 9.);
+        assert_eq!(view.row(1), &[4., 5., 6., 7.]);
+        assert_eq!(view.to_matrix(), matrix);
+
+        let sub = matrix.submatrix(1, 1, 2, 2);
+        assert_eq!(sub.ld, 4);
+        assert_eq!(sub.to_matrix(), Matrix::new(2, 2, vec![5., 6., 9., 10.]));
+
+        let (top, bottom) = sub.split_rows(1);
+        assert_eq!(top.row(0), &[5., 6.]);
+        assert_eq!(bottom.row(0), &[9., 10.]);
+
+        let (left, right) = view.split_columns(3);
+        assert_eq!(left.columns, 3);
+        assert_eq!(right.to_matrix(), Matrix::new(3, 1, vec![3., 7., 11.]));
+    }
+
+    #[test]
+    fn test_view_mut() {
+        let mut matrix = Matrix::<f64>::zero(4, 4);
+
+        {
+            let (top, bottom) = matrix.view_mut().split_rows(2);
+            let (mut top_left, mut top_right) = top.split_columns(2);
+            let mut bottom = bottom.submatrix(1, 1, 1, 2);
+
+            top_left[(1, 1)] = 1.;
+            top_right[(0, 0)] = 2.;
+            bottom[(0, 1)] = 3.;
+        }
+
+        assert_eq!(matrix[(1, 1)], 1.);
+        assert_eq!(matrix[(0, 2)], 2.);
+        assert_eq!(matrix[(3, 2)], 3.);
+
+        for row in matrix.submatrix_mut(1, 1, 2, 2).into_rows() {
+            row[1] = 4.;
+        }
+        assert_eq!(matrix[(1, 2)], 4.);
+        assert_eq!(matrix[(2, 2)], 4.);
+
+        matrix.submatrix_mut(0, 0, 3, 4).reset();
+        assert_eq!(matrix.data.iter().filter(|&&x| x != 0.).count(), 1);
+    }
+
+    #[test]
+    fn test_alignment() {
+        let matrix = Matrix::<f64>::zero(8, 8);
+
+        assert!(matrix.view().is_aligned());
+        assert!(matrix.submatrix(1, 4, 4, 4).is_aligned());
+        assert!(!matrix.submatrix(0, 1, 4, 4).is_aligned());
+
+        let data = vec![0.; 15];
+        assert!(!MatrixView::new(3, 3, 5, &data).is_aligned());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_bounds() {
+        let matrix = Matrix::<f64>::zero(4, 4);
+        matrix.submatrix(2, 2, 3, 2);
+    }
+}
@@ -17,11 +17,77 @@ pub mod blocked;
 pub mod tiled;
 pub mod tiled_rayon;
 
+pub mod strassen;
+pub mod winograd;
+
+pub mod recursive;
+pub mod space_filling;
+
 pub mod asm;
+pub mod packed;
+
+pub mod gemm;
+
+pub mod ops;
+pub use self::ops::MatProduct;
+
+pub mod tuning;
+pub mod autotune;
+
+pub mod differential;
 
 pub mod matrix;
 pub use self::matrix::*;
 
+pub mod kernel;
+pub use self::kernel::*;
+
+/// All registered kernels
+pub static KERNELS: &'static [&'static Kernel] = &[
+    &naive::Naive,
+    &naive_unchecked::NaiveUnchecked,
+    &naive_reordered::NaiveReordered,
+    &naive_transposed::NaiveTransposed,
+    &naive_simd::NaiveSimd,
+    &naive_rayon::NaiveRayon,
+    &iter::Iter,
+    &blocked::Blocked,
+    &tiled::Tiled,
+    &tiled_rayon::TiledRayon,
+    &strassen::Strassen,
+    &winograd::Winograd,
+    &recursive::Recursive,
+    &space_filling::MortonRecursive,
+    &space_filling::HilbertRecursive,
+    &asm::Asm,
+    &packed::Packed,
+];
+
+/// Find a registered kernel by its name
+pub fn find_kernel(name: &str) -> Option<&'static Kernel> {
+    KERNELS.iter().find(|kernel| kernel.name() == name).map(|kernel| *kernel)
+}
+
+/// All kernels which work on views, these are the kernels of `KERNELS` on row major matrices
+pub static VIEW_KERNELS: &'static [&'static ViewKernel] = &[
+    &naive::Naive,
+    &naive_unchecked::NaiveUnchecked,
+    &naive_reordered::NaiveReordered,
+    &naive_simd::NaiveSimd,
+    &naive_rayon::NaiveRayon,
+    &recursive::Recursive,
+    &packed::Packed,
+];
+
+/// Find a kernel which works on views by its name
+///
+/// Kernels which need another layout than row major, e.g. the transposed, tiled and asm
+/// kernels, would have to copy the blocks and are rejected with `MatrixError::UnsupportedView`.
+pub fn find_view_kernel(name: &str) -> Result<&'static ViewKernel, MatrixError> {
+    VIEW_KERNELS.iter().find(|kernel| kernel.name() == name).map(|kernel| *kernel)
+        .ok_or(MatrixError::UnsupportedView)
+}
+
 #[cfg(test)]
 mod test {
 
@@ -33,9 +99,9 @@ mod test {
     fn test_equal () {
         let n = 256;
 
-        let a = Matrix::random(n, n);
-        let b = Matrix::random(n, n);
-        let mut compare = Matrix::zero(n, n);
+        let a = Matrix::<f64>::random(n, n);
+        let b = Matrix::<f64>::random(n, n);
+        let mut compare = Matrix::<f64>::zero(n, n);
         naive::mult(&a, &b, &mut compare);
 
         let a_t = TileMatrix::from(a.clone());
@@ -69,6 +135,15 @@ mod test {
         assert_eq!(compare, res);
         res.reset();
 
+        // the layout generic kernels also work on a row major B
+        blocked::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        naive_transposed::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
         iter::mult(&a, &bT, &mut res);
         assert_eq!(compare, res);
         res.reset();
@@ -81,10 +156,30 @@ mod test {
         assert_eq!(compare_t, res_t);
         res_t.reset();
 
+        strassen::mult(&a_t, &b_t, &mut res_t);
+        assert_eq!(compare_t, res_t);
+        res_t.reset();
+
+        winograd::mult(&a_t, &b_t, &mut res_t);
+        assert_eq!(compare_t, res_t);
+        res_t.reset();
+
+        let mut res_z = MortonMatrix::from(res.clone());
+        space_filling::mult(&MortonMatrix::from(a.clone()), &MortonMatrix::from(b.clone()), &mut res_z);
+        assert_eq!(compare, Matrix::from(res_z));
+
         asm::mult(&a, &bT, &mut res);
         assert_eq!(compare, res);
         res.reset();
 
+        packed::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        recursive::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
 //        File::create("/tmp/a").unwrap().write_all(format!("{}", a_t).as_bytes());
 //        File::create("/tmp/b").unwrap().write_all(format!("{}", b_t).as_bytes());
 //
@@ -94,6 +189,300 @@ mod test {
 
 
     }
-}
 
+    #[test]
+    fn test_layouts() {
+        let (m, k, n) = (10, 6, 14);
+
+        let a = Matrix::<f64>::random(m, k);
+        let b = Matrix::<f64>::random(k, n);
+        let mut compare = Matrix::<f64>::zero(m, n);
+        naive::mult(&a, &b, &mut compare);
+
+        // A in tiles which do not divide its shape, B column major and C in tiles of another size
+        let a_tiled = a.to_layout_with_tile::<Tiled>(4);
+        let b_col = b.to_layout::<ColMajor>();
+        let mut res = Matrix::<f64, Tiled>::zero_with_tile(m, n, 3);
+
+        naive::mult(&a_tiled, &b_col, &mut res);
+        assert_eq!(compare, res.to_layout());
+        res.reset();
+
+        naive_unchecked::mult(&a_tiled, &b_col, &mut res);
+        assert_eq!(compare, res.to_layout());
+        res.reset();
+
+        naive_transposed::mult(&a_tiled, &b_col, &mut res);
+        assert_eq!(compare, res.to_layout());
+        res.reset();
+
+        blocked::mult(&a_tiled, &b_col, &mut res);
+        assert_eq!(compare, res.to_layout());
+
+        // packed reads strides, so it takes the dense layouts
+        let mut res = TransposedMatrix::<f64>::zero(m, n);
+        packed::mult(&a.to_layout::<ColMajor>(), &b_col, &mut res);
+        assert_eq!(compare, Matrix::from(res));
+
+        // naive_reordered only handles multiples of 4 columns
+        let b = b.submatrix(0, 0, k, 12).to_matrix();
+        let mut compare = Matrix::<f64>::zero(m, 12);
+        naive::mult(&a, &b, &mut compare);
+
+        let mut res = Matrix::<f64, Tiled>::zero_with_tile(m, 12, 3);
+        naive_reordered::mult(&a_tiled, &b.to_layout::<Tiled>(), &mut res);
+        assert_eq!(compare, res.to_layout());
+    }
+
+    #[test]
+    fn test_registry() {
+        let n = 256;
+
+        let mut compare = Matrix::<f64>::zero(n, n);
+        naive::mult(&Matrix::<f64>::random(n, n), &Matrix::<f64>::random(n, n), &mut compare);
+
+        let mut operands = Operands::new(Matrix::random(n, n), Matrix::random(n, n));
+
+        for kernel in KERNELS {
+            assert!(kernel.supports(n, n, n), "{} does not support {}x{}", kernel.name(), n, n);
+
+            operands.prepare(kernel.layouts());
+            kernel.run(&mut operands);
+            assert_eq!(compare, operands.result(kernel.layouts().2), "{}", kernel.name());
+            operands.reset();
+        }
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let n = 256;
+
+        let mut compare = Matrix::<f64>::zero(n, n);
+        naive::mult(&Matrix::<f64>::random(n, n), &Matrix::<f64>::random(n, n), &mut compare);
+        let twice = &compare * 2.;
+
+        let mut operands = Operands::new(Matrix::random(n, n), Matrix::random(n, n));
+
+        // the second run starts from a non-zero C, so it has to add to it
+        for kernel in KERNELS {
+            operands.prepare(kernel.layouts());
+            kernel.run(&mut operands);
+            kernel.run(&mut operands);
+            assert_matrix_close!(operands.result(kernel.layouts().2), twice, Tolerance::new(1e-10, 1e-10, 0), "{}", kernel.name());
+            operands.reset();
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn test_element<T: Element>() {
+        let n = 32;
+
+        let a = Matrix::<T>::random(n, n);
+        let b = Matrix::<T>::random(n, n);
+        let mut compare = Matrix::<T>::zero(n, n);
+        naive::mult(&a, &b, &mut compare);
+
+        let bT = TransposedMatrix::from(b.clone());
+        let mut res = Matrix::zero(n, n);
+
+        naive_unchecked::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        naive_reordered::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        naive_transposed::mult(&a, &bT, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        naive_simd::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        naive_rayon::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        blocked::mult(&a, &bT, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        iter::mult(&a, &bT, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        recursive::mult(&a, &b, &mut res);
+        assert_eq!(compare, res);
+        res.reset();
+
+        let mut res_t = TileMatrix::from(res.clone());
+        tiled_rayon::mult(&TileMatrix::from(a.clone()), &TileMatrix::from(b.clone()), &mut res_t);
+        assert_eq!(compare, Matrix::from(res_t));
+    }
+
+    #[test]
+    fn test_elements() {
+        test_element::<f32>();
+        test_element::<i32>();
+        test_element::<i64>();
+        test_element::<Complex<f64>>();
+    }
+
+    #[allow(non_snake_case)]
+    fn test_remainder<T: Element>(m: usize, k: usize, n: usize) {
+        let a = Matrix::<T>::random(m, k);
+        let b = Matrix::<T>::random(k, n);
+        let mut compare = Matrix::<T>::zero(m, n);
+        naive::mult(&a, &b, &mut compare);
+
+        let mut res = Matrix::zero(m, n);
+
+        naive_simd::mult(&a, &b, &mut res);
+        assert_eq!(compare, res, "naive_simd {}x{}x{}", m, k, n);
+        res.reset();
+
+        naive_rayon::mult(&a, &b, &mut res);
+        assert_eq!(compare, res, "naive_rayon {}x{}x{}", m, k, n);
+        res.reset();
+
+        recursive::mult(&a, &b, &mut res);
+        assert_eq!(compare, res, "recursive {}x{}x{}", m, k, n);
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_remainders() {
+        let sizes = [(1, 1, 1), (5, 7, 9), (13, 3, 17), (33, 31, 29), (100, 99, 101)];
 
+        for &(m, k, n) in &sizes {
+            test_remainder::<f64>(m, k, n);
+            test_remainder::<f32>(m, k, n);
+            test_remainder::<i32>(m, k, n);
+
+            let a = Matrix::<f64>::random(m, k);
+            let b = Matrix::<f64>::random(k, n);
+            let bT = TransposedMatrix::from(b.clone());
+            let mut compare = Matrix::<f64>::zero(m, n);
+            naive::mult(&a, &b, &mut compare);
+
+            let mut res = Matrix::zero(m, n);
+            asm::mult(&a, &bT, &mut res);
+            assert_eq!(compare, res, "asm {}x{}x{}", m, k, n);
+
+            for name in &["naive_simd", "naive_rayon", "asm", "recursive"] {
+                assert!(find_kernel(name).unwrap().supports(m, k, n));
+            }
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn test_view_kernel<K>(kernel: &K)
+        where K: for<'a> MatMul<MatrixView<'a>, MatrixView<'a>, MatrixViewMut<'a>>
+    {
+        let a = Matrix::<f64>::random(20, 23);
+        let b = Matrix::<f64>::random(17, 30);
+
+        // (7 x 9) * (9 x 13) on blocks with offsets and leading dimensions != columns
+        let A = a.submatrix(3, 5, 7, 9);
+        let B = b.submatrix(2, 1, 9, 13);
+
+        let mut compare = Matrix::<f64>::zero(7, 13);
+        naive::mult(&A.to_matrix(), &B.to_matrix(), &mut compare);
+
+        let mut c = Matrix::<f64>::zero(12, 16);
+        kernel.mult(&A, &B, &mut c.submatrix_mut(4, 2, 7, 13));
+
+        assert_eq!(c.submatrix(4, 2, 7, 13).to_matrix(), compare, "{}", kernel.name());
+
+        // everything outside of the block is untouched
+        let touched = c.data.iter().filter(|&&x| x != 0.).count();
+        assert_eq!(touched, compare.data.iter().filter(|&&x| x != 0.).count(), "{}", kernel.name());
+    }
+
+    #[test]
+    fn test_views() {
+        test_view_kernel(&naive::Naive);
+        test_view_kernel(&naive_unchecked::NaiveUnchecked);
+        test_view_kernel(&naive_simd::NaiveSimd);
+        test_view_kernel(&naive_rayon::NaiveRayon);
+        test_view_kernel(&packed::Packed);
+        test_view_kernel(&recursive::Recursive);
+    }
+
+    #[test]
+    fn test_supports() {
+        // the simd width is 4 for f64 and 8 for f32 and i32
+        assert!(MatMul::<TileMatrix, TileMatrix, TileMatrix>::supports(&tiled::Tiled, 12, 12, 12));
+        assert!(!MatMul::<TileMatrix<f32>, TileMatrix<f32>, TileMatrix<f32>>::supports(&tiled::Tiled, 12, 12, 12));
+        assert!(!MatMul::<TileMatrix<i32>, TileMatrix<i32>, TileMatrix<i32>>::supports(&strassen::Strassen, 12, 12, 12));
+        assert!(MatMul::<TileMatrix<f32>, TileMatrix<f32>, TileMatrix<f32>>::supports(&winograd::Winograd, 16, 16, 16));
+        assert!(find_kernel("tiled_rayon").unwrap().supports(12, 12, 12));
+    }
+
+    #[test]
+    fn test_find_kernel() {
+        for kernel in KERNELS {
+            assert_eq!(find_kernel(kernel.name()).unwrap().name(), kernel.name());
+        }
+        assert!(find_kernel("unknown").is_none());
+    }
+
+    #[test]
+    fn test_find_view_kernel() {
+        // exactly the kernels on row major matrices work on views
+        for kernel in KERNELS {
+            let row_major = kernel.layouts() == (LayoutKind::RowMajor, LayoutKind::RowMajor, LayoutKind::RowMajor);
+            assert_eq!(find_view_kernel(kernel.name()).is_ok(), row_major, "{}", kernel.name());
+        }
+
+        for name in &["naive_transposed", "tiled", "asm"] {
+            assert_eq!(find_view_kernel(name).err(), Some(MatrixError::UnsupportedView));
+        }
+
+        let a = Matrix::<f64>::random(8, 8);
+        let mut c = Matrix::<f64>::zero(8, 8);
+        let kernel = find_view_kernel("naive_rayon").unwrap();
+
+        let result = kernel.try_mult_view(a.submatrix(0, 0, 4, 3), a.submatrix(0, 0, 4, 4), &mut c.submatrix_mut(0, 0, 4, 4));
+        assert_eq!(result, Err(MatrixError::ShapeMismatch { expected: (3, 4), actual: (4, 4) }));
+
+        let mut compare = Matrix::<f64>::zero(4, 4);
+        naive::mult(&a.submatrix(1, 2, 4, 3).to_matrix(), &a.submatrix(4, 1, 3, 4).to_matrix(), &mut compare);
+
+        kernel.try_mult_view(a.submatrix(1, 2, 4, 3), a.submatrix(4, 1, 3, 4), &mut c.submatrix_mut(2, 2, 4, 4)).unwrap();
+        assert_eq!(c.submatrix(2, 2, 4, 4).to_matrix(), compare);
+    }
+
+    #[test]
+    fn test_try_mult() {
+        let a = Matrix::<f64>::random(6, 4);
+        let b = Matrix::<f64>::random(4, 2);
+
+        let mut compare = Matrix::<f64>::zero(6, 2);
+        naive::mult(&a, &b, &mut compare);
+
+        let mut c = Matrix::<f64>::zero(6, 2);
+        assert_eq!(naive::Naive.try_mult(&a, &b, &mut c), Ok(()));
+        assert_eq!(c, compare);
+
+        let mut wrong = Matrix::<f64>::zero(2, 6);
+        assert_eq!(naive::Naive.try_mult(&a, &b, &mut wrong), Err(MatrixError::ShapeMismatch { expected: (6, 2), actual: (2, 6) }));
+        assert_eq!(naive::Naive.try_mult(&a, &a, &mut c), Err(MatrixError::ShapeMismatch { expected: (4, 4), actual: (6, 4) }));
+
+        // blocked only works on even sizes
+        let odd = Matrix::<f64>::random(3, 3);
+        let mut c = Matrix::<f64>::zero(3, 3);
+        let result = blocked::Blocked.try_mult(&odd, &TransposedMatrix::from(odd.clone()), &mut c);
+        assert_eq!(result, Err(MatrixError::UnsupportedDimension { m: 3, k: 3, n: 3 }));
+        assert_eq!(c, Matrix::zero(3, 3));
+
+        let mut operands = Operands::new(Matrix::random(6, 6), Matrix::random(6, 6));
+        let strassen = find_kernel("strassen").unwrap();
+        operands.prepare(strassen.layouts());
+        assert_eq!(strassen.try_run(&mut operands), Err(MatrixError::UnsupportedDimension { m: 6, k: 6, n: 6 }));
+
+        assert!(Operands::try_new(Matrix::random(6, 4), Matrix::random(6, 4)).is_err());
+    }
+}
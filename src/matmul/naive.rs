@@ -2,23 +2,46 @@
 #[macro_use]
 use super::macros;
 
+use std::ops::{Index, IndexMut};
+
 use super::matrix::*;
 
 use super::TOPOLOGY;
 
+/// Compute C += A * B, the operands may be stored in any layout
+#[allow(non_snake_case)]
+pub fn mult<T: Element, LA: Layout, LB: Layout, LC: Layout>(A: &Matrix<T, LA>, B: &Matrix<T, LB>, C: &mut Matrix<T, LC>) {
+    mult_indexed(A, B, C)
+}
+
+/// Compute C += A * B, the operands may be blocks of bigger matrices
+#[allow(non_snake_case)]
+pub fn mult_view<T: Element>(A: MatrixView<T>, B: MatrixView<T>, C: &mut MatrixViewMut<T>) {
+    mult_indexed(&A, &B, C)
+}
+
+/// The loops of mult and mult_view, which only access the operands by (row, column)
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &Matrix, C: &mut Matrix) {
-    assert_eq!(A.columns, B.rows);
-    assert_eq!(A.rows, C.rows);
-    assert_eq!(B.columns, C.columns);
-
-    for i in 0..C.rows {
-        for j in 0..C.columns {
-            for k in 0..A.columns {
+fn mult_indexed<T, MA, MB, MC>(A: &MA, B: &MB, C: &mut MC)
+    where T: Element,
+          MA: Index<(usize, usize), Output=T> + Shape,
+          MB: Index<(usize, usize), Output=T> + Shape,
+          MC: IndexMut<(usize, usize), Output=T> + Shape {
+    let ((m, depth), (rows_b, n)) = (A.shape(), B.shape());
+    assert_eq!(depth, rows_b);
+    assert_eq!((m, n), C.shape());
+
+    for i in 0..m {
+        for j in 0..n {
+            for k in 0..depth {
                 C[(i,j)] += A[(i, k)] * B[(k, j)];
             }
         }
     }
 }
 
+kernel!(Naive, "naive", |m, k, n| true);
+
+view_kernel!(Naive);
+
 generate_tests!();
\ No newline at end of file
@@ -1,39 +1,52 @@
 
-extern crate simd;
-use self::simd::x86::avx::f64x4;
-
 extern crate rayon;
 use self::rayon::prelude::*;
 
 extern crate core;
-use std;
 use self::core::ptr;
 
 use super::naive_simd;
 
 use super::matrix::*;
 
+use triades::SimdItem;
+
 use super::TOPOLOGY;
 
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &Matrix, C: &mut Matrix) {
+pub fn mult<T: Element>(A: &Matrix<T>, B: &Matrix<T>, C: &mut Matrix<T>) {
+    mult_view(A.view(), B.view(), &mut C.view_mut())
+}
+
+/// Compute C += A * B, the operands may be blocks of bigger matrices
+///
+/// The rows of C are distributed over the rayon workers.
+#[allow(non_snake_case)]
+pub fn mult_view<T: Element>(A: MatrixView<T>, B: MatrixView<T>, C: &mut MatrixViewMut<T>) {
     assert_eq!(A.columns, B.rows);
     assert_eq!(A.rows, C.rows);
     assert_eq!(B.columns, C.columns);
-    assert_eq!(C.columns % 4, 0);
 
-    let ret = (0..C.rows).into_par_iter().for_each(|i| {
-        let b_ptr = B.data.as_ptr() as *const f64x4;
-        let c_ptr = C.data.as_ptr() as *mut f64x4;
+    // columns covered by whole simd vectors, the remaining columns are computed with scalars
+    let n_simd = C.columns - C.columns % T::CHUNK_SIZE;
+    let unrolled = C.columns % (4 * T::CHUNK_SIZE) == 0 && A.is_aligned() && B.is_aligned() && C.is_aligned();
+
+    let columns = C.columns;
+
+    // every worker writes its own rows of C
+    C.reborrow().into_rows().into_par_iter().enumerate().for_each(|(i, c_row)| {
+        let b_ptr = B.as_ptr() as *const T::SimdType;
 
         for k in 0..A.columns {
-            if C.columns % 16 == 0 && A.is_aligned() && B.is_aligned() && C.is_aligned() {
-                let a = f64x4::splat(A[(i, k)]);
+            if unrolled {
+                let a = T::splat(A[(i, k)]);
+
+                let c_ptr = c_row.as_mut_ptr() as *mut T::SimdType;
 
-                let mut b_ind = (k * B.columns / 4) as isize;
-                let mut c_ind = (i * B.columns / 4) as isize;
+                let mut b_ind = (k * B.ld / T::CHUNK_SIZE) as isize;
+                let mut c_ind = 0;
 
-                for j in 0..C.columns/16 {
+                for j in 0..columns / (4 * T::CHUNK_SIZE) {
                     unsafe {
 
                         let b_1 = *b_ptr.offset(b_ind);
@@ -58,19 +71,27 @@ pub fn mult(A: &Matrix, B: &Matrix, C: &mut Matrix) {
                 }
             }
             else {
-                for j in 0..C.columns/4 {
-                    unsafe {
-                        let a = f64x4::splat(*A.get_unchecked((i, k)));
-                        let b = f64x4::load(&B.data, k * B.columns + j * 4);
-                        let c = f64x4::load(&C.data, i * C.columns + j * 4);
+                let a = unsafe { *A.get_unchecked((i, k)) };
+                let a_simd = T::splat(a);
+                let b_row = B.row(k);
 
-                        let result = a * b + c;
-                        ptr::write(c_ptr.offset((i * C.columns / 4 + j) as isize), result);
-                    }
+                for j in (0..n_simd).step_by(T::CHUNK_SIZE) {
+                    let b = T::load(b_row, j);
+                    let c = T::load(c_row, j);
+
+                    (a_simd * b + c).store(c_row, j);
+                }
+
+                for j in n_simd..columns {
+                    c_row[j] += a * b_row[j];
                 }
             }
         }
     });
 }
 
+kernel!(NaiveRayon, "naive_rayon", |m, k, n| true);
+
+view_kernel!(NaiveRayon);
+
 generate_tests!();
\ No newline at end of file
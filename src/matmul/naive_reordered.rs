@@ -2,24 +2,45 @@ use super::matrix::*;
 
 use super::TOPOLOGY;
 
+/// Compute C += A * B, the operands may be stored in any layout
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &Matrix, C: &mut Matrix) {
-    assert_eq!(A.columns, B.rows);
-    assert_eq!(A.rows, C.rows);
-    assert_eq!(B.columns, C.columns);
-
-    for i in 0..C.rows {
-        for k in 0..A.columns {
-            for j in 0..C.columns/4 {
+pub fn mult<T: Element, LA: Layout, LB: Layout, LC: Layout>(A: &Matrix<T, LA>, B: &Matrix<T, LB>, C: &mut Matrix<T, LC>) {
+    mult_indexed(A, B, C)
+}
+
+/// Compute C += A * B, the operands may be blocks of bigger matrices
+#[allow(non_snake_case)]
+pub fn mult_view<T: Element>(A: MatrixView<T>, B: MatrixView<T>, C: &mut MatrixViewMut<T>) {
+    mult_indexed(&A, &B, C)
+}
+
+/// The loops of mult and mult_view, which only access the operands by (row, column)
+#[allow(non_snake_case)]
+fn mult_indexed<T, MA, MB, MC>(A: &MA, B: &MB, C: &mut MC)
+    where T: Element,
+          MA: IndexUnchecked<(usize, usize), Output=T> + Shape,
+          MB: IndexUnchecked<(usize, usize), Output=T> + Shape,
+          MC: IndexUncheckedMut<(usize, usize), Output=T> + Shape {
+    let ((m, depth), (rows_b, n)) = (A.shape(), B.shape());
+    assert_eq!(depth, rows_b);
+    assert_eq!((m, n), C.shape());
+
+    for i in 0..m {
+        for k in 0..depth {
+            for j in 0..n/4 {
                 unsafe {
-                    *C.get_unchecked_mut((i, j * 4)) += A.get_unchecked((i, k)) * B.get_unchecked((k, j * 4));
-                    *C.get_unchecked_mut((i, j * 4 + 1)) += A.get_unchecked((i, k)) * B.get_unchecked((k, j * 4 + 1));
-                    *C.get_unchecked_mut((i, j * 4 + 2)) += A.get_unchecked((i, k)) * B.get_unchecked((k, j * 4 + 2));
-                    *C.get_unchecked_mut((i, j * 4 + 3)) += A.get_unchecked((i, k)) * B.get_unchecked((k, j * 4 + 3));
+                    *C.get_unchecked_mut((i, j * 4)) += *A.get_unchecked((i, k)) * *B.get_unchecked((k, j * 4));
+                    *C.get_unchecked_mut((i, j * 4 + 1)) += *A.get_unchecked((i, k)) * *B.get_unchecked((k, j * 4 + 1));
+                    *C.get_unchecked_mut((i, j * 4 + 2)) += *A.get_unchecked((i, k)) * *B.get_unchecked((k, j * 4 + 2));
+                    *C.get_unchecked_mut((i, j * 4 + 3)) += *A.get_unchecked((i, k)) * *B.get_unchecked((k, j * 4 + 3));
                 }
             }
         }
     }
 }
 
+kernel!(NaiveReordered, "naive_reordered", |m, k, n| n % 4 == 0);
+
+view_kernel!(NaiveReordered);
+
 generate_tests!();
\ No newline at end of file
@@ -5,23 +5,26 @@ use super::macros;
 use super::matrix::*;
 
 
+/// Compute C += A * B, the operands may be stored in any layout, B is registered as TransposedMatrix
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &TransposedMatrix, C: &mut Matrix) {
+pub fn mult<T: Element, LA: Layout, LB: Layout, LC: Layout>(A: &Matrix<T, LA>, B: &Matrix<T, LB>, C: &mut Matrix<T, LC>) {
     assert_eq!(A.columns, B.rows);
     assert_eq!(A.rows, C.rows);
     assert_eq!(B.columns, C.columns);
 
     for i in 0..C.rows {
         for j in 0..C.columns {
-            let mut tmp = 0.0;
+            let mut tmp = T::zero();
             for k in 0..A.columns {
                 unsafe {
-                    tmp += A.get_unchecked((i, k)) * B.get_unchecked((k, j));
+                    tmp += *A.get_unchecked((i, k)) * *B.get_unchecked((k, j));
                 }
             }
-            C[(i, j)] = tmp;
+            C[(i, j)] += tmp;
         }
     }
 }
 
+kernel!(NaiveTransposed, "naive_transposed", (Matrix, TransposedMatrix, Matrix), |m, k, n| true);
+
 generate_tests!(Matrix, TransposedMatrix, Matrix);
\ No newline at end of file
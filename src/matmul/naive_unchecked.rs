@@ -2,21 +2,42 @@ use super::matrix::*;
 
 use super::TOPOLOGY;
 
+/// Compute C += A * B, the operands may be stored in any layout
 #[allow(non_snake_case)]
-pub fn mult(A: &Matrix, B: &Matrix, C: &mut Matrix) {
-    assert_eq!(A.columns, B.rows);
-    assert_eq!(A.rows, C.rows);
-    assert_eq!(B.columns, C.columns);
-
-    for i in 0..C.rows {
-        for j in 0..C.columns {
-            for k in 0..A.columns {
+pub fn mult<T: Element, LA: Layout, LB: Layout, LC: Layout>(A: &Matrix<T, LA>, B: &Matrix<T, LB>, C: &mut Matrix<T, LC>) {
+    mult_indexed(A, B, C)
+}
+
+/// Compute C += A * B, the operands may be blocks of bigger matrices
+#[allow(non_snake_case)]
+pub fn mult_view<T: Element>(A: MatrixView<T>, B: MatrixView<T>, C: &mut MatrixViewMut<T>) {
+    mult_indexed(&A, &B, C)
+}
+
+/// The loops of mult and mult_view, which only access the operands by (row, column)
+#[allow(non_snake_case)]
+fn mult_indexed<T, MA, MB, MC>(A: &MA, B: &MB, C: &mut MC)
+    where T: Element,
+          MA: IndexUnchecked<(usize, usize), Output=T> + Shape,
+          MB: IndexUnchecked<(usize, usize), Output=T> + Shape,
+          MC: IndexUncheckedMut<(usize, usize), Output=T> + Shape {
+    let ((m, depth), (rows_b, n)) = (A.shape(), B.shape());
+    assert_eq!(depth, rows_b);
+    assert_eq!((m, n), C.shape());
+
+    for i in 0..m {
+        for j in 0..n {
+            for k in 0..depth {
                 unsafe {
-                    *C.get_unchecked_mut((i, j)) += A.get_unchecked((i, k)) * B.get_unchecked((k, j));
+                    *C.get_unchecked_mut((i, j)) += *A.get_unchecked((i, k)) * *B.get_unchecked((k, j));
                 }
             }
         }
     }
 }
 
+kernel!(NaiveUnchecked, "naive_unchecked", |m, k, n| true);
+
+view_kernel!(NaiveUnchecked);
+
 generate_tests!();
\ No newline at end of file
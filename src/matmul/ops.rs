@@ -0,0 +1,346 @@
+//! The arithmetic operators of Matrix and TileMatrix
+//!
+//! Element-wise operations require operands of the same shape (and block grid for TileMatrix) and
+//! panic otherwise, like the kernels do. The matrix product computes into a new zero matrix, so
+//! `&a * &b` replaces `let mut c = Matrix::zero(..); mult(&a, &b, &mut c);`.
+
+use std::ops::{Add, Sub, Mul, Neg, AddAssign, SubAssign, MulAssign};
+
+use super::*;
+
+/// Element types which can be multiplied with the `*` operator
+///
+/// The product uses the cache-oblivious recursive kernel by default. f64 uses the kernel which
+/// was tuned for the size, see autotune::mult.
+pub trait MatProduct: Element {
+    /// Compute C += A * B
+    #[allow(non_snake_case)]
+    fn product(A: &Matrix<Self>, B: &Matrix<Self>, C: &mut Matrix<Self>) {
+        recursive::mult(A, B, C)
+    }
+}
+
+impl MatProduct for f64 {
+    #[allow(non_snake_case)]
+    fn product(A: &Matrix, B: &Matrix, C: &mut Matrix) {
+        autotune::mult(A, B, C)
+    }
+}
+
+impl MatProduct for f32 {}
+impl MatProduct for i64 {}
+impl MatProduct for i32 {}
+impl<T: Element> MatProduct for Complex<T> {}
+
+/// Apply f to the elements of a and the corresponding elements of b
+fn zip_with<T: Element, L: Layout, F: Fn(&mut T, T)>(a: &mut Matrix<T, L>, b: &Matrix<T, L>, f: F) {
+    assert!(a.rows == b.rows && a.columns == b.columns,
+            "the shapes {}x{} and {}x{} differ", a.rows, a.columns, b.rows, b.columns);
+    assert!(a.tile() == b.tile(), "the tile sizes {} and {} differ", a.tile(), b.tile());
+
+    for (x, &y) in a.data.iter_mut().zip(b.data.iter()) {
+        f(x, y);
+    }
+}
+
+impl<'a, T: Element, L: Layout> AddAssign<&'a Matrix<T, L>> for Matrix<T, L> {
+    fn add_assign(&mut self, other: &Matrix<T, L>) {
+        zip_with(self, other, |x, y| *x += y);
+    }
+}
+
+impl<'a, T: Element, L: Layout> SubAssign<&'a Matrix<T, L>> for Matrix<T, L> {
+    fn sub_assign(&mut self, other: &Matrix<T, L>) {
+        zip_with(self, other, |x, y| *x = *x - y);
+    }
+}
+
+impl<T: Element, L: Layout> MulAssign<T> for Matrix<T, L> {
+    fn mul_assign(&mut self, scalar: T) {
+        for x in &mut self.data {
+            *x = *x * scalar;
+        }
+    }
+}
+
+impl<'a, T: MatProduct> MulAssign<&'a Matrix<T>> for Matrix<T> {
+    /// Replace self by self * other
+    fn mul_assign(&mut self, other: &Matrix<T>) {
+        *self = &*self * other;
+    }
+}
+
+impl<'a, 'b, T: Element, L: Layout> Add<&'b Matrix<T, L>> for &'a Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn add(self, other: &Matrix<T, L>) -> Matrix<T, L> {
+        self.clone() + other
+    }
+}
+
+impl<'a, T: Element, L: Layout> Add<&'a Matrix<T, L>> for Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    /// Reuse the data of self
+    fn add(mut self, other: &Matrix<T, L>) -> Matrix<T, L> {
+        self += other;
+        self
+    }
+}
+
+impl<T: Element, L: Layout> Add for Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn add(self, other: Matrix<T, L>) -> Matrix<T, L> {
+        self + &other
+    }
+}
+
+impl<'a, 'b, T: Element, L: Layout> Sub<&'b Matrix<T, L>> for &'a Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn sub(self, other: &Matrix<T, L>) -> Matrix<T, L> {
+        self.clone() - other
+    }
+}
+
+impl<'a, T: Element, L: Layout> Sub<&'a Matrix<T, L>> for Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    /// Reuse the data of self
+    fn sub(mut self, other: &Matrix<T, L>) -> Matrix<T, L> {
+        self -= other;
+        self
+    }
+}
+
+impl<T: Element, L: Layout> Sub for Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn sub(self, other: Matrix<T, L>) -> Matrix<T, L> {
+        self - &other
+    }
+}
+
+impl<'a, T: Element, L: Layout> Mul<T> for &'a Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn mul(self, scalar: T) -> Matrix<T, L> {
+        self.clone() * scalar
+    }
+}
+
+impl<T: Element, L: Layout> Mul<T> for Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn mul(mut self, scalar: T) -> Matrix<T, L> {
+        self *= scalar;
+        self
+    }
+}
+
+impl<'a, 'b, T: MatProduct> Mul<&'b Matrix<T>> for &'a Matrix<T> {
+    type Output = Matrix<T>;
+
+    /// The matrix product, see MatProduct for the kernel which is used
+    fn mul(self, other: &Matrix<T>) -> Matrix<T> {
+        assert!(self.columns == other.rows,
+                "can not multiply a {}x{} and a {}x{} matrix", self.rows, self.columns, other.rows, other.columns);
+
+        let mut ret = Matrix::zero(self.rows, other.columns);
+        T::product(self, other, &mut ret);
+        ret
+    }
+}
+
+impl<'a, T: Element, L: Layout> Neg for &'a Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn neg(self) -> Matrix<T, L> {
+        -self.clone()
+    }
+}
+
+impl<T: Element, L: Layout> Neg for Matrix<T, L> {
+    type Output = Matrix<T, L>;
+
+    fn neg(mut self) -> Matrix<T, L> {
+        for x in &mut self.data {
+            *x = -*x;
+        }
+        self
+    }
+}
+
+/// Apply f to the blocks of a and the corresponding blocks of b
+fn zip_blocks<T: Element, F: Fn(&mut Matrix<T>, &Matrix<T>)>(a: &mut TileMatrix<T>, b: &TileMatrix<T>, f: F) {
+    assert!(a.rows == b.rows && a.columns == b.columns,
+            "the shapes {}x{} and {}x{} differ", a.rows, a.columns, b.rows, b.columns);
+    assert!(a.tile() == b.tile(), "the tile sizes {} and {} differ", a.tile(), b.tile());
+    assert!(a.blocks_right == b.blocks_right && a.blocks_down == b.blocks_down, "the block grids differ");
+
+    for (x, y) in a.data.iter_mut().zip(b.data.iter()) {
+        f(x, y);
+    }
+}
+
+impl<'a, T: Element> AddAssign<&'a TileMatrix<T>> for TileMatrix<T> {
+    fn add_assign(&mut self, other: &TileMatrix<T>) {
+        zip_blocks(self, other, |x, y| *x += y);
+    }
+}
+
+impl<'a, T: Element> SubAssign<&'a TileMatrix<T>> for TileMatrix<T> {
+    fn sub_assign(&mut self, other: &TileMatrix<T>) {
+        zip_blocks(self, other, |x, y| *x -= y);
+    }
+}
+
+impl<T: Element> MulAssign<T> for TileMatrix<T> {
+    fn mul_assign(&mut self, scalar: T) {
+        for block in &mut self.data {
+            *block *= scalar;
+        }
+    }
+}
+
+impl<'a, T: Element> MulAssign<&'a TileMatrix<T>> for TileMatrix<T> {
+    /// Replace self by self * other
+    fn mul_assign(&mut self, other: &TileMatrix<T>) {
+        *self = &*self * other;
+    }
+}
+
+impl<'a, 'b, T: Element> Add<&'b TileMatrix<T>> for &'a TileMatrix<T> {
+    type Output = TileMatrix<T>;
+
+    fn add(self, other: &TileMatrix<T>) -> TileMatrix<T> {
+        let mut ret = self.clone();
+        ret += other;
+        ret
+    }
+}
+
+impl<'a, 'b, T: Element> Sub<&'b TileMatrix<T>> for &'a TileMatrix<T> {
+    type Output = TileMatrix<T>;
+
+    fn sub(self, other: &TileMatrix<T>) -> TileMatrix<T> {
+        let mut ret = self.clone();
+        ret -= other;
+        ret
+    }
+}
+
+impl<'a, T: Element> Mul<T> for &'a TileMatrix<T> {
+    type Output = TileMatrix<T>;
+
+    fn mul(self, scalar: T) -> TileMatrix<T> {
+        let mut ret = self.clone();
+        ret *= scalar;
+        ret
+    }
+}
+
+impl<'a, 'b, T: Element> Mul<&'b TileMatrix<T>> for &'a TileMatrix<T> {
+    type Output = TileMatrix<T>;
+
+    /// The matrix product, computed blockwise by tiled_rayon
+    ///
+    /// The blocks of self have to be as wide as the blocks of other are high.
+    fn mul(self, other: &TileMatrix<T>) -> TileMatrix<T> {
+        assert!(self.columns == other.rows && self.blocks_right == other.blocks_down,
+                "can not multiply a {}x{} and a {}x{} matrix", self.rows, self.columns, other.rows, other.columns);
+
+        let (block_rows, block_columns) = (self.data[0].rows, other.data[0].columns);
+        let data = (0..self.blocks_down * other.blocks_right)
+            .map(|_| Matrix::zero(block_rows, block_columns))
+            .collect();
+
+        let mut ret = TileMatrix::new_with_size(self.rows, other.columns, other.blocks_right, self.blocks_down, data);
+        tiled_rayon::mult(self, other, &mut ret);
+        ret
+    }
+}
+
+impl<'a, T: Element> Neg for &'a TileMatrix<T> {
+    type Output = TileMatrix<T>;
+
+    fn neg(self) -> TileMatrix<T> {
+        let mut ret = self.clone();
+        for block in &mut ret.data {
+            *block = -&*block;
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elementwise() {
+        let a = Matrix::<f64>::new(2, 2, vec![1., 2., 3., 4.]);
+        let b = Matrix::<f64>::new(2, 2, vec![4., 3., 2., 1.]);
+
+        assert_eq!(&a + &b, Matrix::new(2, 2, vec![5., 5., 5., 5.]));
+        assert_eq!(&a - &b, Matrix::new(2, 2, vec![-3., -1., 1., 3.]));
+        assert_eq!(&a * 2., Matrix::new(2, 2, vec![2., 4., 6., 8.]));
+        assert_eq!(-&a, Matrix::new(2, 2, vec![-1., -2., -3., -4.]));
+        assert_eq!(a.clone() + b.clone() - a.clone(), b);
+
+        let mut c = a.clone();
+        c += &b;
+        c -= &a;
+        c *= 3.;
+        assert_eq!(c, &b * 3.);
+
+        // the layout does not matter as long as both operands share it
+        let t = TransposedMatrix::from(a.clone()) + &TransposedMatrix::from(b.clone());
+        assert_eq!(Matrix::from(t), &a + &b);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_shape_mismatch() {
+        let _ = &Matrix::<f64>::zero(2, 3) + &Matrix::zero(3, 2);
+    }
+
+    #[test]
+    fn test_product() {
+        let a = Matrix::<f64>::random(13, 7);
+        let b = Matrix::<f64>::random(7, 9);
+
+        let mut compare = Matrix::<f64>::zero(13, 9);
+        naive::mult(&a, &b, &mut compare);
+        assert_eq!(&a * &b, compare);
+
+        let mut c = a.clone();
+        c *= &b;
+        assert_eq!(c, compare);
+
+        let a = Matrix::<i32>::random(5, 3);
+        let b = Matrix::<i32>::random(3, 4);
+        let mut compare = Matrix::<i32>::zero(5, 4);
+        naive::mult(&a, &b, &mut compare);
+        assert_eq!(&a * &b, compare);
+    }
+
+    #[test]
+    fn test_tiled() {
+        let a = Matrix::<f64>::random(64, 32);
+        let b = Matrix::<f64>::random(32, 64);
+
+        let ta = TileMatrix::with_block_size(a.clone(), 16);
+        let tb = TileMatrix::with_block_size(b.clone(), 16);
+
+        assert_eq!(Matrix::from(&ta * &tb), &a * &b);
+        assert_eq!(Matrix::from(&ta + &ta), &a * 2.);
+        assert_eq!(Matrix::from(&ta - &ta), Matrix::<f64>::zero(64, 32));
+        assert_eq!(Matrix::from(-&(&ta * 3.)), &a * -3.);
+
+        let mut tc = ta.clone();
+        tc *= &tb;
+        assert_eq!(tc, &ta * &tb);
+    }
+}
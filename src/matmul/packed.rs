@@ -0,0 +1,292 @@
+
+#[macro_use]
+use super::macros;
+
+use std;
+use std::cmp::min;
+
+extern crate simd;
+use self::simd::x86::avx::f64x4;
+
+extern crate rayon;
+use self::rayon::prelude::*;
+
+use super::*;
+use super::tuning::block_sizes;
+
+/// Rows of the register block of the microkernel
+pub const MR: usize = 4;
+/// Columns of the register block of the microkernel
+pub const NR: usize = 8;
+
+/// A raw pointer which may be shared between the rayon workers
+///
+/// Every worker writes a disjoint block of C, so this is safe.
+#[derive(Clone, Copy)]
+struct SharedPtr(*mut f64);
+
+unsafe impl Send for SharedPtr {}
+unsafe impl Sync for SharedPtr {}
+
+/// Allocate a zeroed buffer of len f64 which is aligned to 32 bytes
+fn aligned_buffer(len: usize) -> Vec<f64x4> {
+    vec![f64x4::splat(0.); (len + 3) / 4]
+}
+
+/// Pack a (mc x kc) block of A into panels of MR rows
+///
+/// Inside a panel the MR entries of a column are contiguous, so the microkernel reads A
+/// sequentially. The last panel is padded with zeros.
+unsafe fn pack_a(a: *const f64, rsa: usize, csa: usize, mc: usize, kc: usize, buffer: *mut f64) {
+    for (panel, ir) in (0..mc).step_by(MR).enumerate() {
+        let mr = min(MR, mc - ir);
+        let dst = buffer.offset((panel * kc * MR) as isize);
+
+        for p in 0..kc {
+            for i in 0..MR {
+                *dst.offset((p * MR + i) as isize) = if i < mr {
+                    *a.offset(((ir + i) * rsa + p * csa) as isize)
+                } else {
+                    0.
+                };
+            }
+        }
+    }
+}
+
+/// Pack a (kc x nc) block of B into panels of NR columns
+///
+/// Inside a panel the NR entries of a row are contiguous, so the microkernel reads B
+/// sequentially. The last panel is padded with zeros.
+unsafe fn pack_b(b: *const f64, rsb: usize, csb: usize, kc: usize, nc: usize, buffer: *mut f64) {
+    for (panel, jr) in (0..nc).step_by(NR).enumerate() {
+        let nr = min(NR, nc - jr);
+        let dst = buffer.offset((panel * kc * NR) as isize);
+
+        for p in 0..kc {
+            for j in 0..NR {
+                *dst.offset((p * NR + j) as isize) = if j < nr {
+                    *b.offset((p * rsb + (jr + j) * csb) as isize)
+                } else {
+                    0.
+                };
+            }
+        }
+    }
+}
+
+/// Compute the (MR x NR) product of a packed panel of A and a packed panel of B
+///
+/// The whole tile is kept in ymm0 - ymm7 during the kc rank-1 updates and is written row major
+/// to ab afterwards. b has to be aligned to 32 bytes.
+#[inline(never)]
+unsafe fn microkernel(kc: usize, a: *const f64, b: *const f64, ab: &mut [f64x4; MR * NR / 4]) {
+    let mut a = a;
+    let mut b = b;
+    let mut kc = kc;
+
+    asm!(
+        "
+        vxorpd %ymm0, %ymm0, %ymm0
+        vxorpd %ymm1, %ymm1, %ymm1
+        vxorpd %ymm2, %ymm2, %ymm2
+        vxorpd %ymm3, %ymm3, %ymm3
+        vxorpd %ymm4, %ymm4, %ymm4
+        vxorpd %ymm5, %ymm5, %ymm5
+        vxorpd %ymm6, %ymm6, %ymm6
+        vxorpd %ymm7, %ymm7, %ymm7
+
+        testq $2, $2
+        je 2f
+        1:
+        vmovapd ($1), %ymm8
+        vmovapd 32($1), %ymm9
+
+        vbroadcastsd ($0), %ymm10
+        vfmadd231pd %ymm10, %ymm8, %ymm0
+        vfmadd231pd %ymm10, %ymm9, %ymm1
+
+        vbroadcastsd 8($0), %ymm11
+        vfmadd231pd %ymm11, %ymm8, %ymm2
+        vfmadd231pd %ymm11, %ymm9, %ymm3
+
+        vbroadcastsd 16($0), %ymm10
+        vfmadd231pd %ymm10, %ymm8, %ymm4
+        vfmadd231pd %ymm10, %ymm9, %ymm5
+
+        vbroadcastsd 24($0), %ymm11
+        vfmadd231pd %ymm11, %ymm8, %ymm6
+        vfmadd231pd %ymm11, %ymm9, %ymm7
+
+        addq $$32, $0
+        addq $$64, $1
+        decq $2
+        jnz 1b
+        2:
+
+        vmovapd %ymm0, ($3)
+        vmovapd %ymm1, 32($3)
+        vmovapd %ymm2, 64($3)
+        vmovapd %ymm3, 96($3)
+        vmovapd %ymm4, 128($3)
+        vmovapd %ymm5, 160($3)
+        vmovapd %ymm6, 192($3)
+        vmovapd %ymm7, 224($3)
+        "
+        : "+r"(a), "+r"(b), "+r"(kc)
+        : "r"(ab.as_mut_ptr())
+        : "ymm0", "ymm1", "ymm2", "ymm3", "ymm4", "ymm5", "ymm6", "ymm7",
+          "ymm8", "ymm9", "ymm10", "ymm11", "memory", "cc"
+        : "volatile"
+    );
+}
+
+/// Compute C += alpha * A * B on strided operands
+///
+/// A is a (m x k), B a (k x n) and C a (m x n) matrix, the element (i, j) of X is stored at
+/// x[i * rsx + j * csx]. This allows row and column major operands as well as transposed ones.
+///
+/// The loops around the microkernel follow the GotoBLAS/BLIS scheme: B is packed in blocks of
+/// (kc x nc) and A in blocks of (mc x kc), the blocks of A are distributed over the rayon workers.
+/// The block sizes are taken from tuning::block_sizes.
+#[allow(non_snake_case)]
+pub unsafe fn gemm(m: usize, n: usize, k: usize, alpha: f64,
+                   a: *const f64, rsa: usize, csa: usize,
+                   b: *const f64, rsb: usize, csb: usize,
+                   c: *mut f64, rsc: usize, csc: usize) {
+    let blocks = block_sizes::<f64>();
+    let (MC, KC, NC) = (blocks.mc, blocks.kc, blocks.nc);
+
+    let mut b_pack = aligned_buffer(KC * (min(NC, n) + NR));
+
+    for jc in (0..n).step_by(NC) {
+        let nc = min(NC, n - jc);
+
+        for pc in (0..k).step_by(KC) {
+            let kc = min(KC, k - pc);
+
+            pack_b(b.offset((pc * rsb + jc * csb) as isize), rsb, csb, kc, nc, b_pack.as_mut_ptr() as *mut f64);
+
+            let b_pack = &b_pack;
+            let a = SharedPtr(a as *mut f64);
+            let c = SharedPtr(c);
+
+            (0..(m + MC - 1) / MC).into_par_iter().for_each(|block| {
+                let ic = block * MC;
+                let mc = min(MC, m - ic);
+
+                let mut a_pack = aligned_buffer(KC * (MC + MR));
+                let a_pack = a_pack.as_mut_ptr() as *mut f64;
+                pack_a(a.0.offset((ic * rsa + pc * csa) as isize), rsa, csa, mc, kc, a_pack);
+
+                let mut ab = [f64x4::splat(0.); MR * NR / 4];
+
+                for (j_panel, jr) in (0..nc).step_by(NR).enumerate() {
+                    let nr = min(NR, nc - jr);
+                    let b_panel = (b_pack.as_ptr() as *const f64).offset((j_panel * kc * NR) as isize);
+
+                    for (i_panel, ir) in (0..mc).step_by(MR).enumerate() {
+                        let mr = min(MR, mc - ir);
+                        let a_panel = a_pack.offset((i_panel * kc * MR) as isize);
+
+                        microkernel(kc, a_panel, b_panel, &mut ab);
+
+                        let ab = &*(ab.as_ptr() as *const [f64; MR * NR]);
+                        for i in 0..mr {
+                            for j in 0..nr {
+                                let index = (ic + ir + i) * rsc + (jc + jr + j) * csc;
+                                *c.0.offset(index as isize) += alpha * ab[i * NR + j];
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Compute C += A * B, the operands may be stored in any dense layout
+#[allow(non_snake_case)]
+pub fn mult<LA: Dense, LB: Dense, LC: Dense>(A: &Matrix<f64, LA>, B: &Matrix<f64, LB>, C: &mut Matrix<f64, LC>) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    let ((rsa, csa), (rsb, csb), (rsc, csc)) = (A.strides(), B.strides(), C.strides());
+    unsafe {
+        gemm(C.rows, C.columns, A.columns, 1.,
+             A.data.as_ptr(), rsa, csa,
+             B.data.as_ptr(), rsb, csb,
+             C.data.as_mut_ptr(), rsc, csc);
+    }
+}
+
+/// Compute C += A * B, the operands may be blocks of bigger matrices
+#[allow(non_snake_case)]
+pub fn mult_view(A: MatrixView, B: MatrixView, C: &mut MatrixViewMut) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    unsafe {
+        gemm(C.rows, C.columns, A.columns, 1.,
+             A.as_ptr(), A.ld, 1,
+             B.as_ptr(), B.ld, 1,
+             C.as_mut_ptr(), C.ld, 1);
+    }
+}
+
+kernel!(Packed, "packed", f64, (Matrix, Matrix, Matrix), |m, k, n| true);
+
+view_kernel!(Packed, f64);
+
+generate_tests!();
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_edges() {
+        let blocks = block_sizes::<f64>();
+        let (MC, KC) = (blocks.mc, blocks.kc);
+
+        // sizes which are no multiples of the register and cache blocks
+        for &(m, k, n) in &[(1, 1, 1), (5, 3, 9), (MR + 1, KC + 1, NR + 1), (MC + 3, 17, 2 * NR - 1)] {
+            let a = Matrix::<f64>::random(m, k);
+            let b = Matrix::<f64>::random(k, n);
+
+            let mut compare = Matrix::<f64>::zero(m, n);
+            naive::mult(&a, &b, &mut compare);
+
+            let mut res = Matrix::<f64>::zero(m, n);
+            mult(&a, &b, &mut res);
+            assert_eq!(compare, res, "{}x{}x{}", m, k, n);
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_transposed_operands() {
+        let (m, k, n) = (13, 7, 11);
+        let a = Matrix::<f64>::random(m, k);
+        let b = Matrix::<f64>::random(k, n);
+        let bT = TransposedMatrix::from(b.clone());
+
+        let mut compare = Matrix::<f64>::zero(m, n);
+        naive::mult(&a, &b, &mut compare);
+
+        // B stored column major and 2 * A * B
+        let mut res = Matrix::<f64>::zero(m, n);
+        unsafe {
+            gemm(m, n, k, 2.,
+                 a.data.as_ptr(), k, 1,
+                 bT.data.as_ptr(), 1, k,
+                 res.data.as_mut_ptr(), n, 1);
+        }
+
+        compare.data.iter_mut().for_each(|x| *x *= 2.);
+        assert_eq!(compare, res);
+    }
+}
@@ -0,0 +1,121 @@
+
+#[macro_use]
+use super::macros;
+
+use std;
+
+extern crate rayon;
+
+use super::*;
+
+/// Sub-problems with no dimension larger than this are computed by naive_simd
+pub const BASE_SIZE: usize = 64;
+
+/// The position at which a dimension of length len is split
+///
+/// Column splits are rounded to whole simd vectors, so the rows of the right half stay aligned.
+fn split_point<T: Element>(len: usize, columns: bool) -> usize {
+    let half = len / 2;
+
+    if columns && half >= T::CHUNK_SIZE {
+        half - half % T::CHUNK_SIZE
+    }
+    else {
+        half
+    }
+}
+
+/// Compute C += A * B, halving the largest dimension until all are at most base
+///
+/// Splits of M and N produce independent blocks of C, which are computed in parallel with
+/// rayon::join. A split of K yields two products into the same C, which are done one after the
+/// other. The working set shrinks with every split, so every cache level is eventually used
+/// well without knowing its size.
+#[allow(non_snake_case)]
+pub fn mult_view_with<T: Element>(A: MatrixView<T>, B: MatrixView<T>, C: &mut MatrixViewMut<T>, base: usize) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    let (m, k, n) = (A.rows, A.columns, B.columns);
+    let largest = std::cmp::max(m, std::cmp::max(k, n));
+
+    if largest <= base.max(1) || m == 0 || k == 0 || n == 0 {
+        return naive_simd::mult_view(A, B, C);
+    }
+
+    if largest == m {
+        let row = split_point::<T>(m, false);
+        let (A_1, A_2) = A.split_rows(row);
+        let (mut C_1, mut C_2) = C.reborrow().split_rows(row);
+
+        rayon::join(|| mult_view_with(A_1, B, &mut C_1, base),
+                    || mult_view_with(A_2, B, &mut C_2, base));
+    }
+    else if largest == n {
+        let column = split_point::<T>(n, true);
+        let (B_1, B_2) = B.split_columns(column);
+        let (mut C_1, mut C_2) = C.reborrow().split_columns(column);
+
+        rayon::join(|| mult_view_with(A, B_1, &mut C_1, base),
+                    || mult_view_with(A, B_2, &mut C_2, base));
+    }
+    else {
+        let column = split_point::<T>(k, true);
+        let (A_1, A_2) = A.split_columns(column);
+        let (B_1, B_2) = B.split_rows(column);
+
+        mult_view_with(A_1, B_1, C, base);
+        mult_view_with(A_2, B_2, C, base);
+    }
+}
+
+/// Compute C += A * B on blocks of bigger matrices, see mult_view_with
+#[allow(non_snake_case)]
+pub fn mult_view<T: Element>(A: MatrixView<T>, B: MatrixView<T>, C: &mut MatrixViewMut<T>) {
+    mult_view_with(A, B, C, BASE_SIZE)
+}
+
+#[allow(non_snake_case)]
+pub fn mult<T: Element>(A: &Matrix<T>, B: &Matrix<T>, C: &mut Matrix<T>) {
+    mult_view(A.view(), B.view(), &mut C.view_mut())
+}
+
+kernel!(Recursive, "recursive", |m, k, n| true);
+
+view_kernel!(Recursive);
+
+generate_tests!();
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shapes() {
+        // every dimension is the largest once, including odd and degenerate shapes
+        let shapes = [(1, 1, 1), (130, 7, 9), (9, 130, 7), (7, 9, 130), (257, 129, 65), (3, 200, 1)];
+
+        for &(m, k, n) in &shapes {
+            let a = Matrix::<f64>::random(m, k);
+            let b = Matrix::<f64>::random(k, n);
+
+            let mut compare = Matrix::<f64>::zero(m, n);
+            naive::mult(&a, &b, &mut compare);
+
+            for &base in &[1, 4, 16, BASE_SIZE] {
+                let mut res = Matrix::zero(m, n);
+                mult_view_with(a.view(), b.view(), &mut res.view_mut(), base);
+                assert_eq!(res, compare, "{}x{}x{} base {}", m, k, n, base);
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_point() {
+        assert_eq!(split_point::<f64>(130, false), 65);
+        assert_eq!(split_point::<f64>(130, true), 64);
+        assert_eq!(split_point::<f64>(5, true), 2);
+        assert_eq!(split_point::<f32>(100, true), 48);
+    }
+}
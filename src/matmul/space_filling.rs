@@ -0,0 +1,126 @@
+
+#[macro_use]
+use super::macros;
+
+extern crate rayon;
+
+use super::*;
+
+/// A square range of blocks of C, the blocks are stored contiguously on the curve
+struct Target<'a, T: 'a> {
+    data: &'a mut [T],
+    /// The first block row and column of the range
+    row: usize,
+    column: usize,
+    /// The position of the first block of data on the curve
+    first: usize,
+}
+
+/// Compute C += A * B on ranges of size x size blocks starting at the given blocks of A and B
+///
+/// The four quadrants of C are disjoint parts of its data, so they are computed in parallel with
+/// rayon::join. A single block is multiplied with naive_simd.
+#[allow(non_snake_case)]
+fn multiply<T: Element, O: Curve>(A: &CurveMatrix<T, O>, B: &CurveMatrix<T, O>, (ar, ac): (usize, usize), (br, bc): (usize, usize), C: Target<T>, size: usize) {
+    let bs = A.block_size();
+
+    if size == 1 {
+        let a = MatrixView::new(bs, bs, bs, A.block(ar, ac));
+        let b = MatrixView::new(bs, bs, bs, B.block(br, bc));
+        naive_simd::mult_view(a, b, &mut MatrixViewMut::new(bs, bs, bs, C.data));
+        return;
+    }
+
+    let half = size / 2;
+    let order = A.order();
+    let (row, column, first) = (C.row, C.column, C.first);
+
+    // the position of every quadrant of C on the curve, relative to the first block
+    let position = |q: usize| (O::index(order, row + q / 2 * half, column + q % 2 * half) - first) / (half * half);
+
+    let mut parts = C.data.chunks_mut(half * half * bs * bs).map(Some).collect::<Vec<_>>();
+    let mut quadrant = |q: usize| parts[position(q)].take().unwrap();
+    let (c_11, c_12, c_21, c_22) = (quadrant(0), quadrant(1), quadrant(2), quadrant(3));
+
+    let task = |q: usize, data: &mut [T]| {
+        let (i, j) = (q / 2, q % 2);
+
+        for k in 0..2 {
+            let target = Target {
+                data: &mut *data,
+                row: row + i * half,
+                column: column + j * half,
+                first: first + position(q) * half * half,
+            };
+            multiply(A, B, (ar + i * half, ac + k * half), (br + k * half, bc + j * half), target, half);
+        }
+    };
+
+    rayon::join(|| rayon::join(|| task(0, c_11), || task(1, c_12)),
+                || rayon::join(|| task(2, c_21), || task(3, c_22)));
+}
+
+/// Compute C += A * B on matrices stored along a space filling curve
+///
+/// The recursion halves the block grid until single blocks are reached. Every quadrant is
+/// contiguous in memory, so each level works on compact parts of A, B and C.
+#[allow(non_snake_case)]
+pub fn mult<T: Element, O: Curve>(A: &CurveMatrix<T, O>, B: &CurveMatrix<T, O>, C: &mut CurveMatrix<T, O>) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    assert!(A.block_size() == B.block_size() && B.block_size() == C.block_size(), "the block sizes differ");
+    assert!(A.order() == B.order() && B.order() == C.order(), "the block grids differ");
+
+    let size = 1 << C.order();
+    let target = Target { data: &mut C.data[..], row: 0, column: 0, first: 0 };
+
+    multiply(A, B, (0, 0), (0, 0), target, size);
+}
+
+kernel!(MortonRecursive, "morton", (MortonMatrix, MortonMatrix, MortonMatrix), |m, k, n| m == k && k == n);
+
+kernel!(HilbertRecursive, "hilbert", (HilbertMatrix, HilbertMatrix, HilbertMatrix), |m, k, n| m == k && k == n);
+
+generate_tests!(MortonMatrix);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check<O: Curve>(n: usize, bs: usize) {
+        let a = Matrix::<f64>::random(n, n);
+        let b = Matrix::<f64>::random(n, n);
+
+        let mut compare = Matrix::<f64>::zero(n, n);
+        naive::mult(&a, &b, &mut compare);
+
+        let mut c = CurveMatrix::<f64, O>::zero_with_block_size(n, n, bs);
+        mult(&CurveMatrix::<f64, O>::with_block_size(a, bs), &CurveMatrix::<f64, O>::with_block_size(b, bs), &mut c);
+
+        assert_eq!(Matrix::from(c), compare, "{}x{} in blocks of {}", n, n, bs);
+    }
+
+    #[test]
+    fn test_curves() {
+        for &(n, bs) in &[(1, 4), (8, 2), (13, 4), (32, 8), (67, 16)] {
+            check::<Morton>(n, bs);
+            check::<Hilbert>(n, bs);
+        }
+    }
+
+    #[test]
+    fn test_hilbert_kernel() {
+        let n = 40;
+        let a = Matrix::<f64>::random(n, n);
+        let b = Matrix::<f64>::random(n, n);
+
+        let mut compare = Matrix::<f64>::zero(n, n);
+        naive::mult(&a, &b, &mut compare);
+
+        let mut c = HilbertMatrix::from(Matrix::zero(n, n));
+        MatMul::mult(&HilbertRecursive, &HilbertMatrix::from(a), &HilbertMatrix::from(b), &mut c);
+        assert_eq!(Matrix::from(c), compare);
+    }
+}
@@ -0,0 +1,314 @@
+
+#[macro_use]
+use super::macros;
+
+use std::sync::RwLock;
+
+use super::*;
+
+/// Quadrants with at most this many rows are multiplied with the classical algorithm
+pub const DEFAULT_CUTOFF: usize = 512;
+
+lazy_static! {
+    static ref CUTOFF: RwLock<usize> = RwLock::new(DEFAULT_CUTOFF);
+}
+
+/// The cutoff used by the strassen and winograd kernels
+pub fn cutoff() -> usize {
+    *CUTOFF.read().unwrap()
+}
+
+/// Set the cutoff used by the strassen and winograd kernels
+pub fn set_cutoff(cutoff: usize) {
+    *CUTOFF.write().unwrap() = cutoff;
+}
+
+/// The scheme which replaces the eight quadrant products by seven
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Strassen,
+    Winograd,
+}
+
+/// One of the seven products as coefficients of the quadrants 11, 12, 21 and 22
+///
+/// The product (sum a_q * A_q) * (sum b_q * B_q) is added to C_q with the coefficient c_q.
+type Product = ([i8; 4], [i8; 4], [i8; 4]);
+
+static STRASSEN: [Product; 7] = [
+    ([1, 0, 0, 1], [1, 0, 0, 1], [1, 0, 0, 1]),
+    ([0, 0, 1, 1], [1, 0, 0, 0], [0, 0, 1, -1]),
+    ([1, 0, 0, 0], [0, 1, 0, -1], [0, 1, 0, 1]),
+    ([0, 0, 0, 1], [-1, 0, 1, 0], [1, 0, 1, 0]),
+    ([1, 1, 0, 0], [0, 0, 0, 1], [-1, 1, 0, 0]),
+    ([-1, 0, 1, 0], [1, 1, 0, 0], [0, 0, 0, 1]),
+    ([0, 1, 0, -1], [0, 0, 1, 1], [1, 0, 0, 0]),
+];
+
+/// Winograd's variant with the sums S1..S4 and T1..T4 expanded into quadrants
+///
+/// The sums are formed from the quadrants directly instead of from each other, which costs a few
+/// additions but no temporaries for the intermediate sums.
+static WINOGRAD: [Product; 7] = [
+    ([1, 0, 0, 0], [1, 0, 0, 0], [1, 1, 1, 1]),
+    ([0, 1, 0, 0], [0, 0, 1, 0], [1, 0, 0, 0]),
+    ([1, 1, -1, -1], [0, 0, 0, 1], [0, 1, 0, 0]),
+    ([0, 0, 0, 1], [1, -1, -1, 1], [0, 0, -1, 0]),
+    ([0, 0, 1, 1], [-1, 1, 0, 0], [0, 1, 0, 1]),
+    ([-1, 0, 1, 1], [1, -1, 0, 1], [0, 1, 1, 1]),
+    ([1, 0, -1, 0], [0, -1, 0, 1], [0, 0, 1, 1]),
+];
+
+/// A square range of blocks of a tiled matrix, starting at block (row, column)
+struct Quad<'a, T: 'a> {
+    matrix: &'a TileMatrix<T>,
+    row: usize,
+    column: usize,
+}
+
+impl<'a, T> Clone for Quad<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for Quad<'a, T> {}
+
+impl<'a, T: Element> Quad<'a, T> {
+    fn whole(matrix: &'a TileMatrix<T>) -> Self {
+        Quad { matrix: matrix, row: 0, column: 0 }
+    }
+
+    /// Quadrant 0, 1, 2 or 3 (11, 12, 21, 22) of a range of size 2 * half blocks
+    fn quadrant(&self, index: usize, half: usize) -> Self {
+        Quad { matrix: self.matrix, row: self.row + index / 2 * half, column: self.column + index % 2 * half }
+    }
+
+    fn block(&self, i: usize, j: usize) -> &'a Matrix<T> {
+        &self.matrix[(self.row + i, self.column + j)]
+    }
+}
+
+/// The index of the only quadrant if coeffs selects exactly one with coefficient 1
+fn single(coeffs: &[i8; 4]) -> Option<usize> {
+    match coeffs.iter().filter(|&&c| c != 0).count() {
+        1 => coeffs.iter().position(|&c| c == 1),
+        _ => None,
+    }
+}
+
+/// A tiled zero matrix of half x half blocks of size bs
+fn temporary<T: Element>(half: usize, bs: usize) -> TileMatrix<T> {
+    TileMatrix::zero(half * bs, half * bs, half, half)
+}
+
+/// The sum of the quadrants of x weighted with coeffs, None if it is a single quadrant
+fn combine<T: Element>(x: Quad<T>, half: usize, coeffs: &[i8; 4]) -> Option<TileMatrix<T>> {
+    if single(coeffs).is_some() {
+        return None;
+    }
+
+    let mut ret = temporary(half, x.block(0, 0).rows);
+
+    for i in 0..half {
+        for j in 0..half {
+            let block = &mut ret[(i, j)];
+
+            for q in (0..4).filter(|&q| coeffs[q] != 0) {
+                let source = x.quadrant(q, half).block(i, j);
+
+                for (r, &s) in block.data.iter_mut().zip(source.data.iter()) {
+                    *r = if coeffs[q] > 0 { *r + s } else { *r - s };
+                }
+            }
+        }
+    }
+
+    Some(ret)
+}
+
+/// Add product to the quadrants of the range of C at (row, column) weighted with coeffs
+fn accumulate<T: Element>(c: &mut TileMatrix<T>, (row, column): (usize, usize), half: usize, product: &TileMatrix<T>, coeffs: &[i8; 4]) {
+    for q in (0..4).filter(|&q| coeffs[q] != 0) {
+        for i in 0..half {
+            for j in 0..half {
+                let block = &mut c[(row + q / 2 * half + i, column + q % 2 * half + j)];
+
+                for (r, &p) in block.data.iter_mut().zip(product[(i, j)].data.iter()) {
+                    *r = if coeffs[q] > 0 { *r + p } else { *r - p };
+                }
+            }
+        }
+    }
+}
+
+/// Compute C += A * B on ranges of size x size blocks, C is the range at (row, column) of c
+fn multiply<T: Element>(a: Quad<T>, b: Quad<T>, c: &mut TileMatrix<T>, (row, column): (usize, usize), size: usize, variant: Variant, cutoff: usize) {
+    let bs = a.block(0, 0).rows;
+
+    // odd numbers of blocks can not be split, they are multiplied classically
+    if size % 2 != 0 || size * bs <= cutoff {
+        for i in 0..size {
+            for j in 0..size {
+                for k in 0..size {
+                    naive_simd::mult(a.block(i, k), b.block(k, j), &mut c[(row + i, column + j)]);
+                }
+            }
+        }
+        return;
+    }
+
+    let half = size / 2;
+    let products = match variant {
+        Variant::Strassen => &STRASSEN,
+        Variant::Winograd => &WINOGRAD,
+    };
+
+    for &(ref coeffs_a, ref coeffs_b, ref coeffs_c) in products.iter() {
+        let sum_a = combine(a, half, coeffs_a);
+        let sum_b = combine(b, half, coeffs_b);
+
+        let left = match sum_a {
+            Some(ref sum) => Quad::whole(sum),
+            None => a.quadrant(single(coeffs_a).unwrap(), half),
+        };
+        let right = match sum_b {
+            Some(ref sum) => Quad::whole(sum),
+            None => b.quadrant(single(coeffs_b).unwrap(), half),
+        };
+
+        match single(coeffs_c) {
+            // a product which only goes to one quadrant is accumulated in place
+            Some(q) => {
+                let position = (row + q / 2 * half, column + q % 2 * half);
+                multiply(left, right, c, position, half, variant, cutoff);
+            }
+            None => {
+                let mut product = temporary(half, bs);
+                multiply(left, right, &mut product, (0, 0), half, variant, cutoff);
+                accumulate(c, (row, column), half, &product, coeffs_c);
+            }
+        }
+    }
+}
+
+/// Compute C += A * B with the given variant, recursing until a quadrant has at most cutoff rows
+///
+/// The recursion works on the blocks of the tiled matrices, so the quadrants are never copied and
+/// the base case is the naive_simd kernel on whole blocks. Block grids which are not square or
+/// have an odd number of blocks at some level are multiplied classically from there on.
+#[allow(non_snake_case)]
+pub fn mult_with<T: Element>(A: &TileMatrix<T>, B: &TileMatrix<T>, C: &mut TileMatrix<T>, variant: Variant, cutoff: usize) {
+    assert_eq!(A.columns, B.rows);
+    assert_eq!(A.rows, C.rows);
+    assert_eq!(B.columns, C.columns);
+
+    let size = A.blocks_down;
+    let square = [A.blocks_right, B.blocks_down, B.blocks_right, C.blocks_down, C.blocks_right].iter().all(|&blocks| blocks == size);
+
+    if !square {
+        return tiled::mult(A, B, C);
+    }
+
+    multiply(Quad::whole(A), Quad::whole(B), C, (0, 0), size, variant, cutoff);
+}
+
+/// Compute C += A * B with Strassen's algorithm, see mult_with
+#[allow(non_snake_case)]
+pub fn mult<T: Element>(A: &TileMatrix<T>, B: &TileMatrix<T>, C: &mut TileMatrix<T>) {
+    mult_with(A, B, C, Variant::Strassen, cutoff());
+}
+
+kernel!(Strassen, "strassen", (TileMatrix, TileMatrix, TileMatrix), |m, k, n| m == k && k == n && n % T::CHUNK_SIZE == 0);
+
+generate_tests!(TileMatrix);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate rand;
+
+    use std;
+
+    fn reference(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut ret = Matrix::zero(a.rows, b.columns);
+        naive::mult(a, b, &mut ret);
+        ret
+    }
+
+    fn run(a: &Matrix, b: &Matrix, bs: usize, variant: Variant, cutoff: usize) -> Matrix {
+        let a = TileMatrix::with_block_size(a.clone(), bs);
+        let b = TileMatrix::with_block_size(b.clone(), bs);
+        let mut c = TileMatrix::with_block_size(Matrix::zero(a.rows, b.columns), bs);
+
+        mult_with(&a, &b, &mut c, variant, cutoff);
+        Matrix::from(c)
+    }
+
+    #[test]
+    fn test_exact() {
+        // integer valued matrices are multiplied without rounding, so the result has to be exact
+        for &variant in &[Variant::Strassen, Variant::Winograd] {
+            // 4 blocks recurse twice, 6 blocks once before the odd grid of 3 is done classically
+            for &(n, bs, cutoff) in &[(64, 16, 16), (96, 16, 16), (40, 16, 16), (64, 16, 64), (4, 16, 1)] {
+                let a = Matrix::<f64>::random(n, n);
+                let b = Matrix::<f64>::random(n, n);
+
+                assert_eq!(run(&a, &b, bs, variant, cutoff), reference(&a, &b), "{:?} n {} bs {} cutoff {}", variant, n, bs, cutoff);
+            }
+        }
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let a = Matrix::<f64>::random(64, 64);
+        let b = Matrix::<f64>::random(64, 64);
+
+        let mut c = TileMatrix::with_block_size(Matrix::<f64>::random(64, 64), 16);
+        mult_with(&TileMatrix::with_block_size(a.clone(), 16), &TileMatrix::with_block_size(b.clone(), 16), &mut c, Variant::Winograd, 16);
+
+        let mut compare = Matrix::<f64>::random(64, 64);
+        naive::mult(&a, &b, &mut compare);
+        assert_eq!(Matrix::from(c), compare);
+    }
+
+    /// Higham's bound on the max norm error of the fast algorithms with n0 the cutoff:
+    /// ((n / n0)^log2(12) * (n0^2 + 5 n0) - 5 n) for Strassen and
+    /// ((n / n0)^log2(18) * (n0^2 + 6 n0) - 6 n) for Winograd, times u * |A| * |B|
+    fn error_bound(variant: Variant, n: usize, n0: usize, u: f64, norm_a: f64, norm_b: f64) -> f64 {
+        let (base, linear) = match variant {
+            Variant::Strassen => (12f64, 5.),
+            Variant::Winograd => (18f64, 6.),
+        };
+        let (n, n0) = (n as f64, n0 as f64);
+
+        ((n / n0).powf(base.log2()) * (n0 * n0 + linear * n0) - linear * n) * u * norm_a * norm_b
+    }
+
+    fn max_norm(matrix: &Matrix) -> f64 {
+        matrix.data.iter().fold(0., |max, x| x.abs().max(max))
+    }
+
+    #[test]
+    fn test_error_bound() {
+        let n = 256;
+
+        for &variant in &[Variant::Strassen, Variant::Winograd] {
+            for &cutoff in &[16, 64] {
+                let a = Matrix::new(n, n, (0..n * n).map(|_| rand::random::<f64>() - 0.5).collect());
+                let b = Matrix::new(n, n, (0..n * n).map(|_| rand::random::<f64>() - 0.5).collect());
+
+                let res = run(&a, &b, 16, variant, cutoff);
+                let compare = reference(&a, &b);
+
+                let error = res.data.iter().zip(compare.data.iter()).fold(0., |max, (x, y)| (x - y).abs().max(max));
+                let bound = error_bound(variant, n, cutoff, std::f64::EPSILON / 2., max_norm(&a), max_norm(&b));
+
+                assert!(error <= bound, "{:?} cutoff {}: error {} exceeds {}", variant, cutoff, error, bound);
+                // the fast algorithms are less accurate, but not by orders of magnitude here
+                assert_matrix_close!(res, compare, Tolerance::new(1e-10, 0., 0), "with {:?} cutoff {}", variant, cutoff);
+            }
+        }
+    }
+}
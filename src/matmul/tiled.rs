@@ -5,11 +5,11 @@ use super::macros;
 use super::*;
 
 #[allow(non_snake_case)]
-pub fn mult(A: &TileMatrix, B: &TileMatrix, C: &mut TileMatrix) {
+pub fn mult<T: Element>(A: &TileMatrix<T>, B: &TileMatrix<T>, C: &mut TileMatrix<T>) {
     assert_eq!(A.columns, B.rows);
     assert_eq!(A.blocks_right, B.blocks_down);
     assert_eq!(A.rows, C.rows);
-    assert_eq!(A.blocks_down, C.blocks_right);
+    assert_eq!(A.blocks_down, C.blocks_down);
     assert_eq!(B.columns, C.columns);
     assert_eq!(B.blocks_right, C.blocks_right);
 
@@ -22,4 +22,6 @@ pub fn mult(A: &TileMatrix, B: &TileMatrix, C: &mut TileMatrix) {
     }
 }
 
+kernel!(Tiled, "tiled", (TileMatrix, TileMatrix, TileMatrix), |m, k, n| m == k && k == n && n % T::CHUNK_SIZE == 0);
+
 generate_tests!(TileMatrix);
\ No newline at end of file
@@ -0,0 +1,189 @@
+
+use std;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::RwLock;
+
+use hwloc::ObjectType;
+
+use super::TOPOLOGY;
+use super::matrix::Element;
+use super::packed::{MR, NR};
+
+/// The cache sizes in bytes and the number of cores of this machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheInfo {
+    pub l1: usize,
+    pub l2: usize,
+    pub l3: usize,
+    pub cores: usize,
+    pub threads: usize,
+}
+
+impl CacheInfo {
+    /// Read the cache sizes from the hwloc topology
+    ///
+    /// Levels which hwloc does not report fall back to the sizes of a Haswell core.
+    pub fn detect() -> Self {
+        let mut sizes = [0usize; 3];
+
+        if let Ok(caches) = TOPOLOGY.objects_with_type(&ObjectType::Cache) {
+            for cache in caches {
+                if let Some(attributes) = cache.cache_attributes() {
+                    let level = attributes.depth as usize;
+                    if level >= 1 && level <= 3 {
+                        // shared caches appear once per socket, take the smallest instance
+                        let size = attributes.size as usize;
+                        if sizes[level - 1] == 0 || size < sizes[level - 1] {
+                            sizes[level - 1] = size;
+                        }
+                    }
+                }
+            }
+        }
+
+        let count = |object_type: ObjectType| {
+            TOPOLOGY.objects_with_type(&object_type).map(|objects| objects.len()).unwrap_or(0)
+        };
+        let cores = std::cmp::max(count(ObjectType::Core), 1);
+        let threads = std::cmp::max(count(ObjectType::PU), cores);
+
+        Self {
+            l1: if sizes[0] > 0 { sizes[0] } else { 32 * 1024 },
+            l2: if sizes[1] > 0 { sizes[1] } else { 256 * 1024 },
+            l3: if sizes[2] > 0 { sizes[2] } else { 8 * 1024 * 1024 },
+            cores: cores,
+            threads: threads,
+        }
+    }
+}
+
+impl fmt::Display for CacheInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L1 {} KiB, L2 {} KiB, L3 {} KiB, {} cores, {} threads",
+               self.l1 / 1024, self.l2 / 1024, self.l3 / 1024, self.cores, self.threads)
+    }
+}
+
+/// The block sizes used by the tiled and packed kernels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSizes {
+    /// Edge length of the square blocks of a TileMatrix, two blocks fit into the L2 cache
+    pub tile: usize,
+    /// Rows of A packed at once, the packed block of A (mc x kc) fits into half of the L2 cache
+    pub mc: usize,
+    /// Depth of the packed panels, a panel of B (kc x NR) fits into half of the L1 cache
+    pub kc: usize,
+    /// Columns of B packed at once, the packed block of B (kc x nc) fits into half of the L3 cache
+    pub nc: usize,
+}
+
+/// Round value down to a multiple of step, but at least step
+fn round_down(value: usize, step: usize) -> usize {
+    std::cmp::max(value / step * step, step)
+}
+
+impl BlockSizes {
+    /// Derive the block sizes for elements of type T from the given caches
+    pub fn from_caches<T: Element>(caches: &CacheInfo) -> Self {
+        let size = std::mem::size_of::<T>();
+
+        // the fast path of naive_simd needs multiples of 4 simd vectors
+        let tile = ((caches.l2 / (2 * size)) as f64).sqrt() as usize;
+        let kc = round_down(caches.l1 / (2 * NR * size), 8);
+
+        Self {
+            tile: round_down(tile, 4 * T::CHUNK_SIZE),
+            mc: round_down(caches.l2 / (2 * kc * size), MR),
+            kc: kc,
+            nc: round_down(caches.l3 / (2 * kc * size), NR),
+        }
+    }
+}
+
+impl fmt::Display for BlockSizes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tile {}, mc {}, kc {}, nc {}", self.tile, self.mc, self.kc, self.nc)
+    }
+}
+
+lazy_static! {
+    static ref CACHES: CacheInfo = CacheInfo::detect();
+    static ref OVERRIDES: RwLock<HashMap<TypeId, BlockSizes>> = RwLock::new(HashMap::new());
+}
+
+/// The caches of this machine, they are detected once
+pub fn caches() -> CacheInfo {
+    *CACHES
+}
+
+/// The block sizes for elements of type T
+///
+/// These are derived from the caches of this machine, unless they are overridden for T with
+/// set_block_sizes.
+pub fn block_sizes<T: Element>() -> BlockSizes {
+    match OVERRIDES.read().unwrap().get(&TypeId::of::<T>()) {
+        Some(&blocks) => blocks,
+        None => BlockSizes::from_caches::<T>(&CACHES),
+    }
+}
+
+/// Override the derived block sizes for elements of type T
+///
+/// The override is global, matrices converted before and after the change of the tile size
+/// have different block grids and can not be multiplied with each other.
+pub fn set_block_sizes<T: Element>(blocks: BlockSizes) {
+    assert!(blocks.tile > 0 && blocks.mc > 0 && blocks.kc > 0 && blocks.nc > 0, "block sizes must be positive");
+    OVERRIDES.write().unwrap().insert(TypeId::of::<T>(), blocks);
+}
+
+/// Go back to the block sizes derived from the caches for elements of type T
+pub fn reset_block_sizes<T: Element>() {
+    OVERRIDES.write().unwrap().remove(&TypeId::of::<T>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::matrix::Complex;
+
+    const HASWELL: CacheInfo = CacheInfo { l1: 32 * 1024, l2: 256 * 1024, l3: 8 * 1024 * 1024, cores: 4, threads: 8 };
+
+    #[test]
+    fn test_from_caches() {
+        let blocks = BlockSizes::from_caches::<f64>(&HASWELL);
+        assert_eq!(blocks, BlockSizes { tile: 128, mc: 64, kc: 256, nc: 2048 });
+
+        let blocks = BlockSizes::from_caches::<f32>(&HASWELL);
+        assert_eq!(blocks.tile % 32, 0);
+        assert_eq!(blocks.kc, 512);
+
+        // a tiny cache still results in usable sizes
+        let tiny = CacheInfo { l1: 1024, l2: 1024, l3: 1024, cores: 1, threads: 1 };
+        let blocks = BlockSizes::from_caches::<f64>(&tiny);
+        assert_eq!(blocks, BlockSizes { tile: 16, mc: 8, kc: 8, nc: 8 });
+    }
+
+    #[test]
+    fn test_detect() {
+        let caches = caches();
+        assert!(caches.l1 > 0 && caches.l1 <= caches.l2 && caches.l2 <= caches.l3);
+        assert!(caches.cores > 0 && caches.cores <= caches.threads);
+    }
+
+    #[test]
+    fn test_override() {
+        // no other test uses Complex<i32>, so the override does not change the block grids of the
+        // tests which run in parallel
+        let blocks = BlockSizes { tile: 64, mc: 32, kc: 128, nc: 512 };
+
+        set_block_sizes::<Complex<i32>>(blocks);
+        assert_eq!(block_sizes::<Complex<i32>>(), blocks);
+        assert_eq!(block_sizes::<f64>(), BlockSizes::from_caches::<f64>(&caches()));
+
+        reset_block_sizes::<Complex<i32>>();
+        assert_eq!(block_sizes::<Complex<i32>>(), BlockSizes::from_caches::<Complex<i32>>(&caches()));
+    }
+}
@@ -0,0 +1,16 @@
+
+#[macro_use]
+use super::macros;
+
+use super::*;
+use super::strassen::{mult_with, cutoff, Variant};
+
+/// Compute C += A * B with Winograd's variant of Strassen's algorithm, see strassen::mult_with
+#[allow(non_snake_case)]
+pub fn mult<T: Element>(A: &TileMatrix<T>, B: &TileMatrix<T>, C: &mut TileMatrix<T>) {
+    mult_with(A, B, C, Variant::Winograd, cutoff());
+}
+
+kernel!(Winograd, "winograd", (TileMatrix, TileMatrix, TileMatrix), |m, k, n| m == k && k == n && n % T::CHUNK_SIZE == 0);
+
+generate_tests!(TileMatrix);
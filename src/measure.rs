@@ -0,0 +1,193 @@
+
+use std;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Convert a duration to seconds
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+/// The number of floating point operations of a (m x k) * (k x n) multiplication
+pub fn matmul_flop(m: usize, k: usize, n: usize) -> u64 {
+    2 * m as u64 * n as u64 * k as u64
+}
+
+/// Minimum, median and maximum runtime in seconds over several runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub median: f64,
+    pub max: f64,
+    pub runs: usize,
+}
+
+impl Stats {
+    /// Compute the statistics of the given runtimes
+    pub fn new(times: &[Duration]) -> Self {
+        assert!(!times.is_empty(), "at least one run is needed");
+
+        let mut secs = times.iter().map(|&t| seconds(t)).collect::<Vec<_>>();
+        secs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let len = secs.len();
+        let median = if len % 2 == 0 { (secs[len / 2 - 1] + secs[len / 2]) / 2. } else { secs[len / 2] };
+
+        Self {
+            min: secs[0],
+            median: median,
+            max: secs[len - 1],
+            runs: len,
+        }
+    }
+
+    /// The rate in units per second for the minimum, median and maximum runtime
+    ///
+    /// The first entry is the best rate, it belongs to the minimum runtime.
+    pub fn rate(&self, units: u64) -> (f64, f64, f64) {
+        let units = units as f64;
+        (units / self.min, units / self.median, units / self.max)
+    }
+
+    /// The achieved GFLOP/s for the minimum, median and maximum runtime
+    pub fn gflops(&self, flop: u64) -> (f64, f64, f64) {
+        let (best, median, worst) = self.rate(flop);
+        (best / 1e9, median / 1e9, worst / 1e9)
+    }
+
+    /// The achieved GB/s for the minimum, median and maximum runtime
+    pub fn gbytes(&self, bytes: u64) -> (f64, f64, f64) {
+        let (best, median, worst) = self.rate(bytes);
+        (best / 1e9, median / 1e9, worst / 1e9)
+    }
+}
+
+/// Run f warmup times without and reps times with measuring
+///
+/// Both f and reset get the state to work on, reset is called after every run of f and is not
+/// measured.
+pub fn measure<S, F, R>(warmup: usize, reps: usize, state: &mut S, mut f: F, mut reset: R) -> Stats
+    where F: FnMut(&mut S), R: FnMut(&mut S)
+{
+    for _ in 0..warmup {
+        f(state);
+        reset(state);
+    }
+
+    let times = (0..reps).map(|_| {
+        let start = Instant::now();
+        f(state);
+        let time = start.elapsed();
+        reset(state);
+        time
+    }).collect::<Vec<_>>();
+
+    Stats::new(&times)
+}
+
+/// The number of warm-up runs of the #[bench] benchmarks
+pub const BENCH_WARMUP: usize = 1;
+
+/// The number of measured runs of the #[bench] benchmarks
+pub const BENCH_REPS: usize = 10;
+
+/// Measure a #[bench] benchmark and write its report to stderr
+///
+/// libtest's bencher only shows the mean time per iteration, so the benchmarks are measured here
+/// instead of in bencher.iter. The report goes to stderr and leaves the results of libtest on
+/// stdout intact.
+pub fn bench<S, F, R>(name: &str, flop: u64, bytes: u64, state: &mut S, f: F, reset: R) -> Report
+    where F: FnMut(&mut S), R: FnMut(&mut S)
+{
+    let report = Report {
+        name: name.to_string(),
+        stats: measure(BENCH_WARMUP, BENCH_REPS, state, f, reset),
+        flop: flop,
+        bytes: bytes,
+    };
+
+    eprintln!("{}", report);
+    report
+}
+
+/// The result of a benchmark with the work done per run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub name: String,
+    pub stats: Stats,
+    pub flop: u64,
+    pub bytes: u64,
+}
+
+impl Report {
+    /// The header for the CSV output of reports
+    pub fn csv_header() -> &'static str {
+        "name,runs,min_s,median_s,max_s,gflops_best,gflops_median,gflops_worst,gbytes_best,gbytes_median,gbytes_worst"
+    }
+
+    /// The header for the text output of reports
+    pub fn text_header() -> String {
+        format!("{:<32} {:>5} {:>12} {:>12} {:>12} {:>26} {:>26}",
+                "name", "runs", "min [s]", "median [s]", "max [s]",
+                "GFLOP/s best/med/worst", "GB/s best/med/worst")
+    }
+
+    pub fn csv(&self) -> String {
+        let (fb, fm, fw) = self.stats.gflops(self.flop);
+        let (bb, bm, bw) = self.stats.gbytes(self.bytes);
+        format!("{},{},{},{},{},{},{},{},{},{},{}",
+                self.name, self.stats.runs, self.stats.min, self.stats.median, self.stats.max,
+                fb, fm, fw, bb, bm, bw)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (fb, fm, fw) = self.stats.gflops(self.flop);
+        let (bb, bm, bw) = self.stats.gbytes(self.bytes);
+        write!(f, "{:<32} {:>5} {:>12.6} {:>12.6} {:>12.6} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2}",
+               self.name, self.stats.runs, self.stats.min, self.stats.median, self.stats.max,
+               fb, fm, fw, bb, bm, bw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::new(&[Duration::new(3, 0), Duration::new(1, 0), Duration::new(2, 0)]);
+        assert_eq!(stats, Stats { min: 1., median: 2., max: 3., runs: 3 });
+
+        let stats = Stats::new(&[Duration::new(4, 0), Duration::new(1, 0), Duration::new(2, 0), Duration::new(3, 0)]);
+        assert_eq!(stats.median, 2.5);
+    }
+
+    #[test]
+    fn test_measure() {
+        let mut runs = 0;
+        let stats = measure(2, 5, &mut runs, |runs| *runs += 1, |_| {});
+
+        assert_eq!(runs, 7);
+        assert_eq!(stats.runs, 5);
+        assert!(stats.min <= stats.median && stats.median <= stats.max);
+    }
+
+    #[test]
+    fn test_bench() {
+        let mut runs = 0;
+        let report = bench("count", 2, 8, &mut runs, |runs| *runs += 1, |_| {});
+
+        assert_eq!(runs, BENCH_WARMUP + BENCH_REPS);
+        assert_eq!(report.stats.runs, BENCH_REPS);
+        assert_eq!((report.flop, report.bytes), (2, 8));
+    }
+
+    #[test]
+    fn test_rates() {
+        let stats = Stats::new(&[Duration::new(1, 0), Duration::new(2, 0), Duration::new(4, 0)]);
+        assert_eq!(stats.gflops(matmul_flop(1000, 1000, 1000)), (2., 1., 0.5));
+        assert_eq!(stats.gbytes(4_000_000_000), (4., 2., 1.));
+    }
+}
@@ -0,0 +1,282 @@
+
+extern crate rayon;
+
+use std;
+use std::fmt;
+
+use self::rayon::prelude::*;
+
+use measure::{measure, matmul_flop, Stats};
+use triades::{Stream, NumType, prepare_arrays_with_length};
+
+/// The floating point operations of one iteration of the FMA microkernel
+const FMA_FLOP_PER_ITERATION: u64 = 10 * 4 * 2;
+
+/// Do iterations rounds of 10 independent fused multiply adds on 4 doubles each
+///
+/// All operands are kept in registers, so this measures the peak FLOP rate of one core.
+#[inline(never)]
+fn fma_microkernel(iterations: u64) {
+    let mut remaining = iterations;
+
+    if remaining == 0 {
+        return;
+    }
+
+    unsafe {
+        asm!(
+            "
+            vxorpd %ymm0, %ymm0, %ymm0
+            vxorpd %ymm1, %ymm1, %ymm1
+            vxorpd %ymm2, %ymm2, %ymm2
+            vxorpd %ymm3, %ymm3, %ymm3
+            vxorpd %ymm4, %ymm4, %ymm4
+            vxorpd %ymm5, %ymm5, %ymm5
+            vxorpd %ymm6, %ymm6, %ymm6
+            vxorpd %ymm7, %ymm7, %ymm7
+            vxorpd %ymm8, %ymm8, %ymm8
+            vxorpd %ymm9, %ymm9, %ymm9
+            vxorpd %ymm10, %ymm10, %ymm10
+            vxorpd %ymm11, %ymm11, %ymm11
+            1:
+            vfmadd231pd %ymm0, %ymm1, %ymm2
+            vfmadd231pd %ymm0, %ymm1, %ymm3
+            vfmadd231pd %ymm0, %ymm1, %ymm4
+            vfmadd231pd %ymm0, %ymm1, %ymm5
+            vfmadd231pd %ymm0, %ymm1, %ymm6
+            vfmadd231pd %ymm0, %ymm1, %ymm7
+            vfmadd231pd %ymm0, %ymm1, %ymm8
+            vfmadd231pd %ymm0, %ymm1, %ymm9
+            vfmadd231pd %ymm0, %ymm1, %ymm10
+            vfmadd231pd %ymm0, %ymm1, %ymm11
+            decq $0
+            jnz 1b
+            "
+            : "+r"(remaining)
+            :
+            : "ymm0", "ymm1", "ymm2", "ymm3", "ymm4", "ymm5", "ymm6", "ymm7", "ymm8", "ymm9", "ymm10", "ymm11", "cc"
+        );
+    }
+}
+
+/// Measure the peak FLOP rate in GFLOP/s with the FMA microkernel on all rayon threads
+pub fn measure_peak(iterations: u64, reps: usize) -> f64 {
+    let threads = rayon::current_num_threads();
+
+    let stats = measure(1, reps, &mut (), |_| {
+        (0..threads).into_par_iter().for_each(|_| fma_microkernel(iterations));
+    }, |_| {});
+
+    stats.gflops(FMA_FLOP_PER_ITERATION * iterations * threads as u64).0
+}
+
+/// Measure the sustainable memory bandwidth in GB/s as the best of the given STREAM kernels
+///
+/// len should be large enough for the arrays not to fit into the caches.
+pub fn measure_bandwidth(streams: &[&Stream], len: usize, reps: usize, write_allocate: bool) -> f64 {
+    let (mut res, a, b, c) = prepare_arrays_with_length(len);
+
+    streams.iter().map(|stream| {
+        let stats = measure(1, reps, &mut res, |res| (stream.run)(res, &a, &b, &c), |_| {});
+        stats.gbytes(stream.bytes(len, write_allocate)).0
+    }).fold(0., f64::max)
+}
+
+/// The arithmetic intensity of a (m x k) * (k x n) multiplication in FLOP/byte
+///
+/// The traffic is the compulsory one: A and B are read once and C is read and written once.
+pub fn matmul_intensity(m: usize, k: usize, n: usize) -> f64 {
+    let bytes = (m * k + k * n + 2 * m * n) * std::mem::size_of::<NumType>();
+    matmul_flop(m, k, n) as f64 / bytes as f64
+}
+
+/// The measured limits of the machine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Machine {
+    /// Sustainable memory bandwidth in GB/s
+    pub bandwidth: f64,
+    /// Peak FLOP rate in GFLOP/s
+    pub peak: f64,
+}
+
+impl Machine {
+    /// The attainable GFLOP/s at the given arithmetic intensity
+    pub fn attainable(&self, intensity: f64) -> f64 {
+        self.peak.min(self.bandwidth * intensity)
+    }
+
+    /// The arithmetic intensity at which a kernel becomes compute bound
+    pub fn ridge(&self) -> f64 {
+        self.peak / self.bandwidth
+    }
+}
+
+/// A kernel placed on the roofline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub name: String,
+    /// Arithmetic intensity in FLOP/byte
+    pub intensity: f64,
+    /// Achieved GFLOP/s
+    pub gflops: f64,
+}
+
+impl Point {
+    /// Place a (m x k) * (k x n) multiplication with the measured runtimes, the best run is used
+    pub fn matmul(name: String, m: usize, k: usize, n: usize, stats: &Stats) -> Self {
+        Self {
+            name: name,
+            intensity: matmul_intensity(m, k, n),
+            gflops: stats.gflops(matmul_flop(m, k, n)).0,
+        }
+    }
+}
+
+/// The roofline model of a machine with the measured kernels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Roofline {
+    pub machine: Machine,
+    pub points: Vec<Point>,
+}
+
+impl Roofline {
+    pub fn new(machine: Machine) -> Self {
+        Self {
+            machine: machine,
+            points: vec![],
+        }
+    }
+
+    /// The roofline and all points as CSV
+    pub fn csv(&self) -> String {
+        let mut csv = String::from("name,intensity_flop_per_byte,gflops,attainable_gflops,efficiency,bound\n");
+        csv += &format!("peak,,{},{},,\n", self.machine.peak, self.machine.peak);
+        csv += &format!("bandwidth,{},,{},,\n", self.machine.ridge(), self.machine.peak);
+
+        for point in &self.points {
+            let attainable = self.machine.attainable(point.intensity);
+            csv += &format!("{},{},{},{},{},{}\n",
+                            point.name, point.intensity, point.gflops, attainable,
+                            point.gflops / attainable, self.bound(point));
+        }
+        csv
+    }
+
+    fn bound(&self, point: &Point) -> &'static str {
+        if point.intensity < self.machine.ridge() { "memory" } else { "compute" }
+    }
+
+    /// A log-log plot of the roofline and all points as SVG
+    pub fn svg(&self) -> String {
+        let (width, height, margin) = (800., 500., 60.);
+
+        // the plotted range spans all points and the ridge with some space around
+        let intensities = self.points.iter().map(|p| p.intensity).chain(Some(self.machine.ridge()));
+        let (x_min, x_max) = intensities.fold((std::f64::MAX, std::f64::MIN), |(lo, hi), x| (lo.min(x), hi.max(x)));
+        let (x_min, x_max) = ((x_min / 4.).log2().floor(), (x_max * 4.).log2().ceil());
+
+        let rates = self.points.iter().map(|p| p.gflops).chain(Some(self.machine.peak));
+        let y_lo = rates.fold(std::f64::MAX, f64::min).min(self.machine.attainable(x_min.exp2()));
+        let (y_min, y_max) = ((y_lo / 2.).log2().floor(), (self.machine.peak * 2.).log2().ceil());
+
+        let x = |intensity: f64| margin + (intensity.log2() - x_min) / (x_max - x_min) * (width - 2. * margin);
+        let y = |gflops: f64| height - margin - (gflops.log2() - y_min) / (y_max - y_min) * (height - 2. * margin);
+
+        let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">\n", width, height);
+        svg += &format!("<rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n", width, height);
+
+        // axes with a tick at every power of two
+        svg += &format!("<line x1=\"{0}\" y1=\"{1}\" x2=\"{2}\" y2=\"{1}\" stroke=\"black\"/>\n", margin, height - margin, width - margin);
+        svg += &format!("<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"black\"/>\n", margin, margin, height - margin);
+        for e in (x_min as i32)...(x_max as i32) {
+            let pos = x(2f64.powi(e));
+            svg += &format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">2^{}</text>\n", pos, height - margin + 16., e);
+        }
+        for e in (y_min as i32)...(y_max as i32) {
+            let pos = y(2f64.powi(e));
+            svg += &format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\">2^{}</text>\n", margin - 6., pos + 4., e);
+        }
+        svg += &format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">arithmetic intensity [FLOP/byte]</text>\n", width / 2., height - 16.);
+        svg += &format!("<text x=\"16\" y=\"{}\" text-anchor=\"middle\" transform=\"rotate(-90 16 {})\">performance [GFLOP/s]</text>\n", height / 2., height / 2.);
+
+        // the roof consists of the bandwidth slope up to the ridge and the peak afterwards
+        let (left, ridge, right) = (x_min.exp2(), self.machine.ridge(), x_max.exp2());
+        svg += &format!("<polyline fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\" points=\"{},{} {},{} {},{}\"/>\n",
+                        x(left), y(self.machine.attainable(left)),
+                        x(ridge), y(self.machine.peak),
+                        x(right), y(self.machine.peak));
+        svg += &format!("<text x=\"{}\" y=\"{}\" fill=\"steelblue\">{:.1} GB/s, {:.1} GFLOP/s</text>\n",
+                        x(ridge) + 6., y(self.machine.peak) - 6., self.machine.bandwidth, self.machine.peak);
+
+        for point in &self.points {
+            svg += &format!("<circle cx=\"{}\" cy=\"{}\" r=\"4\" fill=\"firebrick\"/>\n", x(point.intensity), y(point.gflops));
+            svg += &format!("<text x=\"{}\" y=\"{}\">{}</text>\n", x(point.intensity) + 6., y(point.gflops) + 4., point.name);
+        }
+
+        svg += "</svg>\n";
+        svg
+    }
+}
+
+impl fmt::Display for Roofline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "bandwidth: {:.2} GB/s, peak: {:.2} GFLOP/s, ridge: {:.2} FLOP/byte",
+                 self.machine.bandwidth, self.machine.peak, self.machine.ridge())?;
+        writeln!(f, "{:<32} {:>12} {:>12} {:>12} {:>10} {:>8}",
+                 "name", "FLOP/byte", "GFLOP/s", "attainable", "of roof", "bound")?;
+
+        for point in &self.points {
+            let attainable = self.machine.attainable(point.intensity);
+            writeln!(f, "{:<32} {:>12.2} {:>12.2} {:>12.2} {:>9.1}% {:>8}",
+                     point.name, point.intensity, point.gflops, attainable,
+                     100. * point.gflops / attainable, self.bound(point))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roofline() -> Roofline {
+        let mut roofline = Roofline::new(Machine { bandwidth: 10., peak: 40. });
+        roofline.points.push(Point { name: "slow".to_string(), intensity: 1., gflops: 5. });
+        roofline.points.push(Point { name: "fast".to_string(), intensity: 16., gflops: 30. });
+        roofline
+    }
+
+    #[test]
+    fn test_machine() {
+        let machine = Machine { bandwidth: 10., peak: 40. };
+        assert_eq!(machine.ridge(), 4.);
+        assert_eq!(machine.attainable(1.), 10.);
+        assert_eq!(machine.attainable(16.), 40.);
+    }
+
+    #[test]
+    fn test_intensity() {
+        // 2 * n^3 FLOP on 4 * n^2 doubles
+        assert_eq!(matmul_intensity(64, 64, 64), 2. * 64. / (4. * 8.));
+    }
+
+    #[test]
+    fn test_output() {
+        let roofline = roofline();
+
+        let csv = roofline.csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.contains("slow,1,5,10,0.5,memory"));
+        assert!(csv.contains("fast,16,30,40,0.75,compute"));
+
+        let svg = roofline.svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<polyline"));
+        assert_eq!(svg.matches("<circle").count(), 2);
+    }
+
+    #[test]
+    fn test_peak() {
+        assert!(measure_peak(1000, 1) > 0.);
+    }
+}
@@ -2,17 +2,20 @@
 extern crate rayon;
 extern crate simd;
 extern crate hwloc;
+extern crate rand;
 
 use std;
 use self::std::ptr;
 
 use self::rayon::prelude::*;
 
-use self::simd::x86::avx::{f64x4, f32x8};
+use self::simd::x86::avx::{f64x4, f32x8, i64x4, i32x8};
 
 use self::hwloc::{Topology, ObjectType};
 
 use TOPOLOGY;
+use affinity;
+use aligned::AlignedBuffer;
 use random_array;
 
 
@@ -24,13 +27,25 @@ pub const STRIDE: usize = 1;
 pub const S: NumType = 3.5 as NumType;
 
 
-pub fn prepare_arrays() -> (Vec<NumType>, Vec<NumType>, Vec<NumType>, Vec<NumType>){
+pub fn prepare_arrays() -> (AlignedBuffer<NumType>, AlignedBuffer<NumType>, AlignedBuffer<NumType>, AlignedBuffer<NumType>){
     let array_length = match std::env::var("ARRAY_SIZE") {
         Ok(len) => len.parse::<usize>().expect("ARRAY_SIZE env variable must be the array length"),
         Err(_)  => 4000000
     };
 
-    return (vec![0 as NumType; array_length], random_array(), random_array(), random_array())
+    let random = || AlignedBuffer::from(random_array());
+
+    return (AlignedBuffer::from_elem(0 as NumType, array_length), random(), random(), random())
+}
+
+/// Create a result and three random input arrays of the given length
+///
+/// The arrays are aligned to aligned::alignment().
+pub fn prepare_arrays_with_length(len: usize) -> (AlignedBuffer<NumType>, AlignedBuffer<NumType>, AlignedBuffer<NumType>, AlignedBuffer<NumType>) {
+    // initialized in parallel, so the pages are local to the threads of the parallel kernels
+    let random = || affinity::first_touch(len, |_| rand::random::<NumType>());
+
+    (affinity::first_touch(len, |_| 0 as NumType), random(), random(), random())
 }
 
 // https://doc.rust-lang.org/std/primitive.slice.html#method.copy_from_slice
@@ -70,6 +85,20 @@ pub fn striad<T>(result: &mut [T], a: &[T], b: &[T], s: T)
     }
 }
 
+/// The in place striad y = s*x + y, which is the BLAS axpy
+///
+/// Unlike the STREAM kernels every element is updated regardless of STRIDE, as cblas_daxpy
+/// relies on it.
+pub fn axpy<T>(y: &mut [T], x: &[T], s: T)
+    where T: std::marker::Copy + std::ops::Add<Output=T> + std::ops::Mul<Output=T>
+{
+    assert!(y.len() == x.len());
+
+    for i in 0..x.len() {
+        y[i] = s*x[i] + y[i];
+    }
+}
+
 pub fn vtriad<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
     where T: std::marker::Copy + std::ops::Add<Output=T> + std::ops::Mul<Output=T>
 {
@@ -81,16 +110,17 @@ pub fn vtriad<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
 }
 
 
-pub fn vtriad_itertools<T>(result: &mut Vec<T>, a: &[T], b: &[T], c: &[T])
+pub fn vtriad_itertools<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
     where T: std::marker::Copy + std::ops::Add<Output=T> + std::ops::Mul<Output=T>
 {
+    assert!(result.len() == a.len() && result.len() == b.len() && result.len() == c.len());
+
     let r = a.iter().zip(b).zip(c).map(|((&x, &y), &z)| x * z + y);
 
     let result_ptr = result.as_mut_ptr();
-    let result_slice = result.as_mut_slice();
 
     let end = r.fold(0, |index, item| { unsafe {ptr::write(result_ptr.offset(index as isize), item) }; index + 1});
-    assert_eq!(end, result_slice.len());
+    assert_eq!(end, result.len());
 }
 
 pub fn vtriad_itertools_2<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
@@ -103,23 +133,17 @@ pub fn vtriad_itertools_2<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
     }
 }
 
-pub fn vtriad_rayon<T>(result: &mut Vec<T>, a: &[T], b: &[T], c: &[T])
+pub fn vtriad_rayon<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
     where T: Sync + Send + std::marker::Copy + std::ops::Add<Output=T> + std::ops::Mul<Output=T>
 {
-    ///*
-    let r = a.par_iter().zip(b).zip(c).map(|((&x, &y), &z)| x * z + y);
-    result.clear();
-    result.par_extend(r);
-    //*/
-    /*
+    assert!(result.len() == a.len() && result.len() == b.len() && result.len() == c.len());
+
+    // writes into the existing array, so it keeps its alignment and placement
     let r = result.par_iter_mut().zip(a).zip(b).zip(c);
 
     r.for_each(|(((r, &x), &y), &z)| {
-        unsafe {
-            ptr::write(r as *mut T, x * z + y)
-        };
+        *r = x * z + y;
     });
-    */
 }
 
 pub trait SimdItem {
@@ -144,12 +168,30 @@ impl SimdItem for f32x8 {
     }
 }
 
+impl SimdItem for i64x4 {
+    type Elem = i64;
+
+    fn store (self, array: &mut [i64], idx: usize) {
+        self.store(array, idx);
+    }
+}
+
+impl SimdItem for i32x8 {
+    type Elem = i32;
+
+    fn store (self, array: &mut [i32], idx: usize) {
+        self.store(array, idx);
+    }
+}
+
 pub trait SimdCapable where Self: std::marker::Sized {
-    type SimdType: SimdItem<Elem=Self> + std::fmt::Debug + std::marker::Copy + std::ops::Add<Output=Self::SimdType> + std::ops::Mul<Output=Self::SimdType>;
+    type SimdType: SimdItem<Elem=Self> + std::fmt::Debug + std::marker::Copy + std::marker::Send + std::marker::Sync + std::ops::Add<Output=Self::SimdType> + std::ops::Mul<Output=Self::SimdType>;
     const CHUNK_SIZE: usize;
     type ArrayType;
 
     fn load (array: &[Self], idx: usize) -> Self::SimdType where Self: std::marker::Sized;
+
+    fn splat (value: Self) -> Self::SimdType;
 }
 
 impl SimdCapable for f64 {
@@ -160,6 +202,10 @@ impl SimdCapable for f64 {
     fn load (array: &[Self], idx: usize) -> Self::SimdType {
         Self::SimdType::load(array, idx)
     }
+
+    fn splat (value: Self) -> Self::SimdType {
+        Self::SimdType::splat(value)
+    }
 }
 
 impl SimdCapable for f32 {
@@ -170,6 +216,38 @@ impl SimdCapable for f32 {
     fn load (array: &[Self], idx: usize) -> Self::SimdType {
         Self::SimdType::load(array, idx)
     }
+
+    fn splat (value: Self) -> Self::SimdType {
+        Self::SimdType::splat(value)
+    }
+}
+
+impl SimdCapable for i64 {
+    type SimdType = i64x4;
+    type ArrayType = [i64; 4];
+    const CHUNK_SIZE: usize = 4;
+
+    fn load (array: &[Self], idx: usize) -> Self::SimdType {
+        Self::SimdType::load(array, idx)
+    }
+
+    fn splat (value: Self) -> Self::SimdType {
+        Self::SimdType::splat(value)
+    }
+}
+
+impl SimdCapable for i32 {
+    type SimdType = i32x8;
+    type ArrayType = [i32; 8];
+    const CHUNK_SIZE: usize = 8;
+
+    fn load (array: &[Self], idx: usize) -> Self::SimdType {
+        Self::SimdType::load(array, idx)
+    }
+
+    fn splat (value: Self) -> Self::SimdType {
+        Self::SimdType::splat(value)
+    }
 }
 
 pub fn vtriad_simd<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
@@ -242,7 +320,9 @@ pub fn vtriad_threads<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
     let mut b_iter = b.chunks(part);
     let mut c_iter = c.chunks(part);
 
-    let threads = (0..num_cores).map(|_| {
+    let policy = affinity::policy();
+
+    let threads = (0..num_cores).map(|index| {
         let mut rc = unsafe {
             let slice = res_iter.next().unwrap();
             let len = slice.len();
@@ -266,6 +346,9 @@ pub fn vtriad_threads<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
         };
 
         std::thread::spawn(move || {
+            if let Err(e) = affinity::bind_current_thread(policy, index) {
+                eprintln!("warning: {}", e);
+            }
             vtriad_itertools_2(rc, ac, bc, cc);
         })
     }).collect::<Vec<_>>();
@@ -276,11 +359,87 @@ pub fn vtriad_threads<T>(result: &mut [T], a: &[T], b: &[T], c: &[T])
     }
 }
 
+/// A STREAM kernel with the traffic and work it causes per array element
+pub struct Stream {
+    pub name: &'static str,
+    /// The number of arrays read
+    pub reads: usize,
+    /// The number of arrays written
+    pub writes: usize,
+    /// The floating point operations per element
+    pub flop: usize,
+    pub run: fn(&mut [NumType], &[NumType], &[NumType], &[NumType]),
+}
+
+impl Stream {
+    /// The bytes moved for arrays of length len
+    ///
+    /// With write_allocate every written cache line is read before, which counts as an additional
+    /// read per written array.
+    pub fn bytes(&self, len: usize, write_allocate: bool) -> u64 {
+        let arrays = self.reads + self.writes + if write_allocate { self.writes } else { 0 };
+        (arrays * len * std::mem::size_of::<NumType>()) as u64
+    }
+
+    /// The floating point operations for arrays of length len
+    pub fn flop(&self, len: usize) -> u64 {
+        (self.flop * len) as u64
+    }
+}
+
+/// All STREAM kernels which can be selected by name
+pub static STREAMS: &'static [Stream] = &[
+    Stream { name: "copy", reads: 1, writes: 1, flop: 0, run: run_copy },
+    Stream { name: "add", reads: 2, writes: 1, flop: 1, run: run_add },
+    Stream { name: "striad", reads: 2, writes: 1, flop: 2, run: run_striad },
+    Stream { name: "vtriad", reads: 3, writes: 1, flop: 2, run: run_vtriad },
+    Stream { name: "vtriad_itertools", reads: 3, writes: 1, flop: 2, run: vtriad_itertools },
+    Stream { name: "vtriad_rayon", reads: 3, writes: 1, flop: 2, run: vtriad_rayon },
+    Stream { name: "vtriad_simd", reads: 3, writes: 1, flop: 2, run: run_vtriad_simd },
+    Stream { name: "vtriad_simd_rayon", reads: 3, writes: 1, flop: 2, run: run_vtriad_simd_rayon },
+    Stream { name: "vtriad_threads", reads: 3, writes: 1, flop: 2, run: run_vtriad_threads },
+];
+
+/// Find a STREAM kernel by its name
+pub fn find_stream(name: &str) -> Option<&'static Stream> {
+    STREAMS.iter().find(|stream| stream.name == name)
+}
+
+fn run_copy(result: &mut [NumType], a: &[NumType], _: &[NumType], _: &[NumType]) {
+    copy(a, result);
+}
+
+fn run_add(result: &mut [NumType], a: &[NumType], b: &[NumType], _: &[NumType]) {
+    add(result, a, b);
+}
+
+fn run_striad(result: &mut [NumType], a: &[NumType], b: &[NumType], _: &[NumType]) {
+    striad(result, a, b, S);
+}
+
+fn run_vtriad(result: &mut [NumType], a: &[NumType], b: &[NumType], c: &[NumType]) {
+    vtriad(result, a, b, c);
+}
+
+fn run_vtriad_simd(result: &mut [NumType], a: &[NumType], b: &[NumType], c: &[NumType]) {
+    vtriad_simd(result, a, b, c);
+}
+
+fn run_vtriad_simd_rayon(result: &mut [NumType], a: &[NumType], b: &[NumType], c: &[NumType]) {
+    vtriad_simd_rayon(result, a, b, c);
+}
+
+fn run_vtriad_threads(result: &mut [NumType], a: &[NumType], b: &[NumType], c: &[NumType]) {
+    vtriad_threads(result, a, b, c);
+}
+
 #[allow(unused)]
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use measure::bench;
+
     extern crate test;
 
     fn array_equal<T: std::cmp::PartialEq + std::fmt::Debug>(a: &[T], b: &[T]) {
@@ -385,6 +544,21 @@ mod tests {
         array_equal(&result, &[5., 5., 7., 11., 17., 25., 37., 51.]);
     }
 
+    #[test]
+    fn test_streams() {
+        let (mut res, a, b, c) = (vec![0.; 8], [1.; 8], [2.; 8], [3.; 8]);
+
+        for stream in STREAMS {
+            (find_stream(stream.name).unwrap().run)(&mut res, &a, &b, &c);
+        }
+        array_equal(&res, &[5.; 8]);
+
+        let vtriad = find_stream("vtriad").unwrap();
+        assert_eq!(vtriad.bytes(100, false), 4 * 100 * 8);
+        assert_eq!(vtriad.bytes(100, true), 5 * 100 * 8);
+        assert_eq!(vtriad.flop(100), 200);
+    }
+
     #[test]
     fn test_vtriad_threads() {
         let mut result = vec![0.; 8];
@@ -395,105 +569,85 @@ mod tests {
         array_equal(&result, &[5., 5., 7., 11., 17., 25., 37., 51.]);
     }
 
+    /// Measure the benchmark f of the STREAM kernel stream on result
+    fn bench_stream<T, F: FnMut(&mut T)>(name: &str, stream: &str, len: usize, result: &mut T, mut f: F) {
+        let stream = find_stream(stream).unwrap();
+        bench(name, stream.flop(len), stream.bytes(len, false), result, |result| {
+            f(result);
+            test::black_box(&*result);
+        }, |_| {});
+    }
+
     //#[bench]
-    fn bench_copy(bencher: &mut test::Bencher) {
+    fn bench_copy(_: &mut test::Bencher) {
         let mut dst = random_array();
         let src = test::black_box(random_array());
-        bencher.iter(|| {
-            test::black_box(copy(&src, &mut dst));
-        });
+        bench_stream("copy", "copy", dst.len(), &mut dst, |dst| copy(&src, dst));
     }
 
     //#[bench]
-    fn bench_add(bencher: &mut test::Bencher) {
+    fn bench_add(_: &mut test::Bencher) {
         let mut res = vec![0 as NumType; ARRAY_SIZE];
         let a = test::black_box(random_array());
         let b = random_array();
-        bencher.iter(|| {
-            test::black_box(add(&mut res, &a, &b));
-        });
+        bench_stream("add", "add", res.len(), &mut res, |res| add(res, &a, &b));
     }
 
     //#[bench]
-    fn bench_add_itertools(bencher: &mut test::Bencher) {
+    fn bench_add_itertools(_: &mut test::Bencher) {
         let mut result = vec![0 as NumType; ARRAY_SIZE];
         let a = test::black_box(random_array());
         let b = random_array();
-        bencher.iter(|| {
-            test::black_box(add_itertools(&mut result, &a, &b));
-        });
+        bench_stream("add_itertools", "add", result.len(), &mut result, |result| add_itertools(result, &a, &b));
     }
 
     //#[bench]
-    fn bench_striad(bencher: &mut test::Bencher) {
+    fn bench_striad(_: &mut test::Bencher) {
         let mut res = vec![0 as NumType; ARRAY_SIZE];
         let a = test::black_box(random_array());
         let b = random_array();
-        bencher.iter(|| {
-            test::black_box(striad(&mut res, &a, &b, S));
-        });
+        bench_stream("striad", "striad", res.len(), &mut res, |res| striad(res, &a, &b, S));
     }
 
     //#[bench]
-    fn bench_vtriad(bencher: &mut test::Bencher) {
+    fn bench_vtriad(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-
-        bencher.iter(|| {
-            test::black_box(vtriad(&mut res, &a, &b, &c));
-        });
+        bench_stream("vtriad", "vtriad", res.len(), &mut res, |res| vtriad(res, &a, &b, &c));
     }
 
     #[bench]
-    fn bench_vtriad_itertools(bencher: &mut test::Bencher) {
+    fn bench_vtriad_itertools(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-
-        //for i in 0..100 {
-        bencher.iter(|| {
-            vtriad_itertools(&mut res, &a, &b, &c);
-        });
-        //}
+        bench_stream("vtriad_itertools", "vtriad", res.len(), &mut res, |res| vtriad_itertools(res, &a, &b, &c));
     }
 
     #[bench]
-    fn bench_vtriad_itertools_2(bencher: &mut test::Bencher) {
+    fn bench_vtriad_itertools_2(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-
-        bencher.iter(|| {
-            test::black_box(vtriad_itertools_2(&mut res, &a, &b, &c));
-        });
+        bench_stream("vtriad_itertools_2", "vtriad", res.len(), &mut res, |res| vtriad_itertools_2(res, &a, &b, &c));
     }
 
     #[bench]
-    fn bench_vtriad_rayon(bencher: &mut test::Bencher) {
+    fn bench_vtriad_rayon(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-
-        bencher.iter(|| {
-            test::black_box(vtriad_rayon(&mut res, &a, &b, &c));
-        });
+        bench_stream("vtriad_rayon", "vtriad", res.len(), &mut res, |res| vtriad_rayon(res, &a, &b, &c));
     }
 
     //#[bench]
-    fn bench_vtriad_simd(bencher: &mut test::Bencher) {
+    fn bench_vtriad_simd(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-
-        bencher.iter(|| {
-            test::black_box(vtriad_simd(&mut res, &a, &b, &c));
-        });
+        bench_stream("vtriad_simd", "vtriad", res.len(), &mut res, |res| vtriad_simd(res, &a, &b, &c));
     }
 
     //#[bench]
-    fn bench_vtriad_simd_rayon(bencher: &mut test::Bencher) {
+    fn bench_vtriad_simd_rayon(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-        bencher.iter(|| {
-            test::black_box(vtriad_simd_rayon(&mut res, &a, &b, &c));
-        });
+        bench_stream("vtriad_simd_rayon", "vtriad", res.len(), &mut res, |res| vtriad_simd_rayon(res, &a, &b, &c));
     }
 
     #[bench]
-    fn bench_vtriad_threads(bencher: &mut test::Bencher) {
+    fn bench_vtriad_threads(_: &mut test::Bencher) {
         let (mut res, a, b, c) = prepare_arrays();
-        bencher.iter(|| {
-            test::black_box(vtriad_threads(&mut res, &a, &b, &c));
-        });
+        bench_stream("vtriad_threads", "vtriad", res.len(), &mut res, |res| vtriad_threads(res, &a, &b, &c));
     }
 }
@@ -0,0 +1,151 @@
+/*
+ * Checks the CBLAS interface of the cdylib against a naive reference from C.
+ *
+ * Built and run by tests/cblas.rs, exits with a non-zero status on the first mismatch.
+ */
+
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+enum CBLAS_ORDER { CblasRowMajor = 101, CblasColMajor = 102 };
+enum CBLAS_TRANSPOSE { CblasNoTrans = 111, CblasTrans = 112, CblasConjTrans = 113 };
+
+void cblas_dgemm(const enum CBLAS_ORDER Order, const enum CBLAS_TRANSPOSE TransA,
+                 const enum CBLAS_TRANSPOSE TransB, const int M, const int N, const int K,
+                 const double alpha, const double *A, const int lda, const double *B, const int ldb,
+                 const double beta, double *C, const int ldc);
+void cblas_dcopy(const int N, const double *X, const int incX, double *Y, const int incY);
+void cblas_daxpy(const int N, const double alpha, const double *X, const int incX, double *Y, const int incY);
+double cblas_ddot(const int N, const double *X, const int incX, const double *Y, const int incY);
+void cblas_dscal(const int N, const double alpha, double *X, const int incX);
+
+/* element (i, j) of a stored matrix */
+static double *at(double *x, int row_major, int ld, int i, int j)
+{
+    return row_major ? &x[i * ld + j] : &x[j * ld + i];
+}
+
+static double *random_matrix(int len)
+{
+    double *x = malloc(len * sizeof(double));
+    for (int i = 0; i < len; i++) {
+        x[i] = (double)rand() / RAND_MAX - 0.5;
+    }
+    return x;
+}
+
+static int nearly_equal(double x, double y)
+{
+    return fabs(x - y) <= 1e-12 * (1. + fabs(y));
+}
+
+static int check_dgemm(enum CBLAS_ORDER order, enum CBLAS_TRANSPOSE trans_a, enum CBLAS_TRANSPOSE trans_b,
+                       int m, int n, int k, double alpha, double beta)
+{
+    int row_major = order == CblasRowMajor;
+    int ta = trans_a != CblasNoTrans;
+    int tb = trans_b != CblasNoTrans;
+
+    /* the stored shapes and padded leading dimensions */
+    int a_rows = ta ? k : m, a_cols = ta ? m : k;
+    int b_rows = tb ? n : k, b_cols = tb ? k : n;
+    int lda = (row_major ? a_cols : a_rows) + 3;
+    int ldb = (row_major ? b_cols : b_rows) + 1;
+    int ldc = (row_major ? n : m) + 2;
+
+    double *a = random_matrix(lda * (row_major ? a_rows : a_cols));
+    double *b = random_matrix(ldb * (row_major ? b_rows : b_cols));
+    double *c = random_matrix(ldc * (row_major ? m : n));
+    double *expected = malloc(ldc * (row_major ? m : n) * sizeof(double));
+
+    for (int i = 0; i < m; i++) {
+        for (int j = 0; j < n; j++) {
+            double sum = 0.;
+            for (int p = 0; p < k; p++) {
+                double aip = ta ? *at(a, row_major, lda, p, i) : *at(a, row_major, lda, i, p);
+                double bpj = tb ? *at(b, row_major, ldb, j, p) : *at(b, row_major, ldb, p, j);
+                sum += aip * bpj;
+            }
+            *at(expected, row_major, ldc, i, j) = alpha * sum + beta * *at(c, row_major, ldc, i, j);
+        }
+    }
+
+    cblas_dgemm(order, trans_a, trans_b, m, n, k, alpha, a, lda, b, ldb, beta, c, ldc);
+
+    int ok = 1;
+    for (int i = 0; i < m && ok; i++) {
+        for (int j = 0; j < n && ok; j++) {
+            double got = *at(c, row_major, ldc, i, j);
+            double want = *at(expected, row_major, ldc, i, j);
+            if (!nearly_equal(got, want)) {
+                fprintf(stderr, "dgemm order %d trans %d %d size %dx%dx%d: C(%d, %d) = %g, expected %g\n",
+                        order, trans_a, trans_b, m, k, n, i, j, got, want);
+                ok = 0;
+            }
+        }
+    }
+
+    free(a);
+    free(b);
+    free(c);
+    free(expected);
+    return ok;
+}
+
+static int check_level1(void)
+{
+    double x[8], y[8];
+    for (int i = 0; i < 8; i++) {
+        x[i] = i;
+        y[i] = 1.;
+    }
+
+    cblas_daxpy(4, 2., x, 2, y, -2);
+    double axpy[8] = { 13., 1., 9., 1., 5., 1., 1., 1. };
+    for (int i = 0; i < 8; i++) {
+        if (y[i] != axpy[i]) {
+            fprintf(stderr, "daxpy: y[%d] = %g, expected %g\n", i, y[i], axpy[i]);
+            return 0;
+        }
+    }
+
+    cblas_dcopy(8, x, 1, y, 1);
+    cblas_dscal(8, 0.5, y, 1);
+    double dot = cblas_ddot(8, x, 1, y, 1);
+    if (dot != 70.) {
+        fprintf(stderr, "dcopy, dscal, ddot: %g, expected 70\n", dot);
+        return 0;
+    }
+
+    return 1;
+}
+
+int main(void)
+{
+    enum CBLAS_ORDER orders[] = { CblasRowMajor, CblasColMajor };
+    enum CBLAS_TRANSPOSE transposes[] = { CblasNoTrans, CblasTrans, CblasConjTrans };
+    int sizes[][3] = { { 1, 1, 1 }, { 7, 5, 9 }, { 64, 48, 80 }, { 131, 67, 259 } };
+
+    int ok = 1;
+    for (int o = 0; o < 2; o++) {
+        for (int ta = 0; ta < 3; ta++) {
+            for (int tb = 0; tb < 3; tb++) {
+                for (int s = 0; s < 4; s++) {
+                    ok &= check_dgemm(orders[o], transposes[ta], transposes[tb],
+                                      sizes[s][0], sizes[s][1], sizes[s][2], 1.5, -0.5);
+                }
+                /* beta = 0 must ignore C */
+                ok &= check_dgemm(orders[o], transposes[ta], transposes[tb], 17, 13, 11, 1., 0.);
+            }
+        }
+    }
+
+    ok &= check_level1();
+
+    if (!ok) {
+        return 1;
+    }
+    printf("cblas: all checks passed\n");
+    return 0;
+}
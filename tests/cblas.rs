@@ -0,0 +1,45 @@
+//! Runs the C harness in tests/cblas.c against the cdylib of this crate
+//!
+//! The harness needs a C compiler, CC or cc, so it is ignored by default. Run it with
+//! `cargo test --test cblas -- --ignored`.
+
+extern crate libc;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The directory which contains the cdylib, the integration tests are built to target/<profile>/deps
+fn library_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().and_then(|deps| deps.parent()).unwrap().to_path_buf()
+}
+
+#[test]
+#[ignore]
+fn test_c_harness() {
+    let library_dir = library_dir();
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("cblas.c");
+
+    // a directory of its own, so concurrent runs do not overwrite each other's harness
+    let dir = env::temp_dir().join(format!("rust_cblas_{}", unsafe { libc::getpid() }));
+    fs::create_dir_all(&dir).unwrap();
+    let harness = dir.join("harness");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let output = Command::new(&compiler)
+        .arg("-O2").arg("-std=c99")
+        .arg(&source)
+        .arg("-o").arg(&harness)
+        .arg("-L").arg(&library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .arg("-lrust").arg("-lm")
+        .output()
+        .unwrap_or_else(|e| panic!("could not run {}: {}", compiler, e));
+    assert!(output.status.success(), "compiling the harness failed:\n{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = Command::new(&harness).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "the harness failed:\n{}", String::from_utf8_lossy(&output.stderr));
+}